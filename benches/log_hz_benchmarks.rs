@@ -12,14 +12,14 @@ macro_rules! log_hz_mutex {
             match *tick {
                 None => {
                     *tick = Some(std::time::Instant::now());
-                    log!($level, $($arg)+);
+                    log::log!($level, $($arg)+);
                 },
                 Some(ref mut tick) => {
                     let now = std::time::Instant::now();
                     let time_since_last = now.duration_since(*tick);
                     if time_since_last > *INTERVAL {
                         *tick = now;
-                        log!($level, $($arg)+);
+                        log::log!($level, $($arg)+);
                     }
                 }
             }
@@ -79,5 +79,128 @@ fn benchmark_mutex_vs_lockfree(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, benchmark_mutex_vs_lockfree);
+fn benchmark_lazy_vs_const_interval(c: &mut Criterion) {
+    setup_logger();
+
+    let mut group = c.benchmark_group("log_hz_lazy_vs_const_interval");
+
+    // Interval cached in a LazyLock
+    group.bench_function("lazy_interval", |b| {
+        b.iter(|| {
+            log_hz!(log::Level::Info, 1.0, "Benchmark message {}", black_box(42));
+        });
+    });
+
+    // Interval carried in the type
+    group.bench_function("const_interval", |b| {
+        b.iter(|| {
+            log_hz_const!(log::Level::Info, 1.0, "Benchmark message {}", black_box(42));
+        });
+    });
+
+    group.finish();
+}
+
+fn benchmark_steady_state(c: &mut Criterion) {
+    setup_logger();
+
+    let mut group = c.benchmark_group("log_hz_steady_state");
+
+    // Both go through the exported macro, with `first: false` so no iteration pays for a first-call emission: the
+    // first call only starts the timer, and every later one is rejected within the second.
+    group.bench_function("reject", |b| {
+        b.iter(|| {
+            log_hz!(log::Level::Info, 1.0, first: false, "Benchmark message {}", black_box(42));
+        });
+    });
+
+    // An infinite rate has an interval of 0, so every call after the first claims the slot and reaches the logger.
+    group.bench_function("emit", |b| {
+        b.iter(|| {
+            log_hz!(log::Level::Info, f64::INFINITY, first: false, "Benchmark message {}", black_box(42));
+        });
+    });
+
+    group.finish();
+}
+
+fn benchmark_claim_ordering(c: &mut Criterion) {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let mut group = c.benchmark_group("log_hz_claim_ordering");
+
+    // The emit path on every call: an interval of 0 with the timestamp advancing, so each call wins the claim. On
+    // weakly ordered targets like aarch64 the relaxed compare-and-swap avoids the barriers AcqRel needs, while x86
+    // compiles both to the same instruction.
+    group.bench_function("throttle_state", |b| {
+        let state = ThrottleState::new();
+        let mut now_ns = 0;
+        b.iter(|| {
+            now_ns += 1;
+            black_box(state.should_log(black_box(now_ns), 0));
+        });
+    });
+
+    // The bare compare-and-swap with the ordering ThrottleState used before, and the one it uses now
+    for (name, success) in [("acq_rel_cas", Ordering::AcqRel), ("relaxed_cas", Ordering::Relaxed)] {
+        group.bench_function(name, |b| {
+            let last_log_ns = AtomicU64::new(0);
+            let mut now_ns = 0;
+            b.iter(|| {
+                now_ns += 1;
+                black_box(last_log_ns.compare_exchange(now_ns - 1, black_box(now_ns), success, Ordering::Relaxed))
+            });
+        });
+    }
+
+    group.finish();
+}
+
+fn benchmark_single_threaded_claim(c: &mut Criterion) {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let mut group = c.benchmark_group("log_hz_single_threaded_claim");
+
+    // The emit path on every call as compiled: a compare-and-swap by default, a store with the `single-threaded`
+    // feature. Run with and without `--features single-threaded` to compare the two builds.
+    group.bench_function("throttle_state", |b| {
+        let state = ThrottleState::new();
+        let mut now_ns = 0;
+        b.iter(|| {
+            now_ns += 1;
+            black_box(state.should_log(black_box(now_ns), 0));
+        });
+    });
+
+    // The two ways of claiming the slot side by side, after the same check of the previous timestamp
+    group.bench_function("load_cas", |b| {
+        let last_log_ns = AtomicU64::new(0);
+        let mut now_ns = 0;
+        b.iter(|| {
+            now_ns += 1;
+            let last_ns = last_log_ns.load(Ordering::Relaxed);
+            black_box(last_log_ns.compare_exchange(last_ns, black_box(now_ns), Ordering::Relaxed, Ordering::Relaxed))
+        });
+    });
+    group.bench_function("load_store", |b| {
+        let last_log_ns = AtomicU64::new(0);
+        let mut now_ns = 0;
+        b.iter(|| {
+            now_ns += 1;
+            black_box(last_log_ns.load(Ordering::Relaxed));
+            last_log_ns.store(black_box(now_ns), Ordering::Relaxed);
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    benchmark_mutex_vs_lockfree,
+    benchmark_lazy_vs_const_interval,
+    benchmark_steady_state,
+    benchmark_claim_ordering,
+    benchmark_single_threaded_claim
+);
 criterion_main!(benches);
@@ -0,0 +1,619 @@
+//! Aggregation of the values passed to a throttled call site between emissions.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// Statistics of the values fed to an [Accumulator] since it was last taken.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AccumulatedStats {
+    /// Number of values.
+    pub count: u64,
+    /// Sum of the values.
+    pub sum: f64,
+    /// Smallest value, `f64::INFINITY` if there were none.
+    pub min: f64,
+    /// Largest value, `f64::NEG_INFINITY` if there were none.
+    pub max: f64,
+}
+
+impl AccumulatedStats {
+    const EMPTY: Self = Self {
+        count: 0,
+        sum: 0.0,
+        min: f64::INFINITY,
+        max: f64::NEG_INFINITY,
+    };
+
+    /// Mean of the values, NaN if there were none.
+    pub fn avg(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+}
+
+/// Running count, sum, min and max of values, shared between threads.
+///
+/// Each statistic is a separate atomic, the `f64`s stored as their bits and updated with a compare-and-swap loop, so
+/// adding a value never takes a lock. In exchange the statistics aren't updated together: a value added concurrently
+/// with [take](Self::take) can be counted in one report and land in the sum, min or max of the next.
+#[derive(Debug)]
+pub struct Accumulator {
+    count: AtomicU64,
+    sum: AtomicU64,
+    min: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Default for Accumulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Accumulator {
+    /// Create an accumulator with no values.
+    pub const fn new() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(AccumulatedStats::EMPTY.sum.to_bits()),
+            min: AtomicU64::new(AccumulatedStats::EMPTY.min.to_bits()),
+            max: AtomicU64::new(AccumulatedStats::EMPTY.max.to_bits()),
+        }
+    }
+
+    /// Add a value.
+    pub fn add(&self, value: f64) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        update_f64(&self.sum, |sum| Some(sum + value));
+        // Only written when the value is a new extreme, so a steady stream of values mostly just reads them.
+        update_f64(&self.min, |min| (value < min).then_some(value));
+        update_f64(&self.max, |max| (value > max).then_some(value));
+    }
+
+    /// Take the statistics of the values added so far, resetting the accumulator.
+    pub fn take(&self) -> AccumulatedStats {
+        let take_f64 = |stat: &AtomicU64, empty: f64| f64::from_bits(stat.swap(empty.to_bits(), Ordering::Relaxed));
+        AccumulatedStats {
+            count: self.count.swap(0, Ordering::Relaxed),
+            sum: take_f64(&self.sum, AccumulatedStats::EMPTY.sum),
+            min: take_f64(&self.min, AccumulatedStats::EMPTY.min),
+            max: take_f64(&self.max, AccumulatedStats::EMPTY.max),
+        }
+    }
+}
+
+/// Replace the `f64` stored as bits in `stat` with `update` of it, unless that returns `None`.
+fn update_f64(stat: &AtomicU64, update: impl Fn(f64) -> Option<f64>) {
+    let _ = stat.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+        update(f64::from_bits(bits)).map(f64::to_bits)
+    });
+}
+
+/// Number of items a [Batch] keeps for its summary, later items are only counted.
+pub const BATCH_SAMPLE_ITEMS: usize = 5;
+
+/// Items fed to a [log_hz_batched!] call site since its last emission, shared between threads.
+///
+/// Only the first [BATCH_SAMPLE_ITEMS] are formatted and kept, so memory stays bounded however many calls are
+/// throttled in between.
+#[derive(Debug)]
+pub struct Batch {
+    items: Mutex<(u64, Vec<String>)>,
+}
+
+impl Default for Batch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Batch {
+    /// Create an empty batch.
+    pub const fn new() -> Self {
+        Self {
+            items: Mutex::new((0, Vec::new())),
+        }
+    }
+
+    /// Add an item, formatting it with `Debug` if the batch hasn't kept enough items yet.
+    pub fn push(&self, item: &dyn fmt::Debug) {
+        let mut items = self.items.lock().unwrap_or_else(PoisonError::into_inner);
+        items.0 += 1;
+        if items.1.len() < BATCH_SAMPLE_ITEMS {
+            items.1.push(format!("{item:?}"));
+        }
+    }
+
+    /// Take a summary of the items added so far, `batch of N: [first, few, items, ...]`, emptying the batch.
+    pub fn take_summary(&self) -> String {
+        let (count, items) = std::mem::take(&mut *self.items.lock().unwrap_or_else(PoisonError::into_inner));
+        let more = if count > items.len() as u64 { ", ..." } else { "" };
+        format!("batch of {count}: [{}{more}]", items.join(", "))
+    }
+}
+
+/// Log the items passed to a call site, coalesced into one message at a throttled rate.
+///
+/// Every call adds `item` (anything implementing `Debug`) to the site's batch. When the throttle lets a message
+/// through, a single line summarizing the batch since the last message (including this call's item) is logged, like
+/// `batch of 12: [3, 5, 8, 13, 21, ...]`, and the batch is emptied. The first [BATCH_SAMPLE_ITEMS] items are shown
+/// and the rest are only counted.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// for id in [3, 5, 8] {
+///     log_hz_batched!(1.0, Level::Warn, format!("item {id} failed validation"));
+/// }
+/// ```
+///
+/// How many lines the items are coalesced into, on a `ManualClock` with the `testing` feature:
+///
+#[cfg_attr(feature = "testing", doc = "```rust")]
+#[cfg_attr(not(feature = "testing"), doc = "```rust,ignore")]
+/// use log_hz::*;
+/// use std::time::Duration;
+///
+/// let clock = ManualClock::default();
+/// let emitted = with_clock(clock.clone(), || {
+///     count_emissions(|| {
+///         for id in 0..25 {
+///             log_hz_batched!(10.0, Level::Warn, id);
+///             clock.advance(Duration::from_millis(10));
+///         }
+///     })
+/// });
+/// // 25 items over 250ms, at 10Hz
+/// assert_eq!(emitted, 3);
+/// ```
+#[macro_export]
+macro_rules! log_hz_batched {
+    ($rate:expr, $level:expr, $item:expr) => {
+        {
+            use std::sync::LazyLock;
+
+            static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| $crate::Rate::interval_ns($rate));
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+            static BATCH: $crate::Batch = $crate::Batch::new();
+
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level) {
+                BATCH.push(&$item);
+                let now_ns = $crate::__private::now_ns();
+                if $crate::__private::site_allows(level, now_ns, *INTERVAL_NS, |interval_ns| {
+                    STATE.should_log(now_ns, interval_ns)
+                }) {
+                    $crate::__private::emit_once(&STATE, || {
+                        $crate::__log_hz_emit!(level, "{}", BATCH.take_summary())
+                    });
+                }
+            }
+        }
+    };
+}
+
+/// A statistic passed to the format string of [log_hz_accumulate!] as a named argument.
+///
+/// Passing a named argument the format string doesn't use is a compile error, so the macro also appends each one as
+/// `{name:p}`. The `Display` and `Debug` impls print the statistic, and the `Pointer` impl used by that placeholder
+/// prints nothing, so the statistics the format string leaves out still count as used.
+#[doc(hidden)]
+#[derive(Clone, Copy)]
+pub struct StatArg<T>(pub T);
+
+impl<T: fmt::Display> fmt::Display for StatArg<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for StatArg<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl<T> fmt::Pointer for StatArg<T> {
+    fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Ok(())
+    }
+}
+
+/// Log aggregate statistics of a value at a throttled rate, instead of a single sample.
+///
+/// Every call feeds `value` (anything castable to `f64` with `as`) into the call site's accumulator. When the throttle
+/// lets a message through, the format string is formatted with `{count}`, `{avg}`, `{min}` and `{max}` of the values
+/// since the last message (including this call's), and the accumulator is reset. Each of them may be used with any
+/// `Display` or `Debug` spec, like `{avg:.1}` or `{max:?}`, or left out. Feeding a value takes a few atomic operations
+/// and no lock.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// for reading in [20.5, 21.0, 22.5] {
+///     log_hz_accumulate!(1.0, reading, Level::Info, "Temperature over {count} samples: avg {avg:.1}, max {max}");
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_hz_accumulate {
+    ($rate:expr, $value:expr, $level:expr, $fmt:literal) => {
+        {
+            use std::sync::LazyLock;
+
+            static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| $crate::Rate::interval_ns($rate));
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+            static VALUES: $crate::Accumulator = $crate::Accumulator::new();
+
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level) {
+                VALUES.add($value as f64);
+                let now_ns = $crate::__private::now_ns();
+                if $crate::__private::site_allows(level, now_ns, *INTERVAL_NS, |interval_ns| {
+                    STATE.should_log(now_ns, interval_ns)
+                }) {
+                    let stats = VALUES.take();
+                    $crate::__private::emit_once(&STATE, || {
+                        $crate::__log_hz_emit!(
+                            level,
+                            concat!($fmt, "{count:p}{avg:p}{min:p}{max:p}"),
+                            count = $crate::__private::StatArg(stats.count),
+                            avg = $crate::__private::StatArg(stats.avg()),
+                            min = $crate::__private::StatArg(stats.min),
+                            max = $crate::__private::StatArg(stats.max),
+                        )
+                    });
+                }
+            }
+        }
+    };
+}
+
+/// Count the calls to a site and log just the count once per interval, never an individual event.
+///
+/// For events so frequent that no single occurrence is worth logging, only their rate: each call adds one to the
+/// site's counter, and once per interval `"<label>: <count> events in last <interval>"` is logged and the counter
+/// reset. The first call starts the timer rather than logging, so every summary covers a full interval.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// for _ in 0..1_000 {
+///     count_only_hz!(1.0, Level::Debug, "packets received");
+/// }
+/// ```
+#[macro_export]
+macro_rules! count_only_hz {
+    ($rate:expr, $level:expr, $label:expr) => {
+        {
+            use std::sync::LazyLock;
+            use std::sync::atomic::{AtomicU64, Ordering};
+
+            static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| $crate::Rate::interval_ns($rate));
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::with_first(false);
+            static EVENTS: AtomicU64 = AtomicU64::new(0);
+
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level) {
+                EVENTS.fetch_add(1, Ordering::Relaxed);
+                let now_ns = $crate::__private::now_ns();
+                if $crate::__private::site_allows(level, now_ns, *INTERVAL_NS, |interval_ns| {
+                    STATE.should_log(now_ns, interval_ns)
+                }) {
+                    let events = EVENTS.swap(0, Ordering::Relaxed);
+                    let interval = std::time::Duration::from_nanos($crate::__private::limit_interval(*INTERVAL_NS));
+                    $crate::__private::emit_once(&STATE, || {
+                        $crate::__log_hz_emit!(level, "{}: {} events in last {:?}", $label, events, interval)
+                    });
+                }
+            }
+        }
+    };
+}
+
+/// Buckets per power of two in a [Histogram], as a number of bits of the mantissa.
+const SUB_BUCKET_BITS: u32 = 3;
+
+/// The smallest and largest powers of two a [Histogram] tells apart, about 0.001 and 1.1e12.
+const MIN_EXPONENT: i64 = -10;
+const MAX_EXPONENT: i64 = 40;
+
+/// Number of buckets in a [Histogram].
+pub const HISTOGRAM_BUCKETS: usize = ((MAX_EXPONENT - MIN_EXPONENT) << SUB_BUCKET_BITS) as usize;
+
+/// The bits of the smallest value a [Histogram] tells apart, shifted like a bucket index.
+const FIRST_BUCKET_BITS: i64 = (1023 + MIN_EXPONENT) << SUB_BUCKET_BITS;
+
+/// Percentiles of the values fed to a [Histogram] since it was last taken.
+///
+/// Each percentile is the middle of the bucket it fell in, within about 6% of the actual value. Displayed as
+/// `p50=.. p95=.. p99=.. n=..`, rounded to three significant digits or to a whole number.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Percentiles {
+    /// The median, NaN if there were no values.
+    pub p50: f64,
+    /// The 95th percentile, NaN if there were no values.
+    pub p95: f64,
+    /// The 99th percentile, NaN if there were no values.
+    pub p99: f64,
+    /// Number of values.
+    pub count: u64,
+}
+
+impl fmt::Display for Percentiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (p50, p95, p99) = (Significant(self.p50), Significant(self.p95), Significant(self.p99));
+        write!(f, "p50={p50} p95={p95} p99={p99} n={}", self.count)
+    }
+}
+
+/// A value displayed with three significant digits, or none after the decimal point if it has more.
+struct Significant(f64);
+
+impl fmt::Display for Significant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decimals = if self.0.is_normal() { (2 - self.0.abs().log10().floor() as i64).max(0) } else { 0 };
+        write!(f, "{:.*}", decimals as usize, self.0)
+    }
+}
+
+/// Counts of values in fixed, logarithmically spaced buckets, shared between threads without locking.
+///
+/// Each power of two from about 0.001 to 1.1e12 is split into 8 buckets, so recording a value is one atomic increment
+/// and the memory used is fixed at [HISTOGRAM_BUCKETS] counters. Values below the range (including 0, negative values
+/// and NaN) are counted in the first bucket, and values above it in the last.
+#[derive(Debug)]
+pub struct Histogram {
+    buckets: [AtomicU64; HISTOGRAM_BUCKETS],
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Histogram {
+    /// Create a histogram with no values.
+    pub const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; HISTOGRAM_BUCKETS],
+        }
+    }
+
+    /// Add a value.
+    pub fn record(&self, value: f64) {
+        // The bits of positive floats sort like their values, so the exponent and top bits of the mantissa index
+        // logarithmically spaced buckets.
+        let bucket = if value > 0.0 {
+            let bits = (value.to_bits() >> (52 - SUB_BUCKET_BITS)) as i64;
+            (bits - FIRST_BUCKET_BITS).clamp(0, HISTOGRAM_BUCKETS as i64 - 1)
+        } else {
+            0
+        };
+        self.buckets[bucket as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Take the percentiles of the values added so far, resetting the histogram.
+    ///
+    /// A value added concurrently lands in this report or the next.
+    pub fn take(&self) -> Percentiles {
+        let counts: Vec<u64> = self.buckets.iter().map(|bucket| bucket.swap(0, Ordering::Relaxed)).collect();
+        let count = counts.iter().sum();
+        let percentile = |quantile: f64| {
+            if count == 0 {
+                return f64::NAN;
+            }
+            let rank = ((quantile * count as f64).ceil() as u64).max(1);
+            let mut seen = 0;
+            let bucket = counts.iter().position(|&n| {
+                seen += n;
+                seen >= rank
+            });
+            bucket_midpoint(bucket.unwrap_or(HISTOGRAM_BUCKETS - 1))
+        };
+        Percentiles {
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            count,
+        }
+    }
+}
+
+/// The value in the middle of a [Histogram] bucket.
+fn bucket_midpoint(bucket: usize) -> f64 {
+    let lower_bound =
+        |bucket: usize| f64::from_bits(((bucket as i64 + FIRST_BUCKET_BITS) as u64) << (52 - SUB_BUCKET_BITS));
+    (lower_bound(bucket) + lower_bound(bucket + 1)) / 2.0
+}
+
+/// Log percentiles of a value's distribution at a throttled rate, instead of individual samples.
+///
+/// Every call records `value` (anything castable to `f64` with `as`) in the call site's [Histogram], a single atomic
+/// increment. Once per interval, `"<label>: p50=.. p95=.. p99=.. n=.."` is logged for the values since the last
+/// message (including this call's), and the histogram is reset. The first call starts the timer rather than logging,
+/// so every summary covers a full interval. The percentiles are accurate to within about 6%, see [Percentiles].
+///
+/// ```rust
+/// use log_hz::*;
+/// use std::time::Instant;
+///
+/// # let handle_request = || {};
+/// let start = Instant::now();
+/// handle_request();
+/// log_hz_histogram!(1.0, start.elapsed().as_micros(), Level::Info, "request latency (us)");
+/// ```
+#[macro_export]
+macro_rules! log_hz_histogram {
+    ($rate:expr, $value:expr, $level:expr, $label:expr) => {
+        {
+            use std::sync::LazyLock;
+
+            static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| $crate::Rate::interval_ns($rate));
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::with_first(false);
+            static VALUES: $crate::Histogram = $crate::Histogram::new();
+
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level) {
+                VALUES.record($value as f64);
+                let now_ns = $crate::__private::now_ns();
+                if $crate::__private::site_allows(level, now_ns, *INTERVAL_NS, |interval_ns| {
+                    STATE.should_log(now_ns, interval_ns)
+                }) {
+                    $crate::__private::emit_once(&STATE, || {
+                        $crate::__log_hz_emit!(level, "{}: {}", $label, VALUES.take())
+                    });
+                }
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn take_resets_the_stats() {
+        let values = Accumulator::new();
+        for value in [3.0, -1.0, 4.0] {
+            values.add(value);
+        }
+        let stats = values.take();
+        assert_eq!((stats.count, stats.sum, stats.min, stats.max), (3, 6.0, -1.0, 4.0));
+        assert_eq!(stats.avg(), 2.0);
+        assert_eq!(values.take(), AccumulatedStats::EMPTY);
+    }
+
+    #[test]
+    fn concurrent_adds_are_all_counted() {
+        let values = Accumulator::new();
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let values = &values;
+                scope.spawn(move || (0..1_000).for_each(|value| values.add((thread * 1_000 + value) as f64)));
+            }
+        });
+        let stats = values.take();
+        assert_eq!((stats.count, stats.sum, stats.min, stats.max), (4_000, 7_998_000.0, 0.0, 3_999.0));
+    }
+
+    #[test]
+    fn stats_can_be_left_out_or_formatted_with_specs() {
+        testing_logger::setup();
+        log_hz_accumulate!(10.0, 2.25, Level::Info, "avg {avg:.1}, max {max:?}");
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(captured_logs[0].body, "avg 2.2, max 2.25");
+        });
+    }
+
+    #[test]
+    fn batch_coalesces_items_into_one_emission() {
+        testing_logger::setup();
+        let log = |id: u32| {
+            log_hz_batched!(10.0, Level::Warn, id);
+        };
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            for id in 0..3 {
+                log(id);
+            }
+            clock.advance(std::time::Duration::from_millis(100));
+            log(3);
+            for id in 10..20 {
+                log(id);
+            }
+            clock.advance(std::time::Duration::from_millis(100));
+            log(20);
+        });
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(
+                bodies,
+                ["batch of 1: [0]", "batch of 3: [1, 2, 3]", "batch of 11: [10, 11, 12, 13, 14, ...]"]
+            );
+        });
+    }
+
+    #[test]
+    fn emission_reports_the_values_since_the_last_one() {
+        testing_logger::setup();
+        let log = |value: u32| {
+            log_hz_accumulate!(10.0, value, Level::Info, "n={count} avg={avg} min={min} max={max}");
+        };
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            for value in 1..=10 {
+                log(value);
+            }
+            clock.advance(std::time::Duration::from_millis(100));
+            log(11);
+        });
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["n=1 avg=1 min=1 max=1", "n=10 avg=6.5 min=2 max=11"]);
+        });
+    }
+
+    #[test]
+    fn count_only_reports_the_events_of_each_interval() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            // A call every millisecond, from the start of the second to its end inclusive
+            for _ in 0..=1_000 {
+                count_only_hz!(1.0, Level::Info, "ticks");
+                clock.advance(std::time::Duration::from_millis(1));
+            }
+        });
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["ticks: 1001 events in last 1s"]);
+        });
+    }
+
+    #[test]
+    fn histogram_reports_percentiles_of_the_interval() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            // Latencies of 1 to 1000, then one more once the interval is over
+            for latency in (1..=1_000).chain([1_000]) {
+                log_hz_histogram!(1.0, latency, Level::Info, "latency");
+                clock.advance(std::time::Duration::from_millis(1));
+            }
+        });
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(captured_logs.len(), 1);
+            let fields: Vec<f64> = captured_logs[0]
+                .body
+                .strip_prefix("latency: ")
+                .unwrap()
+                .split(' ')
+                .map(|field| field.split_once('=').unwrap().1.parse().unwrap())
+                .collect();
+            let [p50, p95, p99, count] = fields[..] else { panic!("{fields:?}") };
+            assert!((p50 - 500.0).abs() < 500.0 * 0.07, "{p50}");
+            assert!((p95 - 950.0).abs() < 950.0 * 0.07, "{p95}");
+            assert!((p99 - 990.0).abs() < 990.0 * 0.07, "{p99}");
+            assert_eq!(count, 1001.0);
+        });
+    }
+
+    #[test]
+    fn histogram_buckets_cover_every_value() {
+        let histogram = Histogram::new();
+        for value in [f64::NAN, -1.0, 0.0, 1e-9, 1e300, f64::INFINITY] {
+            histogram.record(value);
+        }
+        let percentiles = histogram.take();
+        assert_eq!(percentiles.count, 6);
+        assert!(percentiles.p50 < 0.002 && percentiles.p99 > 1e12, "{percentiles:?}");
+        assert!(histogram.take().p50.is_nan());
+        assert_eq!(
+            Percentiles { p50: 1234.6, p95: 0.012345, p99: 2.0, count: 3 }.to_string(),
+            "p50=1235 p95=0.0123 p99=2.00 n=3"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Detection of throttles that never suppress anything, enabled by the `checked` feature.
+
+use crate::ThrottleHandle;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Calls a site must make in a window before it can be judged.
+const CHECK_CALLS: u64 = 10;
+
+/// Intervals a window must span before it can be judged, so a short burst doesn't count as a sustained call rate.
+const CHECK_INTERVALS: u64 = 10;
+
+/// Watches one call site for a call rate at or below its throttle rate, and warns once if it finds one.
+///
+/// With the `checked` feature, every `log_hz!` and `log_hz_dyn!` site owns one. Calls are grouped into windows of at
+/// least [CHECK_CALLS] calls spanning [CHECK_INTERVALS] intervals, and a window in which the throttle suppressed
+/// nothing means the site could just as well use a plain `log!`.
+#[doc(hidden)]
+#[derive(Debug, Default)]
+pub struct StarvationCheck {
+    /// When the current window started, 0 before the first call.
+    window_start_ns: AtomicU64,
+    /// Calls in the current window.
+    calls: AtomicU64,
+    /// The site's total suppressed count when the window started.
+    suppressed_at_start: AtomicU64,
+    warned: AtomicBool,
+}
+
+impl StarvationCheck {
+    pub const fn new() -> Self {
+        Self {
+            window_start_ns: AtomicU64::new(0),
+            calls: AtomicU64::new(0),
+            suppressed_at_start: AtomicU64::new(0),
+            warned: AtomicBool::new(false),
+        }
+    }
+
+    /// Record a call at `now_ns` to the site behind `handle`, after its throttle check with `interval_ns`.
+    pub fn observe(&self, handle: &ThrottleHandle, now_ns: u64, interval_ns: u64) {
+        // A rate that is disabled or always logs has nothing to judge.
+        if self.warned.load(Ordering::Relaxed) || interval_ns == 0 || interval_ns == u64::MAX {
+            return;
+        }
+        let start_ns = self.window_start_ns.load(Ordering::Relaxed);
+        if start_ns == 0 {
+            if self.window_start_ns.compare_exchange(0, now_ns.max(1), Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+                self.suppressed_at_start.store(handle.state().total_suppressed(), Ordering::Relaxed);
+            }
+            return;
+        }
+        let calls = self.calls.fetch_add(1, Ordering::Relaxed) + 1;
+        let window_ns = interval_ns.saturating_mul(CHECK_INTERVALS);
+        if calls < CHECK_CALLS || now_ns.saturating_sub(start_ns) < window_ns {
+            return;
+        }
+        // Only the call that closes the window judges it
+        let next_ns = now_ns.max(1);
+        if self.window_start_ns.compare_exchange(start_ns, next_ns, Ordering::Relaxed, Ordering::Relaxed).is_err() {
+            return;
+        }
+        let suppressed = handle.state().total_suppressed();
+        let starved = suppressed == self.suppressed_at_start.swap(suppressed, Ordering::Relaxed);
+        self.calls.store(0, Ordering::Relaxed);
+        if starved && !self.warned.swap(true, Ordering::Relaxed) {
+            let window = Duration::from_nanos(now_ns - start_ns);
+            log::warn!(
+                "log_hz: the throttle at {}:{} suppressed none of {calls} calls over {window:?}, the site is called no faster than its rate of {}Hz",
+                handle.file(),
+                handle.line(),
+                1e9 / interval_ns as f64,
+            );
+        }
+    }
+}
+
+#[cfg(all(test, feature = "checked"))]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    fn starvation_warnings(captured_logs: &[testing_logger::CapturedLog]) -> usize {
+        captured_logs.iter().filter(|log| log.body.contains("suppressed none of")).count()
+    }
+
+    #[test]
+    fn slow_calls_warn_once() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            // Called once a second, throttled at 10Hz
+            for _ in 0..60 {
+                info_hz!(10.0, "tick");
+                clock.advance(Duration::from_secs(1));
+            }
+        });
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(starvation_warnings(captured_logs), 1);
+            assert_eq!(captured_logs.len(), 61);
+        });
+    }
+
+    #[test]
+    fn fast_calls_do_not_warn() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            for _ in 0..1_000 {
+                log_hz_dyn!(Level::Info, 10.0, "tick");
+                clock.advance(Duration::from_millis(10));
+            }
+        });
+        testing_logger::validate(|captured_logs| assert_eq!(starvation_warnings(captured_logs), 0));
+    }
+}
@@ -0,0 +1,383 @@
+//! The time source shared by every throttled call site.
+
+use std::sync::LazyLock;
+#[cfg(any(feature = "coarsetime", feature = "minstant"))]
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{Duration, Instant};
+
+/// The start time of every compiled-in clock, captured together so that they all measure from the same moment and
+/// the clock can be switched at runtime with [init_best_clock].
+struct StartTime {
+    std: Instant,
+    #[cfg(feature = "coarsetime")]
+    coarse: coarsetime::Instant,
+    #[cfg(feature = "minstant")]
+    tsc: minstant::Instant,
+}
+
+/// A shared, static start time for the process.
+/// Using LazyLock ensures it's initialized only once.
+///
+/// A site's interval is a separate `LazyLock`, but that can't skew its first decisions: threads racing on the first
+/// calls all measure from the one start time whichever of them initializes it, and the interval depends only on the
+/// rate. The site's timestamp compare-and-swap then lets a single one of them log.
+static START_TIME: LazyLock<StartTime> = LazyLock::new(|| StartTime {
+    std: Instant::now(),
+    #[cfg(feature = "coarsetime")]
+    coarse: coarsetime::Instant::now(),
+    #[cfg(feature = "minstant")]
+    tsc: minstant::Instant::now(),
+});
+
+/// The instant all of log_hz's relative timestamps are measured from.
+///
+/// Every throttle timestamp in the crate (e.g. [ThrottleState::last_log_ns](crate::ThrottleState::last_log_ns)
+/// for call sites) is nanoseconds elapsed since this instant, so durations measured from it line up with them.
+/// It is captured the first time it's needed, either by this function or the first throttled log call.
+pub fn process_start() -> std::time::Instant {
+    START_TIME.std
+}
+
+/// Which clock [now_ns] reads.
+#[cfg(any(feature = "coarsetime", feature = "minstant"))]
+const STD: u8 = 0;
+#[cfg(feature = "coarsetime")]
+const COARSE: u8 = 1;
+#[cfg(feature = "minstant")]
+const TSC: u8 = 2;
+
+/// The clock [now_ns] reads until [init_best_clock] picks another. `coarsetime` takes precedence over `minstant` if
+/// both are enabled.
+#[cfg(feature = "coarsetime")]
+static BACKEND: AtomicU8 = AtomicU8::new(COARSE);
+#[cfg(all(feature = "minstant", not(feature = "coarsetime")))]
+static BACKEND: AtomicU8 = AtomicU8::new(TSC);
+
+/// Pick the fastest clock that works on this machine among those compiled in, returning its name for logging.
+///
+/// Which clock to read is otherwise fixed by the `coarsetime` and `minstant` features, but a binary shipped to
+/// different hardware may not be able to use the same one everywhere. Enabling both features and calling this at
+/// startup probes them in order of speed, falling back to the next one that works:
+///
+/// 1. `"minstant"`: the CPU's timestamp counter, if it is invariant and synchronized across cores.
+/// 2. `"coarsetime"`: the OS's coarse clock, if it is seen advancing (which takes up to 50ms to check).
+/// 3. `"std"`: `std::time::Instant`, which always works.
+///
+/// Only backends whose feature is enabled are probed, so without either feature this always returns `"std"`. All
+/// backends measure from [process_start], so switching only moves the throttles' timestamps by up to the coarse
+/// clock's resolution. Call it before logging starts anyway, as a site may let one extra message through if they do.
+///
+/// ```rust
+/// use log_hz::*;
+/// # #[cfg(feature = "no-log-reexport")] use log::info;
+///
+/// let clock = init_best_clock();
+/// info!("Throttled logging reads the {clock} clock");
+/// ```
+pub fn init_best_clock() -> &'static str {
+    #[cfg(feature = "minstant")]
+    if minstant::is_tsc_available() {
+        BACKEND.store(TSC, Ordering::Relaxed);
+        return "minstant";
+    }
+    #[cfg(feature = "coarsetime")]
+    if coarse_clock_advances() {
+        BACKEND.store(COARSE, Ordering::Relaxed);
+        return "coarsetime";
+    }
+    #[cfg(any(feature = "coarsetime", feature = "minstant"))]
+    BACKEND.store(STD, Ordering::Relaxed);
+    "std"
+}
+
+/// Whether the coarse clock moves within 50ms, spinning on it until it does.
+#[cfg(feature = "coarsetime")]
+fn coarse_clock_advances() -> bool {
+    let (coarse, precise) = (coarsetime::Instant::now(), Instant::now());
+    while precise.elapsed() < Duration::from_millis(50) {
+        if coarsetime::Instant::now() > coarse {
+            return true;
+        }
+        std::thread::yield_now();
+    }
+    false
+}
+
+/// Nanoseconds elapsed since [START_TIME], the time base for all throttle timestamps.
+#[cfg(not(any(feature = "coarsetime", feature = "minstant")))]
+#[inline]
+pub fn now_ns() -> u64 {
+    #[cfg(any(test, feature = "testing"))]
+    if let Some(now) = crate::testing::clock_now() {
+        return ns_since_start(now);
+    }
+    ns_since_start(Instant::now())
+}
+
+/// Nanoseconds elapsed since [START_TIME], the time base for all throttle timestamps, read from the selected clock.
+#[cfg(any(feature = "coarsetime", feature = "minstant"))]
+#[inline]
+pub fn now_ns() -> u64 {
+    #[cfg(any(test, feature = "testing"))]
+    if let Some(now) = crate::testing::clock_now() {
+        return ns_since_start(now);
+    }
+    read_ns(BACKEND.load(Ordering::Relaxed))
+}
+
+/// Nanoseconds elapsed since [START_TIME] on `backend`'s clock.
+#[cfg(any(feature = "coarsetime", feature = "minstant"))]
+#[inline]
+fn read_ns(backend: u8) -> u64 {
+    match backend {
+        #[cfg(feature = "coarsetime")]
+        COARSE => coarse_ns(),
+        #[cfg(feature = "minstant")]
+        TSC => tsc_ns(),
+        _ => ns_since_start(Instant::now()),
+    }
+}
+
+/// Nanoseconds elapsed since [START_TIME] on the TSC.
+///
+/// Reads the TSC through `minstant` where it is usable. Elsewhere (e.g. non-x86 platforms, or an unstable TSC)
+/// `minstant` would fall back to the wall clock, which can jump, so `std::time::Instant` is read instead.
+#[cfg(feature = "minstant")]
+#[inline]
+fn tsc_ns() -> u64 {
+    if !minstant::is_tsc_available() {
+        return ns_since_start(Instant::now());
+    }
+    minstant::Instant::now().checked_duration_since(START_TIME.tsc).unwrap_or(Duration::ZERO).as_nanos() as u64
+}
+
+/// Nanoseconds elapsed since [START_TIME] on the coarse clock.
+///
+/// Falls back to `std::time::Instant` for the rest of the process if the coarse clock is found to be frozen.
+#[cfg(feature = "coarsetime")]
+#[inline]
+fn coarse_ns() -> u64 {
+    if frozen::FALLBACK.load(Ordering::Relaxed) {
+        return precise_ns();
+    }
+    let now = coarsetime::Instant::now();
+    // Checked explicitly rather than relying on the subtraction saturating, see [ns_since_start]
+    let coarse_ns = if now >= START_TIME.coarse { (now - START_TIME.coarse).as_nanos() } else { 0 };
+    let is_frozen = frozen::DETECTOR.with(|detector| {
+        let mut state = detector.get();
+        let is_frozen = state.observe(coarse_ns, precise_ns);
+        detector.set(state);
+        is_frozen
+    });
+    if is_frozen {
+        frozen::fall_back();
+        return precise_ns();
+    }
+    coarse_ns
+}
+
+#[cfg(feature = "coarsetime")]
+fn precise_ns() -> u64 {
+    ns_since_start(Instant::now())
+}
+
+/// Nanoseconds from [process_start] to `now`, or 0 if `now` is before it.
+///
+/// Some clock backends (notably in virtualized environments) aren't guaranteed to be monotonic, so a reading
+/// taken after the start time may still compare before it. That is treated as no time having passed rather
+/// than panicking inside a log macro.
+#[inline]
+fn ns_since_start(now: Instant) -> u64 {
+    now.checked_duration_since(process_start()).unwrap_or(Duration::ZERO).as_nanos() as u64
+}
+
+/// Detection of a coarse clock that has stopped advancing.
+///
+/// If the coarse clock appears frozen, every call looks like it happened at the same instant and nothing
+/// after the first message is logged. Each thread counts how many calls in a row see the same coarse
+/// reading, and every [CHECK_EVERY_CALLS] repeats cross-checks against the precise clock. If the precise
+/// clock has moved more than [FROZEN_AFTER_NS] while the coarse one hasn't, the coarse clock is frozen.
+#[cfg(feature = "coarsetime")]
+mod frozen {
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Repeated readings before the coarse clock is cross-checked against the precise one.
+    pub(super) const CHECK_EVERY_CALLS: u32 = 1 << 16;
+
+    /// Precise time that must pass without the coarse clock moving for it to be considered frozen.
+    ///
+    /// Far larger than any coarse clock resolution, so a slow but working clock is never mistaken for a frozen one.
+    pub(super) const FROZEN_AFTER_NS: u64 = 1_000_000_000;
+
+    /// Set once a frozen coarse clock has been detected, switching every call site to the precise clock.
+    pub(super) static FALLBACK: AtomicBool = AtomicBool::new(false);
+
+    thread_local! {
+        pub(super) static DETECTOR: Cell<FrozenClockDetector> = const { Cell::new(FrozenClockDetector::new()) };
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    pub(super) struct FrozenClockDetector {
+        last_coarse_ns: u64,
+        repeats: u32,
+        /// Precise time when the coarse reading last changed.
+        changed_at_ns: u64,
+    }
+
+    impl FrozenClockDetector {
+        pub(super) const fn new() -> Self {
+            Self {
+                last_coarse_ns: u64::MAX,
+                repeats: 0,
+                changed_at_ns: 0,
+            }
+        }
+
+        /// Record a coarse reading, returning true if the coarse clock appears to be frozen.
+        ///
+        /// `precise_ns` is only called when the coarse reading changes or a cross-check is due.
+        pub(super) fn observe(&mut self, coarse_ns: u64, precise_ns: impl FnOnce() -> u64) -> bool {
+            if coarse_ns != self.last_coarse_ns {
+                *self = Self {
+                    last_coarse_ns: coarse_ns,
+                    repeats: 0,
+                    changed_at_ns: precise_ns(),
+                };
+                return false;
+            }
+            self.repeats += 1;
+            if self.repeats < CHECK_EVERY_CALLS {
+                return false;
+            }
+            self.repeats = 0;
+            precise_ns().saturating_sub(self.changed_at_ns) > FROZEN_AFTER_NS
+        }
+    }
+
+    /// Switch to the precise clock, with a one-time diagnostic in debug builds.
+    #[cold]
+    pub(super) fn fall_back() {
+        if !FALLBACK.swap(true, Ordering::Relaxed) && cfg!(debug_assertions) {
+            log::warn!(
+                "log_hz: the coarsetime clock hasn't advanced in over {}s, falling back to std::time::Instant. \
+                 log_hz reads coarsetime::Instant::now(), so check CLOCK_MONOTONIC_COARSE (or your platform's \
+                 equivalent) works here; coarsetime's Updater is only needed for Instant::recent()",
+                FROZEN_AFTER_NS / 1_000_000_000
+            );
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::clock::now_ns;
+
+        #[test]
+        fn advancing_clock_is_not_frozen() {
+            let mut detector = FrozenClockDetector::new();
+            for i in 0..CHECK_EVERY_CALLS as u64 * 4 {
+                // Coarse ticks every 1000 calls while precise advances 1ms per call
+                assert!(!detector.observe(i / 1_000, || i * 1_000_000));
+            }
+        }
+
+        #[test]
+        fn fast_loop_within_one_coarse_tick_is_not_frozen() {
+            let mut detector = FrozenClockDetector::new();
+            for _ in 0..CHECK_EVERY_CALLS * 4 {
+                // Lots of calls, but the precise clock agrees no real time has passed
+                assert!(!detector.observe(42, || 1_000));
+            }
+        }
+
+        #[test]
+        fn frozen_clock_is_detected() {
+            let mut detector = FrozenClockDetector::new();
+            let precise = Cell::new(0);
+            let mut detected = false;
+            for _ in 0..CHECK_EVERY_CALLS * 2 {
+                precise.set(precise.get() + 100_000);
+                detected |= detector.observe(42, || precise.get());
+            }
+            assert!(detected);
+        }
+
+        #[test]
+        fn fallback_keeps_time_advancing() {
+            now_ns();
+            fall_back();
+            assert!(FALLBACK.load(Ordering::Relaxed));
+            let before = now_ns();
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            let advanced = now_ns() >= before + 5_000_000;
+            // Back to the coarse clock for the rest of the tests, which only read the precise one meanwhile
+            FALLBACK.store(false, Ordering::Relaxed);
+            assert!(advanced);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn process_start_is_stable() {
+        let start = process_start();
+        assert_eq!(start, process_start());
+        assert!(start <= std::time::Instant::now());
+        assert!(std::time::Duration::from_nanos(now_ns()) <= start.elapsed());
+    }
+
+    #[cfg(all(feature = "minstant", not(feature = "coarsetime")))]
+    #[test]
+    fn minstant_clock_keeps_pace_with_std() {
+        let (before_ns, before) = (now_ns(), std::time::Instant::now());
+        std::thread::sleep(Duration::from_millis(50));
+        let (elapsed_ns, expected) = (now_ns() - before_ns, before.elapsed());
+        // Both clocks are read twice, so allow for the time between the paired reads
+        assert!(elapsed_ns.abs_diff(expected.as_nanos() as u64) < 5_000_000, "{elapsed_ns}ns vs {expected:?}");
+    }
+
+    #[test]
+    fn clock_before_start_does_not_panic() {
+        let start = process_start();
+        let clock = crate::ManualClock::new(start.checked_sub(Duration::from_secs(1)).unwrap_or(start));
+        testing_logger::setup();
+        crate::with_clock(clock.clone(), || {
+            assert_eq!(now_ns(), 0);
+            for i in 0..20 {
+                if i == 10 {
+                    // Time moving forwards again past the interval logs as usual
+                    clock.set(start + Duration::from_secs(2));
+                } else if i < 10 {
+                    clock.rewind(Duration::from_millis(10));
+                }
+                crate::info_hz!(1.0, "before start");
+            }
+        });
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 2));
+    }
+
+    #[test]
+    fn best_clock_is_monotonic_and_throttles() {
+        let name = init_best_clock();
+        assert!(["minstant", "coarsetime", "std"].contains(&name), "{name}");
+        let state = crate::ThrottleState::new();
+        let (start_ns, start) = (now_ns(), Instant::now());
+        let (mut last_ns, mut logged) = (start_ns, 0);
+        while start.elapsed() < Duration::from_millis(100) {
+            let now_ns = now_ns();
+            assert!(now_ns >= last_ns, "{name} went back from {last_ns}ns to {now_ns}ns");
+            last_ns = now_ns;
+            // Throttled at 50Hz
+            logged += state.should_log(now_ns, 20_000_000) as u32;
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        // Allow for the coarse clock's resolution
+        let elapsed = Duration::from_nanos(last_ns - start_ns);
+        assert!(elapsed.abs_diff(start.elapsed()) < Duration::from_millis(20), "{name}: {elapsed:?}");
+        assert!((3..=7).contains(&logged), "{name} logged {logged} times");
+    }
+}
@@ -0,0 +1,525 @@
+//! Suppression of repeated messages, keyed by the formatted message rather than the call site.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Number of distinct messages a [DedupWindow] remembers.
+pub const DEDUP_CAPACITY: usize = 32;
+
+/// A fixed-capacity set of recently emitted message hashes and when they were emitted.
+///
+/// When full, the entry emitted longest ago is evicted, so memory use is bounded no matter how many
+/// distinct messages a call site produces.
+#[derive(Debug)]
+pub struct DedupWindow {
+    /// `(message hash, last emission in ns)` for the first `len` slots.
+    entries: [(u64, u64); DEDUP_CAPACITY],
+    len: usize,
+}
+
+impl Default for DedupWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DedupWindow {
+    /// Create a window that hasn't seen any messages.
+    pub const fn new() -> Self {
+        Self {
+            entries: [(0, 0); DEDUP_CAPACITY],
+            len: 0,
+        }
+    }
+
+    /// Returns true (and records the emission) if the message with `hash` wasn't emitted within `window_ns` of `now_ns`.
+    pub fn should_log(&mut self, hash: u64, now_ns: u64, window_ns: u64) -> bool {
+        let entries = &mut self.entries[..self.len];
+        if let Some(entry) = entries.iter_mut().find(|(seen, _)| *seen == hash) {
+            if now_ns.saturating_sub(entry.1) < window_ns {
+                return false;
+            }
+            entry.1 = now_ns;
+            return true;
+        }
+        if self.len < DEDUP_CAPACITY {
+            self.entries[self.len] = (hash, now_ns);
+            self.len += 1;
+        } else {
+            let oldest = self
+                .entries
+                .iter_mut()
+                .min_by_key(|(_, last_ns)| *last_ns)
+                .expect("capacity is non-zero");
+            *oldest = (hash, now_ns);
+        }
+        true
+    }
+}
+
+/// Number of distinct messages a [SeenMessages] remembers.
+pub const SEEN_CAPACITY: usize = 256;
+
+/// A fixed-capacity set of message hashes that have been seen, for [log_once_per_args!].
+///
+/// When full, the message seen least recently is evicted (and would be logged again if it came back), so memory use
+/// is bounded no matter how many distinct messages a call site produces.
+#[derive(Debug)]
+pub struct SeenMessages {
+    /// `(message hash, when it was last seen)` for the first `len` slots, in calls to [first_sighting].
+    ///
+    /// [first_sighting]: Self::first_sighting
+    entries: [(u64, u64); SEEN_CAPACITY],
+    len: usize,
+    calls: u64,
+}
+
+impl Default for SeenMessages {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SeenMessages {
+    /// Create a set that hasn't seen any messages.
+    pub const fn new() -> Self {
+        Self {
+            entries: [(0, 0); SEEN_CAPACITY],
+            len: 0,
+            calls: 0,
+        }
+    }
+
+    /// Returns true (and remembers the message) if the message with `hash` isn't in the set.
+    pub fn first_sighting(&mut self, hash: u64) -> bool {
+        self.calls += 1;
+        if let Some(entry) = self.entries[..self.len].iter_mut().find(|(seen, _)| *seen == hash) {
+            entry.1 = self.calls;
+            return false;
+        }
+        if self.len < SEEN_CAPACITY {
+            self.entries[self.len] = (hash, self.calls);
+            self.len += 1;
+        } else {
+            let least_recent = self
+                .entries
+                .iter_mut()
+                .min_by_key(|(_, last_seen)| *last_seen)
+                .expect("capacity is non-zero");
+            *least_recent = (hash, self.calls);
+        }
+        true
+    }
+}
+
+/// The last value emitted by a [log_hz_dedup_numeric!] site, to compare new values against.
+#[derive(Debug)]
+pub struct NumericDedup {
+    /// The value's bits, or [NumericDedup::NONE] before the first emission. NaNs are stored as [f64::NAN], whose bits
+    /// differ from it.
+    last_bits: AtomicU64,
+}
+
+impl Default for NumericDedup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NumericDedup {
+    const NONE: u64 = u64::MAX;
+
+    /// Create a site that hasn't emitted a value yet.
+    pub const fn new() -> Self {
+        Self { last_bits: AtomicU64::new(Self::NONE) }
+    }
+
+    /// Whether `value` is worth logging: the first value, one that moved more than `tolerance` from the last one
+    /// emitted, or one that became or stopped being NaN.
+    pub fn exceeds(&self, value: f64, tolerance: f64) -> bool {
+        match self.last_bits.load(Ordering::Relaxed) {
+            Self::NONE => true,
+            last_bits => {
+                let last = f64::from_bits(last_bits);
+                if value.is_nan() || last.is_nan() {
+                    value.is_nan() != last.is_nan()
+                } else {
+                    (value - last).abs() > tolerance
+                }
+            }
+        }
+    }
+
+    /// Remember `value` as the last one emitted.
+    pub fn record(&self, value: f64) {
+        let value = if value.is_nan() { f64::NAN } else { value };
+        self.last_bits.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Log a numeric value only when it moved more than `tolerance` away from the last value logged.
+///
+/// For readings that drift, logging on every exact change is too chatty: this logs the first value, then only
+/// values more than `tolerance` from the one last logged, so a slow ramp is logged once per `tolerance` step. A
+/// value turning NaN is logged once, as is the first real value after it. The value is appended as the last argument
+/// of the format string, like the error of [log_hz_try!](crate::log_hz_try). Passing `min_interval:` (a
+/// [Duration](std::time::Duration)) after the tolerance also limits the site to one message per interval.
+///
+/// ```rust
+/// use log_hz::*;
+/// use std::time::Duration;
+///
+/// # let read_temperature = || 21.3;
+/// let celsius: f64 = read_temperature();
+/// log_hz_dedup_numeric!(0.5, Level::Info, celsius, "Temperature is now {:.1}°C");
+/// log_hz_dedup_numeric!(0.5, min_interval: Duration::from_secs(10), Level::Info, celsius, "Temperature: {:.1}°C");
+/// ```
+#[macro_export]
+macro_rules! log_hz_dedup_numeric {
+    (
+        $tolerance:expr, min_interval: $min_interval:expr, $level:expr, $value:expr, $fmt:literal $(, $arg:expr)* $(,)?
+    ) => {
+        {
+            static LAST: $crate::NumericDedup = $crate::NumericDedup::new();
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level) {
+                let value = $value;
+                let interval_ns = std::time::Duration::as_nanos(&$min_interval).min(u64::MAX as u128) as u64;
+                // Only a value worth logging uses up the interval.
+                if LAST.exceeds(value as f64, $tolerance as f64)
+                    && {
+                        let now_ns = $crate::__private::now_ns();
+                        $crate::__private::site_allows(level, now_ns, interval_ns, |interval_ns| {
+                            STATE.should_log(now_ns, interval_ns)
+                        })
+                    }
+                {
+                    LAST.record(value as f64);
+                    $crate::__private::emit_once(&STATE, || $crate::__log_hz_emit!(level, $fmt, $($arg,)* value));
+                }
+            }
+        }
+    };
+    ($tolerance:expr, $level:expr, $value:expr, $fmt:literal $(, $arg:expr)* $(,)?) => {
+        $crate::log_hz_dedup_numeric!(
+            $tolerance, min_interval: std::time::Duration::ZERO, $level, $value, $fmt $(, $arg)*
+        )
+    };
+}
+
+/// A [fmt::Write] sink that hashes what is written to it with 64-bit FNV-1a, instead of storing it.
+///
+/// The hash is computed byte by byte, so it doesn't depend on how the formatter splits the message into writes.
+#[derive(Debug, Clone, Copy)]
+struct MessageHasher(u64);
+
+impl MessageHasher {
+    const fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl fmt::Write for MessageHasher {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+        Ok(())
+    }
+}
+
+/// Hash a formatted message for [DedupWindow].
+pub fn message_hash(message: &str) -> u64 {
+    args_hash(format_args!("{message}"))
+}
+
+/// Hash the message `args` format to, without allocating it. Equal to [message_hash] of the formatted message.
+pub fn args_hash(args: fmt::Arguments) -> u64 {
+    let mut hasher = MessageHasher::new();
+    // Writing to the hasher never fails, only a `Display` impl returning an error can
+    let _ = fmt::write(&mut hasher, args);
+    hasher.0
+}
+
+/// Log a message unless the exact same formatted message was emitted within `window`.
+///
+/// Unlike the rate limited macros, different messages from the same call site don't throttle each other:
+/// each distinct formatted message may be emitted once per `window` (a [Duration](std::time::Duration)).
+/// The call site remembers the last [DEDUP_CAPACITY] distinct messages, evicting the one emitted longest ago.
+///
+/// The message is formatted on every call in order to compare it, so this is more expensive than [log_hz!]. It is
+/// formatted straight into a hash rather than a `String` though, so a suppressed repeat doesn't allocate.
+///
+/// ```rust
+/// use log_hz::*;
+/// use std::time::Duration;
+///
+/// for id in [1, 2, 1, 1, 2] {
+///     // Logs "sensor 1" and "sensor 2" once each
+///     log_hz_dedup_window!(Duration::from_secs(10), Level::Warn, "sensor {} out of range", id);
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_hz_dedup_window {
+    ($window:expr, $level:expr, $($arg:tt)+) => {
+        {
+            use std::sync::{Mutex, PoisonError};
+
+            static SEEN: Mutex<$crate::DedupWindow> = Mutex::new($crate::DedupWindow::new());
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level) {
+                // Matched rather than bound with `let`, so the arguments' temporaries live until the message is logged.
+                match format_args!($($arg)+) {
+                    args => {
+                        let window_ns = std::time::Duration::as_nanos(&$window).min(u64::MAX as u128) as u64;
+                        let now_ns = $crate::__private::now_ns();
+                        // Hashed before locking, as formatting runs `Display` impls that may log from this site.
+                        let hash = $crate::args_hash(args);
+                        let fresh = SEEN
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .should_log(hash, now_ns, window_ns);
+                        // The window isn't the site's interval, but the process-wide limits still apply.
+                        if fresh
+                            && $crate::__private::site_allows(level, now_ns, 0, |interval_ns| match interval_ns {
+                                0 => STATE.force(now_ns),
+                                interval_ns => STATE.should_log(now_ns, interval_ns),
+                            })
+                        {
+                            $crate::__private::emit_once(&STATE, || $crate::__log_hz_emit!(level, "{}", args));
+                        }
+                    }
+                }
+            }
+        }
+    };
+}
+
+/// Log each distinct formatted message once, the first time it appears.
+///
+/// A plain "log once" fires once per call site, but a site like `"sensor {} out of range"` describes a different
+/// problem for each sensor. This keys "once" on the formatted message instead, so each distinct message is logged
+/// the first time it is seen and never again. The call site remembers the [SEEN_CAPACITY] messages seen most recently,
+/// so a message that has not come up for that many distinct messages is forgotten and would be logged again.
+///
+/// Like [log_hz_dedup_window!], the message is formatted into a hash on every call to compare it, without allocating.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// for id in [1, 2, 1, 3, 2] {
+///     // Logs sensors 1, 2 and 3 once each
+///     log_once_per_args!(Level::Warn, "sensor {} out of range", id);
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_once_per_args {
+    ($level:expr, $($arg:tt)+) => {
+        {
+            use std::sync::{Mutex, PoisonError};
+
+            static SEEN: Mutex<$crate::SeenMessages> = Mutex::new($crate::SeenMessages::new());
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level) {
+                // Matched rather than bound with `let`, so the arguments' temporaries live until the message is logged.
+                match format_args!($($arg)+) {
+                    args => {
+                        // Hashed before locking, as formatting runs `Display` impls that may log from this site.
+                        let hash = $crate::args_hash(args);
+                        let first = SEEN.lock().unwrap_or_else(PoisonError::into_inner).first_sighting(hash);
+                        if first && {
+                            let now_ns = $crate::__private::now_ns();
+                            $crate::__private::site_allows(level, now_ns, 0, |interval_ns| match interval_ns {
+                                0 => STATE.force(now_ns),
+                                interval_ns => STATE.should_log(now_ns, interval_ns),
+                            })
+                        } {
+                            $crate::__private::emit_once(&STATE, || $crate::__log_hz_emit!(level, "{}", args));
+                        }
+                    }
+                }
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn repeats_are_suppressed_within_the_window() {
+        let mut window = DedupWindow::new();
+        assert!(window.should_log(1, 0, 100));
+        assert!(window.should_log(2, 10, 100));
+        assert!(!window.should_log(1, 50, 100));
+        assert!(window.should_log(1, 100, 100));
+        assert!(!window.should_log(2, 100, 100));
+    }
+
+    #[test]
+    fn oldest_entry_is_evicted_when_full() {
+        let mut window = DedupWindow::new();
+        for hash in 0..DEDUP_CAPACITY as u64 {
+            assert!(window.should_log(hash, hash, 1_000));
+        }
+        // Evicts hash 0, which was emitted longest ago
+        assert!(window.should_log(1_000, 100, 1_000));
+        assert!(window.should_log(0, 101, 1_000));
+        assert!(!window.should_log(DEDUP_CAPACITY as u64 - 1, 102, 1_000));
+    }
+
+    #[test]
+    fn hash_does_not_depend_on_how_the_message_is_written() {
+        let (sensor, problem) = (7, "out of range");
+        assert_eq!(args_hash(format_args!("sensor {sensor} {problem}")), message_hash("sensor 7 out of range"));
+        assert_ne!(message_hash("sensor 7"), message_hash("sensor 8"));
+    }
+
+    #[test]
+    fn distinct_messages_pass_and_repeats_are_suppressed() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            for id in [1, 2, 1, 2, 1] {
+                log_hz_dedup_window!(Duration::from_secs(60), Level::Warn, "sensor {} out of range", id);
+                // Further apart than the strict rate ceiling
+                clock.advance(Duration::from_millis(10));
+            }
+        });
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["sensor 1 out of range", "sensor 2 out of range"]);
+        });
+    }
+
+    // The strict rate ceiling would hold back messages less than 1ms apart.
+    #[cfg(not(feature = "strict-rate-ceiling"))]
+    #[test]
+    fn reentrant_argument_does_not_deadlock_the_window() {
+        /// Logs from the site it is formatted for, the first time with a message of its own.
+        struct Reentrant;
+
+        impl fmt::Display for Reentrant {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                log_window(&"inner");
+                f.write_str("outer")
+            }
+        }
+
+        fn log_window(value: &dyn fmt::Display) {
+            log_hz_dedup_window!(Duration::from_secs(60), Level::Warn, "value {}", value);
+        }
+
+        testing_logger::setup();
+        log_window(&Reentrant);
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["value inner", "value outer"]);
+        });
+    }
+
+    #[test]
+    fn least_recently_seen_message_is_evicted_when_full() {
+        let mut seen = SeenMessages::new();
+        for hash in 0..SEEN_CAPACITY as u64 {
+            assert!(seen.first_sighting(hash));
+        }
+        // Seeing 0 again makes 1 the least recent
+        assert!(!seen.first_sighting(0));
+        assert!(seen.first_sighting(1_000));
+        assert!(!seen.first_sighting(0));
+        assert!(seen.first_sighting(1));
+    }
+
+    #[test]
+    fn each_distinct_message_logs_once() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            for id in [1, 2, 3, 1, 2, 3] {
+                log_once_per_args!(Level::Warn, "sensor {} out of range", id);
+                // Further apart than the strict rate ceiling
+                clock.advance(Duration::from_millis(10));
+            }
+        });
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["sensor 1 out of range", "sensor 2 out of range", "sensor 3 out of range"]);
+        });
+    }
+
+    // The strict rate ceiling would hold back messages less than 1ms apart.
+    #[cfg(not(feature = "strict-rate-ceiling"))]
+    #[test]
+    fn reentrant_argument_does_not_deadlock_log_once() {
+        /// Logs from the site it is formatted for, the first time with a message of its own.
+        struct Reentrant;
+
+        impl fmt::Display for Reentrant {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                log_once(&"inner");
+                f.write_str("outer")
+            }
+        }
+
+        fn log_once(value: &dyn fmt::Display) {
+            log_once_per_args!(Level::Warn, "value {}", value);
+        }
+
+        testing_logger::setup();
+        log_once(&Reentrant);
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["value inner", "value outer"]);
+        });
+    }
+
+    // The strict rate ceiling would hold back crossings less than 1ms apart.
+    #[cfg(not(feature = "strict-rate-ceiling"))]
+    #[test]
+    fn numeric_dedup_logs_at_tolerance_crossings() {
+        testing_logger::setup();
+        let log = |value: f64| log_hz_dedup_numeric!(0.5, Level::Info, value, "temperature {:.1}");
+        // A ramp from 20.0 to 22.0 in steps of 0.1
+        for tenths in 200..=220 {
+            log(tenths as f64 / 10.0);
+        }
+        // NaN is logged once, and so is the first real value after it
+        for value in [f64::NAN, f64::NAN, 22.0, 22.1] {
+            log(value);
+        }
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(
+                bodies,
+                [
+                    "temperature 20.0",
+                    "temperature 20.6",
+                    "temperature 21.2",
+                    "temperature 21.8",
+                    "temperature NaN",
+                    "temperature 22.0"
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn numeric_dedup_respects_the_min_interval() {
+        testing_logger::setup();
+        for value in [0.0, 10.0, 20.0] {
+            let min_interval = std::time::Duration::from_secs(3600);
+            log_hz_dedup_numeric!(0.5, min_interval: min_interval, Level::Info, value, "value {}");
+        }
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 1));
+    }
+}
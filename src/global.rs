@@ -0,0 +1,254 @@
+//! Optional process-wide limits on top of the per-site throttles: a cap on each level, a maximum rate for every
+//! site, and a signal from the logger that it is overloaded.
+
+use crate::{Level, Rate, ThrottleHandle, ThrottleState};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{PoisonError, RwLock};
+
+/// The cap's interval for each level, indexed by `level as usize - 1`. 0 means no cap.
+static CAP_INTERVAL_NS: [AtomicU64; 5] = [const { AtomicU64::new(0) }; 5];
+/// The timestamp of each level's last emission, and how many messages the cap held back.
+static CAP_STATE: [ThrottleState; 5] = [const { ThrottleState::new() }; 5];
+
+/// The shortest interval any site may use, set by [set_max_hz]. 0 means no limit.
+static MIN_INTERVAL_NS: AtomicU64 = AtomicU64::new(0);
+
+/// The fastest rate a call site may be given with the `strict-rate-ceiling` feature, in Hz.
+///
+/// Read from the `LOG_HZ_RATE_CEILING` environment variable when log_hz is compiled, a whole number, and 1000 if it
+/// is unset. A `log_hz!` (or `info_hz!`, etc.) site with a literal rate above it fails to compile, as does a
+/// [log_hz_const!](crate::log_hz_const) site with a constant one. Rates only known at runtime, e.g. with
+/// [log_hz_dyn!](crate::log_hz_dyn), are clamped to it instead, like [set_max_hz] does.
+#[cfg(feature = "strict-rate-ceiling")]
+pub const STRICT_RATE_CEILING_HZ: u64 = match option_env!("LOG_HZ_RATE_CEILING") {
+    Some(ceiling) => parse_hz(ceiling),
+    None => 1000,
+};
+
+/// The interval of [STRICT_RATE_CEILING_HZ].
+#[cfg(feature = "strict-rate-ceiling")]
+const CEILING_INTERVAL_NS: u64 = crate::interval_from_rate(STRICT_RATE_CEILING_HZ as f64);
+
+#[cfg(feature = "strict-rate-ceiling")]
+const fn parse_hz(hz: &str) -> u64 {
+    let (bytes, mut i, mut parsed) = (hz.as_bytes(), 0, 0u64);
+    assert!(!bytes.is_empty(), "LOG_HZ_RATE_CEILING must be a whole number of Hz");
+    while i < bytes.len() {
+        assert!(bytes[i].is_ascii_digit(), "LOG_HZ_RATE_CEILING must be a whole number of Hz");
+        parsed = parsed * 10 + (bytes[i] - b'0') as u64;
+        i += 1;
+    }
+    parsed
+}
+
+/// The predicate set with [set_backpressure_signal], if any.
+static BACKPRESSURE_SIGNAL: RwLock<Option<fn() -> bool>> = RwLock::new(None);
+/// Messages held back because the backpressure signal reported an overloaded logger.
+static BACKPRESSURE_SUPPRESSED: AtomicU64 = AtomicU64::new(0);
+
+/// Whether sites shift their emissions by a phase derived from their location, set by [set_phase_spreading].
+static PHASE_SPREADING: AtomicBool = AtomicBool::new(false);
+
+/// Limit every throttled call site to at most `rate` messages per second, like [set_max_level](crate::set_max_level)
+/// does for levels.
+///
+/// Sites with a lower rate are unaffected, while faster ones are slowed down to `rate`. This applies to each site on
+/// its own, unlike [set_global_level_cap] which limits all sites at a level together. There is no limit by default,
+/// and setting an infinite rate removes it again. A rate of 0 or less stops all throttled messages.
+///
+/// Every macro that throttles by a rate or an interval is limited, as are [LogEveryHz](crate::LogEveryHz) and the
+/// stream adapter. The initial burst of [log_hz_smart!](crate::log_hz_smart), the edge of
+/// [log_hz_edge!](crate::log_hz_edge) and the messages let through by
+/// [log_hz_dedup_window!](crate::log_hz_dedup_window) and [log_once_per_args!](crate::log_once_per_args) skip the
+/// site's own interval but are still held to this one. Selection that doesn't depend on time is not:
+/// [log_hz_ratio!](crate::log_hz_ratio) without `min_gap`, [log_hz_seq!](crate::log_hz_seq) and the wall-clock
+/// seconds of `log_hz_on_nth_second!`. Neither are
+/// [force_log_hz!](crate::force_log_hz), which bypasses throttling on purpose, and [Throttler](crate::Throttler),
+/// which isn't a call site.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// set_max_hz(1.0);
+/// info_hz!(100.0, "Logged at most once a second");
+/// # set_max_hz(f64::INFINITY);
+/// ```
+pub fn set_max_hz(rate: impl Rate) {
+    MIN_INTERVAL_NS.store(rate.interval_ns(), Ordering::Relaxed);
+}
+
+/// The limit set with [set_max_hz], infinite if there is none.
+pub fn max_hz() -> f64 {
+    match MIN_INTERVAL_NS.load(Ordering::Relaxed) {
+        0 => f64::INFINITY,
+        u64::MAX => 0.0,
+        interval_ns => 1e9 / interval_ns as f64,
+    }
+}
+
+/// `interval_ns`, lengthened to the limit set with [set_max_hz] (or the strict rate ceiling) if it is shorter.
+#[doc(hidden)]
+#[inline]
+pub fn limit_interval(interval_ns: u64) -> u64 {
+    #[cfg(feature = "strict-rate-ceiling")]
+    let ceiling_ns = CEILING_INTERVAL_NS;
+    #[cfg(not(feature = "strict-rate-ceiling"))]
+    let ceiling_ns = 0;
+    interval_ns.max(MIN_INTERVAL_NS.load(Ordering::Relaxed)).max(ceiling_ns)
+}
+
+/// Whether a message at `level` may be emitted at `now_ns`: the site's own throttle `check`, run with `interval_ns`
+/// lengthened by [limit_interval], lets it through, and then so do the backpressure signal and the level's cap.
+///
+/// Every throttled macro and adapter decides through this, so the process-wide limits reach all of them alike.
+#[doc(hidden)]
+#[inline]
+pub fn site_allows(level: Level, now_ns: u64, interval_ns: u64, check: impl FnOnce(u64) -> bool) -> bool {
+    check(limit_interval(interval_ns)) && backpressure_allows() && global_cap_allows(level, now_ns)
+}
+
+/// Like [site_allows], but without asking the backpressure signal, which takes a lock and runs a callback. The other
+/// limits are atomics only.
+#[doc(hidden)]
+#[inline]
+pub fn site_allows_without_backpressure(
+    level: Level,
+    now_ns: u64,
+    interval_ns: u64,
+    check: impl FnOnce(u64) -> bool,
+) -> bool {
+    check(limit_interval(interval_ns)) && global_cap_allows(level, now_ns)
+}
+
+/// Limit messages at `level` to `rate` per second across every call site, as a last-resort guard against spam.
+///
+/// A throttled message is only emitted if it passes both its own site's throttle and the cap for its level.
+/// Levels have no cap by default. Passing a rate of 0 or less silences throttled messages at `level`
+/// entirely, while plain `log` macros are never capped. A message held back by the cap still used up its
+/// site's slot, so it is counted in [global_level_suppressed] rather than in the site's suppressed count. The cap
+/// applies to the same sites as [set_max_hz], by the level each message is emitted at.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// // No more than 10 throttled errors per second, however many sites they come from
+/// set_global_level_cap(Level::Error, 10.0);
+/// # clear_global_level_cap(Level::Error);
+/// ```
+pub fn set_global_level_cap(level: Level, rate: impl Rate) {
+    // An interval of 0 would mean no cap, and only an infinite rate rounds to it.
+    let interval_ns = rate.interval_ns().max(1);
+    CAP_INTERVAL_NS[index(level)].store(interval_ns, Ordering::Relaxed);
+}
+
+/// Remove the cap set with [set_global_level_cap], so `level` is only throttled per site again.
+pub fn clear_global_level_cap(level: Level) {
+    CAP_INTERVAL_NS[index(level)].store(0, Ordering::Relaxed);
+    CAP_STATE[index(level)].reset();
+}
+
+/// Total number of messages at `level` that passed their site's throttle but were held back by the global cap.
+pub fn global_level_suppressed(level: Level) -> u64 {
+    CAP_STATE[index(level)].total_suppressed()
+}
+
+/// Whether a message at `level` that passed its site's throttle at `now_ns` is also allowed by the global cap.
+#[doc(hidden)]
+#[inline]
+pub fn global_cap_allows(level: Level, now_ns: u64) -> bool {
+    match CAP_INTERVAL_NS[index(level)].load(Ordering::Relaxed) {
+        0 => true,
+        interval_ns => CAP_STATE[index(level)].should_log(now_ns, interval_ns),
+    }
+}
+
+/// Hold back throttled messages while `signal` returns true, e.g. while an async logger's buffer is full.
+///
+/// A fixed rate can still overwhelm a logger that has fallen behind, so `signal` is asked before each message that
+/// passed its site's throttle, and the message is dropped if it reports backpressure. Like a message held back by
+/// [set_global_level_cap], it used up its site's slot, and is counted in [backpressure_suppressed]. `signal` is called
+/// from whichever thread is logging, so it should be as cheap as an atomic load. It isn't asked by
+/// [log_hz_panic_guard!](crate::log_hz_panic_guard), which may run while the thread is unwinding.
+///
+/// ```rust
+/// use log_hz::*;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// static QUEUED: AtomicUsize = AtomicUsize::new(0); // Maintained by the logger
+///
+/// set_backpressure_signal(|| QUEUED.load(Ordering::Relaxed) > 10_000);
+/// # clear_backpressure_signal();
+/// ```
+pub fn set_backpressure_signal(signal: fn() -> bool) {
+    *BACKPRESSURE_SIGNAL.write().unwrap_or_else(PoisonError::into_inner) = Some(signal);
+}
+
+/// Remove the signal set with [set_backpressure_signal].
+pub fn clear_backpressure_signal() {
+    *BACKPRESSURE_SIGNAL.write().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// Total number of messages that passed their site's throttle but were held back by the backpressure signal.
+pub fn backpressure_suppressed() -> u64 {
+    BACKPRESSURE_SUPPRESSED.load(Ordering::Relaxed)
+}
+
+/// Whether a message that passed its site's throttle may be emitted, i.e. no backpressure is reported.
+#[doc(hidden)]
+#[inline]
+pub fn backpressure_allows() -> bool {
+    let signal = *BACKPRESSURE_SIGNAL.read().unwrap_or_else(PoisonError::into_inner);
+    if signal.is_some_and(|overloaded| overloaded()) {
+        BACKPRESSURE_SUPPRESSED.fetch_add(1, Ordering::Relaxed);
+        return false;
+    }
+    true
+}
+
+/// Spread the emissions of sites with the same rate across their interval, instead of all emitting on the same tick.
+///
+/// Sites that first ran together, e.g. at process start, log in lockstep: dozens of 1Hz sites make a spike in log
+/// volume once a second. With phase spreading, each site's second message is delayed by an offset within the
+/// interval derived from a hash of its file and line, and later messages keep that phase. The offset is the same in
+/// every run, unlike the random one of [log_hz_jittered!](crate::log_hz_jittered), and the first message is still
+/// logged immediately. Applies to `log_hz!` (and `info_hz!`, etc.) and `log_hz_dyn!` sites whose first message is
+/// emitted while it is enabled. Off by default.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// set_phase_spreading(true);
+/// info_hz!(1.0, "Logged right away, then once a second at this site's own phase");
+/// # set_phase_spreading(false);
+/// ```
+pub fn set_phase_spreading(enabled: bool) {
+    PHASE_SPREADING.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether [set_phase_spreading] is enabled.
+pub fn phase_spreading() -> bool {
+    PHASE_SPREADING.load(Ordering::Relaxed)
+}
+
+/// After the site behind `handle` emitted at `now_ns`, delay its next message by its phase if this was its first.
+#[doc(hidden)]
+#[inline]
+pub fn spread_phase(handle: &ThrottleHandle, now_ns: u64, interval_ns: u64) {
+    let state = handle.state();
+    if PHASE_SPREADING.load(Ordering::Relaxed) && state.total_emitted() == 1 && interval_ns != 0 {
+        state.shift_last_log(now_ns.saturating_add(phase_offset_ns(handle.file(), handle.line(), interval_ns)));
+    }
+}
+
+/// A site's offset within `interval_ns`, from an FNV-1a hash of its location so it is the same in every run.
+fn phase_offset_ns(file: &str, line: u32, interval_ns: u64) -> u64 {
+    let hash = file
+        .bytes()
+        .chain(line.to_le_bytes())
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3));
+    hash % interval_ns
+}
+
+fn index(level: Level) -> usize {
+    level as usize - 1
+}
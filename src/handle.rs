@@ -0,0 +1,261 @@
+//! Handles for inspecting and resetting a call site's throttle from elsewhere.
+
+use crate::ThrottleState;
+use crate::clock::now_ns;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// A temporary override of a call site's interval, set with [boost_site](crate::boost_site).
+#[doc(hidden)]
+#[derive(Debug, Default)]
+pub struct RateBoost {
+    interval_ns: AtomicU64,
+    /// When the override expires, 0 if it was never set.
+    until_ns: AtomicU64,
+}
+
+impl RateBoost {
+    pub const fn new() -> Self {
+        Self {
+            interval_ns: AtomicU64::new(u64::MAX),
+            until_ns: AtomicU64::new(0),
+        }
+    }
+
+    /// The interval to use at `now_ns`: the override while it lasts, `compiled_ns` otherwise.
+    #[inline]
+    pub fn interval_ns(&self, now_ns: u64, compiled_ns: u64) -> u64 {
+        // Only the registry can set an override, so without it this is always the compiled interval.
+        if cfg!(feature = "registry") && now_ns < self.until_ns.load(Ordering::Acquire) {
+            self.interval_ns.load(Ordering::Relaxed)
+        } else {
+            compiled_ns
+        }
+    }
+
+    /// Whether an override was ever set, so a disabled site must still read the clock in case it is boosted.
+    #[inline]
+    pub fn is_set(&self) -> bool {
+        cfg!(feature = "registry") && self.until_ns.load(Ordering::Relaxed) != 0
+    }
+
+    #[cfg(feature = "registry")]
+    pub(crate) fn set(&self, interval_ns: u64, until_ns: u64) {
+        self.interval_ns.store(interval_ns, Ordering::Relaxed);
+        // Release pairs with the Acquire above so an unexpired override implies its interval is visible.
+        self.until_ns.store(until_ns, Ordering::Release);
+    }
+}
+
+/// A `'static` reference to one call site's throttle state.
+///
+/// Returned by the `handle:` form of [log_hz!](crate::log_hz), [log_hz_dyn!](crate::log_hz_dyn) and the level
+/// shorthands like `info_hz!`, which log as usual and evaluate to the site's handle. Handles are `Copy`, so they can be
+/// stored and used from any thread, e.g. to reset a site from an admin command. With the `registry` feature,
+/// [throttle_handle!](crate::throttle_handle) placed after a call returns the same handle.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// let handle = info_hz!(handle: 1.0, "Hello, world!");
+/// assert_eq!(handle.line(), line!() - 1);
+/// handle.reset();
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct ThrottleHandle {
+    state: &'static ThrottleState,
+    interval_ns: fn() -> u64,
+    boost: &'static RateBoost,
+    file: &'static str,
+    line: u32,
+}
+
+impl ThrottleHandle {
+    #[doc(hidden)]
+    pub const fn new(
+        state: &'static ThrottleState,
+        interval_ns: fn() -> u64,
+        boost: &'static RateBoost,
+        file: &'static str,
+        line: u32,
+    ) -> Self {
+        Self {
+            state,
+            interval_ns,
+            boost,
+            file,
+            line,
+        }
+    }
+
+    /// The source file of the call site.
+    pub fn file(&self) -> &'static str {
+        self.file
+    }
+
+    /// The line of the call site.
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub(crate) fn state(&self) -> &'static ThrottleState {
+        self.state
+    }
+
+    /// The site's interval without any boost.
+    #[cfg(any(test, feature = "registry", feature = "testing"))]
+    pub(crate) fn compiled_interval_ns(&self) -> u64 {
+        (self.interval_ns)()
+    }
+
+    #[cfg(feature = "registry")]
+    pub(crate) fn boost(&self) -> &'static RateBoost {
+        self.boost
+    }
+
+    /// Reset the site so its next call logs immediately.
+    pub fn reset(&self) {
+        self.state.reset();
+    }
+
+    /// Number of calls throttled at this site since its last emission.
+    pub fn suppressed_count(&self) -> u64 {
+        self.state.suppressed_count()
+    }
+
+    /// How long until the site will next be allowed to log.
+    ///
+    /// Returns [Duration::ZERO] if the next call would log, and [Duration::MAX] if the site is disabled.
+    pub fn time_until_next(&self) -> Duration {
+        let now_ns = now_ns();
+        let interval_ns = crate::limit_interval(self.boost.interval_ns(now_ns, (self.interval_ns)()));
+        if interval_ns == u64::MAX {
+            return Duration::MAX;
+        }
+        match self.state.last_log_ns() {
+            None if self.state.logs_first() => Duration::ZERO,
+            // The next call only starts the timer
+            None => Duration::from_nanos(interval_ns),
+            Some(last_ns) => {
+                Duration::from_nanos(last_ns.saturating_add(interval_ns).saturating_sub(now_ns))
+            }
+        }
+    }
+}
+
+/// The line [log_hz_stats!] logs for `handle`'s site.
+#[doc(hidden)]
+pub fn stats_line(handle: &ThrottleHandle) -> String {
+    let state = handle.state();
+    let next = match handle.time_until_next() {
+        Duration::MAX => "never".to_string(),
+        Duration::ZERO => "now".to_string(),
+        until_next => format!("in {until_next:?}"),
+    };
+    format!(
+        "log_hz: {}:{} emitted {}, suppressed {} ({} since last), next allowed {next}",
+        handle.file(),
+        handle.line(),
+        state.total_emitted(),
+        state.total_suppressed(),
+        state.suppressed_count(),
+    )
+}
+
+/// Log the counters of a call site, for a quick look while debugging.
+///
+/// Takes the [ThrottleHandle] returned by the site's `handle:` form. It logs one unthrottled line at `Info`, or at the
+/// given level, with the site's emitted and suppressed totals and when it may next log. For the counters of every
+/// site use [site_stats](crate::site_stats) with the `registry` feature instead.
+///
+/// With the `registry` feature, `log_hz_stats!()` placed after a call logs the counters of that site, found like
+/// [throttle_handle!](crate::throttle_handle) finds it, or says that no site above it has run yet.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// fn process(item: u32) -> ThrottleHandle {
+///     info_hz!(handle: 1.0, "Processing item {}", item)
+/// }
+///
+/// let site = (0..100).map(process).last().unwrap();
+/// log_hz_stats!(site); // log_hz: src/main.rs:4 emitted 1, suppressed 99 (99 since last), next allowed in 999.9ms
+/// log_hz_stats!(site, Level::Debug);
+/// ```
+#[macro_export]
+macro_rules! log_hz_stats {
+    () => {
+        match $crate::throttle_handle!() {
+            Some(handle) => $crate::log_hz_stats!(handle),
+            None => $crate::__private::log!(
+                $crate::Level::Info,
+                "log_hz: no call site at or above {}:{} has run",
+                file!(),
+                line!()
+            ),
+        }
+    };
+    ($handle:expr) => {
+        $crate::log_hz_stats!($handle, $crate::Level::Info)
+    };
+    ($handle:expr, $level:expr) => {
+        $crate::__private::log!($level, "{}", $crate::__private::stats_line(&$handle))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn handle_resets_its_site() {
+        testing_logger::setup();
+        let log = || info_hz!(handle: 1.0, "Hello, world!");
+        let handle = log();
+        log();
+        log();
+        assert_eq!(handle.suppressed_count(), 2);
+        assert!(handle.time_until_next() > Duration::ZERO);
+
+        handle.reset();
+        assert_eq!(handle.time_until_next(), Duration::ZERO);
+        log();
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 2));
+    }
+
+    #[test]
+    fn handle_refers_to_its_own_site() {
+        testing_logger::setup();
+        // A throttled call made while formatting the message doesn't affect which site the handle refers to
+        struct Nested;
+        impl std::fmt::Display for Nested {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                info_hz!(0.0, "second");
+                write!(f, "nested")
+            }
+        }
+        let first = info_hz!(handle: 1.0, "first {}", Nested);
+        let second = log_hz_dyn!(handle: Level::Info, 0.0, "second");
+        assert_eq!(first.line(), line!() - 2);
+        assert!(first.time_until_next() > Duration::ZERO);
+        assert!(first.time_until_next() < Duration::from_secs(2));
+        assert_eq!(second.time_until_next(), Duration::MAX);
+    }
+
+    #[test]
+    fn stats_report_the_given_site() {
+        testing_logger::setup();
+        let log = || warn_hz!(handle: 1.0, "driven");
+        for _ in 0..9 {
+            log();
+        }
+        log_hz_stats!(log());
+        testing_logger::validate(|captured_logs| {
+            let stats = &captured_logs.last().unwrap().body;
+            assert_eq!(captured_logs.len(), 2);
+            assert!(stats.contains(&format!("handle.rs:{}", line!() - 8)), "{stats}");
+            assert!(stats.contains("emitted 1, suppressed 9 (9 since last), next allowed in "), "{stats}");
+        });
+    }
+}
@@ -0,0 +1,128 @@
+//! Throttled logging from inside iterator chains.
+
+use crate::__private::level_enabled;
+use crate::clock::now_ns;
+use crate::global::site_allows;
+use crate::sink::target_enabled;
+use crate::{Level, ThrottleState, ThrottledLogger, interval_from_rate};
+use std::fmt::Display;
+
+/// Extension trait adding throttled logging to any iterator.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// let total: u64 = (0..1_000_000u64)
+///     .log_every_hz(1.0, Level::Info, |i| format!("Processing item {i}"))
+///     .with_target(module_path!())
+///     .sum();
+/// ```
+pub trait ThrottleIterExt: Iterator + Sized {
+    /// Log a message built from the current item at most `rate` times per second.
+    ///
+    /// Items are passed through unchanged. `msg_fn` is only called when a message is actually emitted,
+    /// and like the macros the first item always logs. Each adapter has its own throttle state. Items at a disabled
+    /// level don't read the clock or use up the slot.
+    fn log_every_hz<F, M>(self, rate: f64, level: Level, msg_fn: F) -> LogEveryHz<Self, F>
+    where
+        F: FnMut(&Self::Item) -> M,
+        M: Display,
+    {
+        LogEveryHz {
+            iter: self,
+            msg_fn,
+            level,
+            target: None,
+            interval_ns: interval_from_rate(rate),
+            state: ThrottleState::new(),
+        }
+    }
+}
+
+impl<I: Iterator> ThrottleIterExt for I {}
+
+/// Iterator returned by [ThrottleIterExt::log_every_hz].
+#[derive(Debug)]
+pub struct LogEveryHz<I, F> {
+    iter: I,
+    msg_fn: F,
+    level: Level,
+    target: Option<&'static str>,
+    interval_ns: u64,
+    state: ThrottleState,
+}
+
+impl<I, F> LogEveryHz<I, F> {
+    /// Log with `target`, e.g. `module_path!()`, instead of this module's path, so the logger's per-target filters
+    /// apply. Like a `target:` passed to the macros, items the logger filters out don't use up the slot.
+    pub fn with_target(mut self, target: &'static str) -> Self {
+        self.target = Some(target);
+        self
+    }
+}
+
+impl<I, F, M> Iterator for LogEveryHz<I, F>
+where
+    I: Iterator,
+    F: FnMut(&I::Item) -> M,
+    M: Display,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next()?;
+        if level_enabled(self.level)
+            && target_enabled(self.level, self.target)
+            && {
+                let now_ns = now_ns();
+                site_allows(self.level, now_ns, self.interval_ns, |interval_ns| {
+                    self.state.should_log(now_ns, interval_ns)
+                })
+            }
+        {
+            let target = self.target.unwrap_or(module_path!());
+            log::log!(logger: ThrottledLogger, target: target, self.level, "{}", (self.msg_fn)(&item));
+        }
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn items_pass_through_while_logs_are_throttled() {
+        testing_logger::setup();
+        let items: Vec<u32> = (0..100)
+            .log_every_hz(1.0, Level::Info, |i| format!("item {i}"))
+            .collect();
+        assert_eq!(items, (0..100).collect::<Vec<_>>());
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(captured_logs.len(), 1);
+            assert_eq!(captured_logs[0].body, "item 0");
+        });
+    }
+
+    #[test]
+    fn adapter_follows_the_crate_clock_and_target() {
+        testing_logger::setup();
+        let clock = crate::ManualClock::default();
+        let items = crate::with_clock(clock.clone(), || {
+            (0..30)
+                .inspect(|_| clock.advance(std::time::Duration::from_millis(100)))
+                .log_every_hz(1.0, Level::Info, |i| format!("item {i}"))
+                .with_target("app::items")
+                .count()
+        });
+        assert_eq!(items, 30);
+        testing_logger::validate(|captured_logs| {
+            let logs: Vec<_> = captured_logs.iter().map(|log| (log.target.as_str(), log.body.as_str())).collect();
+            assert_eq!(logs, [("app::items", "item 0"), ("app::items", "item 10"), ("app::items", "item 20")]);
+        });
+    }
+}
@@ -0,0 +1,492 @@
+//! Throttles keyed by a runtime value, so one call site can hold a separate timer per key.
+
+use crate::{Level, ThrottleState};
+use std::collections::BTreeMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Mutex, PoisonError};
+
+/// A set of [ThrottleState]s created on demand, one per key.
+///
+/// Every check takes a lock and looks the key up, so this is much slower than the lock-free throttle of
+/// a plain call site. Keys are never removed, so the key space should be bounded.
+///
+/// Like the rest of log_hz, keyed throttling needs `std`: the timers read a `std` clock and the keys sit behind a
+/// [std::sync::Mutex]. There is no `no_std` + `alloc` configuration.
+#[derive(Debug, Default)]
+pub struct KeyedThrottle<K> {
+    states: Mutex<BTreeMap<K, ThrottleState>>,
+}
+
+impl<K: Ord> KeyedThrottle<K> {
+    /// Create a throttle with no keys.
+    pub const fn new() -> Self {
+        Self {
+            states: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Like [ThrottleState::should_log], using the timer for `key` (which logs first if the key is new).
+    pub fn should_log(&self, key: K, now_ns: u64, interval_ns: u64) -> bool {
+        self.states
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(key)
+            .or_default()
+            .should_log(now_ns, interval_ns)
+    }
+}
+
+/// The throttles shared by [log_hz_merge!] sites, one per merge key.
+static MERGED: Mutex<BTreeMap<String, ThrottleState>> = Mutex::new(BTreeMap::new());
+
+/// Like [ThrottleState::should_log], using the throttle shared by every [log_hz_merge!] site with `key`.
+#[doc(hidden)]
+pub fn merged_should_log(key: &str, now_ns: u64, interval_ns: u64) -> bool {
+    let mut states = MERGED.lock().unwrap_or_else(PoisonError::into_inner);
+    match states.get(key) {
+        Some(state) => state.should_log(now_ns, interval_ns),
+        // Only allocate the key the first time it's seen
+        None => states.entry(key.to_owned()).or_default().should_log(now_ns, interval_ns),
+    }
+}
+
+/// Number of discriminants a [CoalescedErrors] remembers.
+pub const COALESCE_KEYS: usize = 32;
+
+/// Number of distinct messages counted per discriminant between emissions, beyond which the count stops growing.
+pub const COALESCE_VARIANTS: usize = 32;
+
+#[derive(Debug)]
+struct CoalescedKey {
+    key: u64,
+    state: ThrottleState,
+    /// Hashes of the distinct messages seen since the key's last emission.
+    variants: Vec<u64>,
+}
+
+/// The state behind [log_hz_coalesce_errors!]: a throttle per discriminant, and the distinct messages each has seen.
+///
+/// Holds at most [COALESCE_KEYS] discriminants. When a new one arrives while full, the one emitted longest ago is
+/// forgotten, so memory use is bounded however many discriminants there are.
+#[derive(Debug, Default)]
+pub struct CoalescedErrors {
+    keys: Mutex<Vec<CoalescedKey>>,
+}
+
+impl CoalescedErrors {
+    /// Create a set with no discriminants.
+    pub const fn new() -> Self {
+        Self { keys: Mutex::new(Vec::new()) }
+    }
+
+    /// Record a message hashing to `message` under the discriminant hashing to `key`.
+    ///
+    /// Returns how many distinct messages the discriminant saw since its last emission (including this one) if the
+    /// message should be emitted now, and `None` if it is throttled.
+    pub fn check(&self, key: u64, message: u64, now_ns: u64, interval_ns: u64) -> Option<usize> {
+        let mut keys = self.keys.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = match keys.iter().position(|entry| entry.key == key) {
+            Some(index) => index,
+            None => {
+                if keys.len() == COALESCE_KEYS
+                    && let Some(oldest) = (0..keys.len()).min_by_key(|&i| keys[i].state.last_log_ns().unwrap_or(0))
+                {
+                    keys.swap_remove(oldest);
+                }
+                keys.push(CoalescedKey {
+                    key,
+                    state: ThrottleState::new(),
+                    variants: Vec::new(),
+                });
+                keys.len() - 1
+            }
+        };
+        let entry = &mut keys[index];
+        if !entry.variants.contains(&message) && entry.variants.len() < COALESCE_VARIANTS {
+            entry.variants.push(message);
+        }
+        if !entry.state.should_log(now_ns, interval_ns) {
+            return None;
+        }
+        let variants = entry.variants.len();
+        entry.variants.clear();
+        Some(variants)
+    }
+}
+
+/// Hash a discriminant for [CoalescedErrors].
+pub fn key_hash(key: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Log errors throttled per root cause, counting the distinct messages coalesced into each emission.
+///
+/// Errors that share a cause often differ in their details, like a timeout's duration or a peer's address, so
+/// throttling them per message lets every variant through while throttling per call site hides other causes.
+/// This throttles per `discriminant` instead (anything [Hash], typically an error kind enum), so each cause logs
+/// at `rate` on its own. When an emission stands in for more than one distinct message, it says so with
+/// `(coalesced N distinct messages)` appended, counting those seen since the cause last logged (up to
+/// [COALESCE_VARIANTS]).
+///
+/// The message is formatted into a hash on every call to tell variants apart, without allocating. The site tracks
+/// up to [COALESCE_KEYS] discriminants, forgetting the one emitted longest ago when a new one arrives.
+///
+/// ```rust
+/// use log_hz::*;
+/// # let results: Vec<Result<(), std::io::Error>> = Vec::new();
+///
+/// for result in results {
+///     if let Err(error) = result {
+///         log_hz_coalesce_errors!(error.kind(), 1.0, Level::Error, "Request failed: {}", error);
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_hz_coalesce_errors {
+    ($discriminant:expr, $rate:expr, $level:expr, $($arg:tt)+) => {
+        {
+            use std::sync::LazyLock;
+
+            static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| $crate::Rate::interval_ns($rate));
+            static ERRORS: $crate::CoalescedErrors = $crate::CoalescedErrors::new();
+
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level) {
+                // Matched rather than bound with `let`, so the arguments' temporaries live until the message is logged.
+                match format_args!($($arg)+) {
+                    args => {
+                        let key = $crate::key_hash(&$discriminant);
+                        let now_ns = $crate::__private::now_ns();
+                        let mut variants = 0;
+                        if $crate::__private::site_allows(level, now_ns, *INTERVAL_NS, |interval_ns| {
+                            variants = ERRORS.check(key, $crate::args_hash(args), now_ns, interval_ns).unwrap_or(0);
+                            variants > 0
+                        }) {
+                            $crate::__private::emit_once(&ERRORS, || {
+                                if variants > 1 {
+                                    $crate::__log_hz_emit!(
+                                        level,
+                                        "{} (coalesced {} distinct messages)",
+                                        args,
+                                        variants
+                                    );
+                                } else {
+                                    $crate::__log_hz_emit!(level, "{}", args);
+                                }
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    };
+}
+
+/// Log a message at a throttled rate, with a separate timer for each location that calls the enclosing function.
+///
+/// A `log_hz!` inside a helper function has one static timer, shared by every caller of the helper. When the
+/// helper is marked `#[track_caller]`, this macro keys its timer on [Location::caller](std::panic::Location::caller)
+/// instead, so each place that calls the helper is throttled independently. Without `#[track_caller]` the location
+/// is the macro's own, and it behaves like [log_hz!].
+///
+/// Each call takes a lock and looks up the caller in a map, costing far more than the single atomic load of
+/// [log_hz!]'s fast path. Only use it where the per-caller throttling is needed.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// #[track_caller]
+/// fn report_failure(reason: &str) {
+///     log_hz_caller!(Level::Warn, 1.0, "Operation failed: {}", reason);
+/// }
+///
+/// report_failure("timeout"); // Logs
+/// report_failure("timeout"); // Logs too, this is a different caller
+/// ```
+#[macro_export]
+macro_rules! log_hz_caller {
+    ($level:expr, $rate:expr, $($arg:tt)+) => {
+        {
+            use std::sync::LazyLock;
+
+            static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| $crate::Rate::interval_ns($rate));
+            static CALLERS: $crate::KeyedThrottle<std::panic::Location<'static>> = $crate::KeyedThrottle::new();
+
+            let (caller, level): (_, $crate::Level) = (*std::panic::Location::caller(), $level);
+            if $crate::__private::level_enabled(level) && {
+                let now_ns = $crate::__private::now_ns();
+                $crate::__private::site_allows(level, now_ns, *INTERVAL_NS, |interval_ns| {
+                    CALLERS.should_log(caller, now_ns, interval_ns)
+                })
+            } {
+                $crate::__private::emit_once(&CALLERS, || $crate::__log_hz_emit!(level, $($arg)+));
+            }
+        }
+    };
+}
+
+/// Log a message at a throttled rate shared by every call site using the same `merge_key`.
+///
+/// The same message logged from several places, like copy-pasted error handling, is one problem to an operator but
+/// gets a timer per call site. Sites given the same `merge_key` (any expression that derefs to `str`, and may be built
+/// at runtime) throttle against one process-wide timer for that key instead, so the message's total rate stays at
+/// `rate` however many sites produce it. Unlike [log_hz_group!](crate::log_hz_group), which only resets and counts
+/// its sites together, the throttle itself is shared. Each site checks the shared timer against its own `rate`, so
+/// sites sharing a key should use the same rate.
+///
+/// Each call takes a lock and looks up the key in a map, costing far more than the single atomic load of [log_hz!]'s
+/// fast path. Keys are never removed, so the key space should be bounded.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// fn load_config() {
+///     log_hz_merge!("config-missing", 1.0, Level::Warn, "No config file, using defaults");
+/// }
+///
+/// fn reload_config() {
+///     // Throttled together with the warning in `load_config`
+///     log_hz_merge!("config-missing", 1.0, Level::Warn, "No config file, using defaults");
+/// }
+/// # load_config();
+/// # reload_config();
+/// ```
+#[macro_export]
+macro_rules! log_hz_merge {
+    ($merge_key:expr, $rate:expr, $level:expr, $($arg:tt)+) => {
+        {
+            use std::sync::LazyLock;
+
+            static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| $crate::Rate::interval_ns($rate));
+
+            let level: $crate::Level = $level;
+            let merge_key: &str = &$merge_key;
+            if $crate::__private::level_enabled(level)
+                && {
+                    let now_ns = $crate::__private::now_ns();
+                    $crate::__private::site_allows(level, now_ns, *INTERVAL_NS, |interval_ns| {
+                        $crate::__private::merged_should_log(merge_key, now_ns, interval_ns)
+                    })
+                }
+            {
+                $crate::__private::emit_once(&INTERVAL_NS, || $crate::__log_hz_emit!(level, $($arg)+));
+            }
+        }
+    };
+}
+
+/// The interval shared by the sites of an [OrderedThrottle], and the most severe level emitted in it.
+#[derive(Debug, Default)]
+struct OrderedWindow {
+    start_ns: Option<u64>,
+    most_severe: Option<Level>,
+}
+
+/// A throttle shared by several [log_hz_ordered!] sites, which lets a more severe message through an interval that
+/// a less severe one already used.
+///
+/// Intervals start with the first message emitted after the previous one ended. Within an interval, a message is
+/// emitted only if it is more severe than every message emitted so far, so up to one message per level gets through
+/// (in increasing severity), and once an error is emitted nothing else is until the interval ends. Every check takes
+/// a lock shared by all of the sites.
+#[derive(Debug, Default)]
+pub struct OrderedThrottle {
+    window: Mutex<OrderedWindow>,
+}
+
+impl OrderedThrottle {
+    /// Create a throttle whose first message is emitted whatever its level.
+    pub const fn new() -> Self {
+        Self {
+            window: Mutex::new(OrderedWindow { start_ns: None, most_severe: None }),
+        }
+    }
+
+    /// Returns true if a message at `level` should be emitted at `now_ns`, given `interval_ns` between intervals.
+    pub fn should_log(&self, level: Level, now_ns: u64, interval_ns: u64) -> bool {
+        if interval_ns == u64::MAX {
+            return false;
+        }
+        let mut window = self.window.lock().unwrap_or_else(PoisonError::into_inner);
+        let in_window = window.start_ns.is_some_and(|start_ns| now_ns.saturating_sub(start_ns) < interval_ns);
+        if !in_window {
+            window.start_ns = Some(now_ns);
+        } else if window.most_severe.is_some_and(|most_severe| level >= most_severe) {
+            return false;
+        }
+        window.most_severe = Some(level);
+        true
+    }
+}
+
+/// Log at a throttled rate shared with other sites, preferring the most severe level when they fire together.
+///
+/// When errors, warnings and infos all fire in a tight loop, throttling each site on its own lets them all through,
+/// and a single shared throttle lets whichever runs first through, even if it's an info in the same moment as an
+/// error. Sites passing the same [OrderedThrottle] instead share one interval, in which a message is only emitted if
+/// it is more severe than every message already emitted in it. So an error is never held back by a less severe
+/// message, while those are dropped once something more severe was logged.
+///
+/// Opt in only where the ordering matters: every call takes the context's lock, unlike the lock-free fast path of
+/// [log_hz!](crate::log_hz), and up to one message per level may be emitted in each interval.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// static STORAGE: OrderedThrottle = OrderedThrottle::new();
+///
+/// # let (queue_len, write_failed) = (100, true);
+/// log_hz_ordered!(STORAGE, 1.0, Level::Info, "Queue length: {}", queue_len);
+/// if write_failed {
+///     log_hz_ordered!(STORAGE, 1.0, Level::Error, "Write failed"); // Logs despite the info above
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_hz_ordered {
+    ($context:expr, $rate:expr, $level:expr, $($arg:tt)+) => {
+        {
+            // Identifies the site to the re-entry guard, as the throttle is shared.
+            static SITE: u8 = 0;
+
+            let context: &$crate::OrderedThrottle = &$context;
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level)
+                && {
+                    let now_ns = $crate::__private::now_ns();
+                    $crate::__private::site_allows(level, now_ns, $crate::Rate::interval_ns($rate), |interval_ns| {
+                        context.should_log(level, now_ns, interval_ns)
+                    })
+                }
+            {
+                $crate::__private::emit_once(&SITE, || $crate::__log_hz_emit!(level, $($arg)+));
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn keys_are_throttled_independently() {
+        let throttle = KeyedThrottle::new();
+        assert!(throttle.should_log("a", 10, 100));
+        assert!(throttle.should_log("b", 20, 100));
+        assert!(!throttle.should_log("a", 50, 100));
+        assert!(throttle.should_log("a", 110, 100));
+    }
+
+    #[test]
+    fn coalesced_keys_are_capped() {
+        let errors = CoalescedErrors::new();
+        for key in 0..COALESCE_KEYS as u64 {
+            assert_eq!(errors.check(key, 0, key + 1, 1_000), Some(1));
+        }
+        // Evicts key 0, which was emitted longest ago
+        assert_eq!(errors.check(1_000, 0, 100, 1_000), Some(1));
+        assert_eq!(errors.check(0, 0, 101, 1_000), Some(1));
+        assert_eq!(errors.check(COALESCE_KEYS as u64 - 1, 0, 102, 1_000), None);
+        assert_eq!(errors.keys.lock().unwrap().len(), COALESCE_KEYS);
+    }
+
+    #[test]
+    fn errors_are_throttled_per_kind_with_coalesce_counts() {
+        #[derive(Hash)]
+        enum Kind {
+            Timeout,
+            Refused,
+        }
+
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        let log = |kind: Kind, detail: &str| {
+            log_hz_coalesce_errors!(kind, 1.0, Level::Error, "request failed: {}", detail);
+        };
+        with_clock(clock.clone(), || {
+            log(Kind::Timeout, "timeout after 1ms");
+            log(Kind::Refused, "refused by 10.0.0.1");
+            for detail in ["timeout after 2ms", "timeout after 3ms", "timeout after 2ms"] {
+                clock.advance(std::time::Duration::from_millis(100));
+                log(Kind::Timeout, detail);
+            }
+            clock.advance(std::time::Duration::from_millis(700));
+            log(Kind::Timeout, "timeout after 4ms");
+            log(Kind::Refused, "refused by 10.0.0.1");
+        });
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(
+                bodies,
+                [
+                    "request failed: timeout after 1ms",
+                    "request failed: refused by 10.0.0.1",
+                    "request failed: timeout after 4ms (coalesced 3 distinct messages)",
+                    "request failed: refused by 10.0.0.1",
+                ]
+            );
+        });
+    }
+
+    #[track_caller]
+    fn helper() {
+        log_hz_caller!(Level::Info, 1.0, "from helper");
+    }
+
+    #[test]
+    fn each_caller_of_a_helper_is_throttled_independently() {
+        testing_logger::setup();
+        for _ in 0..3 {
+            helper();
+            helper();
+        }
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 2));
+    }
+
+    #[test]
+    fn merged_sites_share_one_throttle() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        let key = String::from("test-merged");
+        with_clock(clock.clone(), || {
+            // Two sites with the same key, called every 100ms for 3 seconds
+            for _ in 0..30 {
+                log_hz_merge!(key, 1.0, Level::Warn, "first site");
+                log_hz_merge!("test-merged", 1.0, Level::Warn, "second site");
+                log_hz_merge!("test-other", 1.0, Level::Warn, "other key");
+                clock.advance(std::time::Duration::from_millis(100));
+            }
+        });
+        testing_logger::validate(|captured_logs| {
+            let merged = captured_logs.iter().filter(|log| log.body != "other key").count();
+            assert_eq!((merged, captured_logs.len() - merged), (3, 3));
+        });
+    }
+
+    #[test]
+    fn more_severe_messages_win_a_shared_interval() {
+        testing_logger::setup();
+        static CONTEXT: OrderedThrottle = OrderedThrottle::new();
+        let clock = ManualClock::default();
+        let storm = |levels: [Level; 3]| {
+            // Every site fires every 10ms for a second
+            for _ in 0..100 {
+                for level in levels {
+                    log_hz_ordered!(CONTEXT, 1.0, level, "{level}");
+                }
+                clock.advance(std::time::Duration::from_millis(10));
+            }
+        };
+        with_clock(clock.clone(), || {
+            storm([Level::Error, Level::Warn, Level::Info]);
+            storm([Level::Info, Level::Warn, Level::Error]);
+        });
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["ERROR", "INFO", "WARN", "ERROR"]);
+        });
+    }
+}
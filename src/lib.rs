@@ -31,8 +31,10 @@
 //!
 //! This crate provides the following throttled logging macros, matching their equivalents from the `log` crate:
 //! [error_hz!], [warn_hz!], [info_hz!], [debug_hz!], and [trace_hz!].
+//! [log_hz_try!] throttles the error of a `Result` in place, passing the `Result` on for `?`.
 //!
-//! The rate is specified in Hz, and can be any expression that can be compile time cast to a `f32` with `as f32`.
+//! The rate is specified in Hz, and can be any expression of a primitive numeric type (see [Rate]).
+//! Integer rates are converted to an interval with integer math, so `info_hz!(3, ...)` logs exactly every 333_333_333ns.
 //!
 //! ```rust
 //! use log_hz::*;
@@ -42,7 +44,7 @@
 //!   let rate = 1.0;
 //!   // info_hz!(rate, "Hello, world!"); // This won't compile
 //!
-//!   // Instead, use a literal or something that can be compile time cast to a f32
+//!   // Instead, use a literal or another constant expression of a numeric type
 //!   info_hz!(1.0, "Hello, world!");
 //!   info_hz!(1.0f32, "Hello, world!");
 //!   info_hz!(1, "Hello, world!");
@@ -52,14 +54,129 @@
 //!
 //!   static RATE: f32 = 1.0;
 //!   info_hz!(RATE, "Hello, world!");
+//!
+//!   // Rates that change at runtime need the dynamic variant, which recomputes the interval when the rate changes:
+//!   log_hz_dyn!(Level::Info, rate, "Hello, world!");
 //! }
 //! ```
 //!
+//! [log_hz_ms!] and [log_hz_us!] take a period in whole milliseconds or microseconds instead of a rate, for exact
+//! intervals without a float reciprocal. Code that computes an interval on every iteration can pass it as a `Duration`
+//! to [log_every_dyn!].
+//!
+//! When the rate is a constant, [log_hz_const!] carries the interval in the site's type instead of a lazily initialized static.
+//!
+//! To tune one site without recompiling, [log_hz_rate_from_env!] reads its rate from an environment variable.
+//! [log_hz_with_rate_fn!] derives each site's rate from its level with one policy function.
+//! [log_hz_banded!] picks the rate from the band a value is in, e.g. logging more often as a battery runs low.
+//!
+//! In fixed rate loops, [log_hz_of_loop!] expresses the rate as "once every N iterations" of the loop's rate.
+//! For loops of unknown rate, [log_hz_every_nth_or_hz!] logs every Nth call but never faster than a maximum rate.
+//! [log_hz_ratio!] ignores time altogether and deterministically logs one in every N calls, optionally never closer
+//! together than a minimum gap.
+//! [log_hz_seq!] logs the messages of a numbered stream whose sequence number is a multiple of K.
+//!
+//! With the `wall-clock` feature, [log_hz_on_nth_second!] logs on wall-clock seconds that are a multiple of N instead,
+//! e.g. on :00, :15, :30 and :45 of every minute.
+//!
+//! [log_hz_coalesce_errors!] throttles errors per root cause (e.g. an error kind) rather than per message, and says
+//! how many distinct messages each emission stands in for.
+//!
+//! [log_hz_merge!] shares one throttle between every call site logging with the same merge key, so a message repeated
+//! across sites is limited to one rate in total.
+//! [log_hz_ordered!] shares an interval between sites, preferring the most severe level when they fire together.
+//!
+//! To throttle each instance of a type on its own, embed a [ThrottleState] in it and log with [log_hz_on!].
+//! [force_log_hz!] logs through the same state on demand, and throttling resumes from the forced message.
+//!
+//! In `Drop` impls and other code that may run while unwinding, [log_hz_panic_guard!] throttles with atomics only and
+//! contains panics from formatting the message.
+//!
+//! Before a logger is installed, `log` drops every message. [log_hz_stderr_fallback!] writes its throttled messages to
+//! stderr until then, so diagnostics from early startup aren't lost.
+//!
+//! To find out why a site isn't logging as expected, [log_hz_probe!] returns the timing behind each call's decision.
+//!
+//! To keep a fleet of identical processes from logging in lockstep, [log_hz_jittered!] shifts each site's messages by a
+//! random fraction of the interval.
+//!
+//! For numeric telemetry, [log_hz_accumulate!] reports the count, average, minimum and maximum of the values seen
+//! between messages instead of a single sample.
+//! [log_hz_histogram!] reports their p50, p95 and p99 from a lock-free histogram instead.
+//!
+//! For events where only the rate matters, [count_only_hz!] logs how many calls were made in each interval.
+//!
+//! When many distinct messages arrive in bursts, [log_hz_batched!] logs one summary of the items seen since the last
+//! message instead.
+//!
+//! For conditions that flap, [log_hz_edge!] logs the first call after an idle gap immediately and throttles the rest.
+//!
+//! For error storms, [log_hz_smart!] logs the first few occurrences immediately, then throttles and reports how many
+//! messages were suppressed in between.
+//!
+//! [log_hz_escalate!] raises a site's level, e.g. from `Info` to `Warn`, when enough calls were suppressed between
+//! messages to indicate a storm.
+//!
+//! Context that is expensive to gather can be computed only for messages that are emitted with [log_hz_with_context!].
+//!
+//! To avoid repeating the exact same line while still letting different messages from one call site through,
+//! [log_hz_dedup_window!] suppresses a formatted message if it was already emitted within a time window.
+//! [log_hz_dedup_numeric!] only logs a number once it moved more than a tolerance from the last value logged.
+//! [log_once_per_args!] logs each distinct formatted message only once, e.g. once per sensor id.
+//!
+//! When a helper function wraps the logging call, [log_hz_caller!] throttles each location calling the helper separately.
+//!
+//! To reset or inspect a specific call site from elsewhere, its `handle:` form (e.g. `info_hz!(handle: 1.0, ...)`)
+//! logs as usual and returns a `Copy` [ThrottleHandle] to that site's throttle. [log_hz_stats!] logs its counters.
+//!
+//! Library authors who want one configurable type can use a [Throttler], whose [Strategy] (a rate, every Nth call, a
+//! token bucket or a fixed window) can be changed at runtime and is checked with [Throttler::check].
+//! The [Throttle] trait lets code be generic over a `Throttler`, a [ThrottleState] or a [ConstThrottleState].
+//!
+//! For logging from inside iterator chains, [ThrottleIterExt::log_every_hz] throttles a message built from each item
+//! while passing the items through unchanged. With the `futures` feature, [ThrottleStreamExt] does the same for async streams.
+//!
+//! With the `registry` feature, call sites register themselves the first time they run, so their throttle timers can
+//! be saved and restored together with [snapshot_throttle_state] and [restore_throttle_state], e.g. around a fork or checkpoint.
+//! [site_stats] returns how many messages each site emitted and suppressed, and [throttle_handle!] placed after a call
+//! returns the handle of that site.
+//! Sites logged with [log_hz_group!] can be reset together with [reset_group], and [group_stats] sums their counts.
+//! [boost_site] temporarily raises one site's rate, e.g. during an incident. [report_suppression] (or a thread started
+//! with [start_suppression_reporter], which logs a final report when stopped) logs how much each site emitted and
+//! suppressed, as JSON with the `json-reports` feature. [flush_suppressed] reports how many messages each site is
+//! still holding back, and [install_panic_flush_hook] does so when the program panics.
+//!
+//! [set_global_level_cap] adds a process-wide limit per level on top of the per-site throttles, e.g. at most 10
+//! throttled errors per second from all sites combined. [set_max_hz] instead sets the fastest rate any one site may log
+//! at. [set_backpressure_signal] holds messages back while the logger reports it is overloaded. [set_phase_spreading]
+//! staggers sites that would otherwise all log on the same tick.
+//!
+//! The `strict-rate-ceiling` feature enforces a maximum rate at compile time, for code that must bound its logging
+//! overhead: a literal rate above `STRICT_RATE_CEILING_HZ` (1000 by default, set with the `LOG_HZ_RATE_CEILING`
+//! environment variable at build time) is a compile error, and rates only known at runtime are clamped to it.
+//!
+//! Programs that only ever log from one thread, such as many embedded control loops, can enable the `single-threaded`
+//! feature to claim a site's slot with a plain atomic store instead of a compare-and-swap, taking the read-modify-write
+//! off the emit path. Enabling it in a multi-threaded program is still free of undefined behavior, but threads racing
+//! for the same slot may each emit a message, so a site can log more than once per interval.
+//!
+//! With the `link-section-metadata` feature, every `log_hz!` and `log_hz_dyn!` call site (including the level
+//! shorthands like `info_hz!`) is also recorded in [THROTTLE_SITES], a `linkme` distributed slice in the binary's
+//! link section, so tools can list the throttled sites and their rates before any of them has run.
+//!
+//! Messages are still emitted through `log`, but with the `tracing` feature a site called within a `tracing` span
+//! throttles separately per span, e.g. per request. Add [SpanThrottleLayer] to the subscriber to clean up after spans.
+//! [log_hz_trace_span!] opens a span itself at a throttled rate, for instrumenting only some runs of a section.
+//!
+//! Every throttled message that is emitted can also be forwarded to a second logger with [set_secondary_sink],
+//! e.g. to keep recent throttled activity in memory for a debug UI.
+//!
 //! It also re-exports all of the `log` crate's macros and functions, so you can use them as you normally would without needing to import it separately:
 //!
 //! ```rust
 //! // Cargo.toml only contains `log_hz = "0.1.0"`, no need to import `log` separately
 //! use log_hz::*;
+//! # #[cfg(feature = "no-log-reexport")] use log::error;
 //!
 //! fn main() {
 //!   // This comes from the log crate
@@ -69,6 +186,11 @@
 //! }
 //! ```
 //!
+//! To keep `log`'s items out of your namespace, import the curated [prelude] instead: `use log_hz::prelude::*`. Or
+//! enable the `no-log-reexport` feature, which removes the re-export so the crate root only holds log_hz's own items
+//! and [Level], and import from `log` explicitly. As Cargo features are unified, this applies to every crate in the
+//! build using log_hz, so it's meant for a binary's own dependency rather than a library's.
+//!
 //! This crate is compatible with all the amazing loggers that the `log` crate is compatible with. An abbreviated list include:
 //! - [env_logger](https://crates.io/crates/env_logger) - Extremely common logger for getting started with Enviroment variable configuration using `RUST_LOG`.
 //! - [simple_logger](https://crates.io/crates/simple_logger) - Good for simple logging needs, manually configured in code.
@@ -82,37 +204,132 @@
 //!
 //! Note: This crate uses `std::time::Instant` to track time, which is not available in `no_std` environments.
 //! If you're interested in alternative timing backends for this crate, feel free to open an issue or PR to add them behind features.
+//!
+//! The crate contains no unsafe code in any configuration (it is `#![forbid(unsafe_code)]`) and its macros expand to
+//! safe code, so they can be used from crates that forbid unsafe. The default `std::time::Instant` clock adds no
+//! dependencies, while the `coarsetime` and `minstant` backends read their clocks through unsafe code inside those crates.
+//! The one exception is the `link-section-metadata` feature: each call site then declares a static in a custom link
+//! section, which the `unsafe_code` lint flags. The macros allow it on that static, which works under
+//! `#![deny(unsafe_code)]` but not `#![forbid(unsafe_code)]`.
+
+#![cfg_attr(not(feature = "link-section-metadata"), forbid(unsafe_code))]
+#![cfg_attr(feature = "link-section-metadata", deny(unsafe_code))]
 
+#[cfg(not(feature = "no-log-reexport"))]
 pub use log::*;
+#[cfg(feature = "no-log-reexport")]
+pub use log::Level;
+
+mod accumulate;
+mod checked;
+mod clock;
+mod dedup;
+mod global;
+mod handle;
+mod iter;
+mod keyed;
+#[cfg(feature = "link-section-metadata")]
+mod metadata;
+pub mod prelude;
+#[cfg(feature = "registry")]
+mod registry;
+mod sink;
+#[cfg(feature = "tracing")]
+mod span;
+mod state;
+#[cfg(feature = "futures")]
+mod stream;
+#[cfg(any(test, feature = "testing"))]
+mod testing;
+mod throttler;
+mod trace;
+mod variants;
+#[cfg(feature = "wall-clock")]
+mod wall;
+pub use accumulate::*;
+pub use checked::*;
+pub use clock::{init_best_clock, process_start};
+pub use dedup::*;
+pub use global::*;
+pub use handle::*;
+pub use iter::*;
+pub use keyed::*;
+#[cfg(feature = "link-section-metadata")]
+pub use metadata::*;
+#[cfg(feature = "registry")]
+pub use registry::*;
+pub use sink::*;
+#[cfg(feature = "tracing")]
+pub use span::*;
+pub use state::*;
+pub use variants::{ThrottleProbe, set_suppressed_format};
+#[cfg(feature = "futures")]
+pub use stream::*;
+#[cfg(any(test, feature = "testing"))]
+pub use testing::*;
+pub use throttler::*;
+pub use trace::*;
+#[cfg(feature = "wall-clock")]
+pub use wall::WallSecondGate;
 
 /// Log a message at [Level::Error] at a throttled rate, first call will always log.
 #[macro_export]
 macro_rules! error_hz {
-    ($rate:expr,$($arg:tt)+) => { $crate::log_hz!($crate::Level::Error, $rate, $($arg)+); }
+    (handle: target: $target:expr, $($arg:tt)+) => {
+        $crate::log_hz!(handle: target: $target, $crate::Level::Error, $($arg)+)
+    };
+    (handle: $($arg:tt)+) => { $crate::log_hz!(handle: $crate::Level::Error, $($arg)+) };
+    (target: $target:expr, $($arg:tt)+) => { $crate::log_hz!(target: $target, $crate::Level::Error, $($arg)+); };
+    // Forwarded as tokens so `log_hz!` can still tell a literal rate apart.
+    ($($arg:tt)+) => { $crate::log_hz!($crate::Level::Error, $($arg)+); };
 }
 
 /// Log a message at [Level::Warn] at a throttled rate, first call will always log.
 #[macro_export]
 macro_rules! warn_hz {
-    ($rate:expr,$($arg:tt)+) => { $crate::log_hz!($crate::Level::Warn, $rate, $($arg)+); }
+    (handle: target: $target:expr, $($arg:tt)+) => {
+        $crate::log_hz!(handle: target: $target, $crate::Level::Warn, $($arg)+)
+    };
+    (handle: $($arg:tt)+) => { $crate::log_hz!(handle: $crate::Level::Warn, $($arg)+) };
+    (target: $target:expr, $($arg:tt)+) => { $crate::log_hz!(target: $target, $crate::Level::Warn, $($arg)+); };
+    // Forwarded as tokens so `log_hz!` can still tell a literal rate apart.
+    ($($arg:tt)+) => { $crate::log_hz!($crate::Level::Warn, $($arg)+); };
 }
 
 /// Log a message at [Level::Info] at a throttled rate, first call will always log.
 #[macro_export]
 macro_rules! info_hz {
-    ($rate:expr,$($arg:tt)+) => { $crate::log_hz!($crate::Level::Info, $rate, $($arg)+); }
+    (handle: target: $target:expr, $($arg:tt)+) => {
+        $crate::log_hz!(handle: target: $target, $crate::Level::Info, $($arg)+)
+    };
+    (handle: $($arg:tt)+) => { $crate::log_hz!(handle: $crate::Level::Info, $($arg)+) };
+    (target: $target:expr, $($arg:tt)+) => { $crate::log_hz!(target: $target, $crate::Level::Info, $($arg)+); };
+    // Forwarded as tokens so `log_hz!` can still tell a literal rate apart.
+    ($($arg:tt)+) => { $crate::log_hz!($crate::Level::Info, $($arg)+); };
 }
 
 /// Log a message at [Level::Debug] at a throttled rate, first call will always log.
 #[macro_export]
 macro_rules! debug_hz {
-    ($rate:expr,$($arg:tt)+) => { $crate::log_hz!($crate::Level::Debug, $rate, $($arg)+); }
+    (handle: target: $target:expr, $($arg:tt)+) => {
+        $crate::log_hz!(handle: target: $target, $crate::Level::Debug, $($arg)+)
+    };
+    (handle: $($arg:tt)+) => { $crate::log_hz!(handle: $crate::Level::Debug, $($arg)+) };
+    (target: $target:expr, $($arg:tt)+) => { $crate::log_hz!(target: $target, $crate::Level::Debug, $($arg)+); };
+    // Forwarded as tokens so `log_hz!` can still tell a literal rate apart.
+    ($($arg:tt)+) => { $crate::log_hz!($crate::Level::Debug, $($arg)+); };
 }
 
 /// Log a message at [Level::Trace] at a throttled rate, first call will always log.
 #[macro_export]
 macro_rules! trace_hz {
-    ($rate:expr,$($arg:tt)+) => { $crate::log_hz!($crate::Level::Trace, $rate, $($arg)+); }
+    (handle: target: $target:expr, $($arg:tt)+) => {
+        $crate::log_hz!(handle: target: $target, $crate::Level::Trace, $($arg)+)
+    };
+    (handle: $($arg:tt)+) => { $crate::log_hz!(handle: $crate::Level::Trace, $($arg)+) };
+    (target: $target:expr, $($arg:tt)+) => { $crate::log_hz!(target: $target, $crate::Level::Trace, $($arg)+); };
+    // Forwarded as tokens so `log_hz!` can still tell a literal rate apart.
+    ($($arg:tt)+) => { $crate::log_hz!($crate::Level::Trace, $($arg)+); };
 }
 
 /// Log a message at the specified level at a throttled rate, first call will always log.
@@ -122,78 +339,480 @@ macro_rules! trace_hz {
 ///
 /// An optional `coarsetime` feature can be enabled to use a faster, but less precise, time source
 /// on platforms that support it (currently Linux with `CLOCK_MONOTONIC_COARSE`).
+/// No setup is needed: the macros read `coarsetime::Instant::now()`, which queries the OS clock directly,
+/// so coarsetime's `Updater` thread (only needed for `Instant::recent()`) doesn't have to be started.
+/// The coarse clock's resolution (typically 1-4ms on Linux) limits how precisely high rates are throttled.
+/// If the coarse clock is ever found not to advance for over a second while the precise clock does,
+/// log_hz falls back to `std::time::Instant` for the rest of the process, warning once in debug builds.
+///
+/// The `minstant` feature instead reads the CPU's timestamp counter through `minstant::Instant`, which is
+/// faster than `std::time::Instant` without giving up precision. Where the TSC isn't usable (non-x86 platforms,
+/// or a TSC that isn't stable across cores), `std::time::Instant` is used. `coarsetime` takes precedence if both
+/// features are enabled, unless [init_best_clock] is called at startup to pick the fastest one that works here.
+///
+/// The interval is computed from the rate once, on the first call, and cached in a static for the
+/// life of the process. Changing the rate requires recompiling, and state that outlives its code
+/// (e.g. a plugin or hot-reloaded library whose statics survive a reload) keeps the old interval.
+/// Use [log_hz_dyn!] when the rate needs to change at runtime. Debug builds record the interval each location
+/// computes and warn (through `log`) if the same location later computes a different one, which happens when a
+/// reloaded copy of the site starts alongside the old one. Only a copy that is initialized again is caught.
+///
+/// Calls at a level that is disabled, statically or by [max_level], return before reading the clock, so they
+/// don't use up the site's slot and a statically disabled call compiles down to nothing. With the `count-disabled`
+/// feature they are counted as suppressed instead, so [site_stats] (with the `registry` feature) still reports how
+/// often the site runs in its `attempts`. That puts an atomic increment back on the disabled path of every site.
+///
+/// With the `checked` feature, each site also watches how often it is called, and warns once (through `log`) if it is
+/// called no faster than its rate for a sustained period: at least 10 calls spanning 10 intervals without a single
+/// one being suppressed. Such a throttle only adds overhead, so the rate is likely mis-tuned. The check costs an extra
+/// atomic increment per call, so the feature is meant for development builds.
+///
+/// The first call always logs, but before a logger is set `log` hands messages to a no-op logger that drops them.
+/// Calls return early while the maximum level is `Off`, as it is until a logger raises it, but with a complex
+/// initialization order the level can be raised first. With the `wait-for-logger` feature, a site that hasn't emitted
+/// yet also asks the logger whether it would accept the message, counting the call as suppressed rather than using
+/// up its first message until one does. Once the site has emitted, this costs a single extra atomic load per call.
+///
+/// Like `log!`, the macros take a `target:` before the level (or before the rate for `info_hz!`, etc.), which
+/// defaults to the module path. A site with an explicit target asks the logger whether it accepts the target before
+/// checking the throttle, so messages the logger filters out by target (e.g. with `RUST_LOG`) don't use up the slot:
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// info_hz!(target: "app::net", 1.0, "Reconnecting");
+/// log_hz!(target: "app::net", Level::Warn, 1.0, "Connection lost");
+/// ```
+///
+/// To keep the throttle somewhere other than a site-local static, e.g. in a struct or an arena, pass a reference to a
+/// [ThrottleState] with `state:`, followed by the rate and then the level like [log_hz_on!]. The site has no static of
+/// its own, so it has no [ThrottleHandle] and doesn't register with the registry:
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// struct Connection {
+///     throttle: ThrottleState,
+/// }
+///
+/// let connection = Box::new(Connection { throttle: ThrottleState::new() });
+/// log_hz!(state: &connection.throttle, 1.0, Level::Warn, "Connection is slow");
+/// ```
+///
+/// With the `tracing` feature, a call made inside a `tracing` span is throttled on a timer of its own for that span,
+/// so e.g. one message per request isn't suppressed by other requests. Add [SpanThrottleLayer] to the subscriber to
+/// drop a span's timers when it closes. A level the subscriber filters out is treated like one disabled in `log`, so
+/// the call doesn't use up the site's slot.
+///
+/// With the `decision-trace` feature, each call that reads the clock also records whether it emitted in a global ring
+/// buffer, which [dump_decision_trace] returns for post-mortem debugging.
+///
+/// A call claims its site's slot before the message is formatted. If formatting the message calls back
+/// into the same site (e.g. a `Display` impl that logs through it), the outer call emits and the
+/// re-entrant call is suppressed, however high the rate.
+///
+/// With the `kv` feature, a message made up only of key-value fields can be logged without a format string,
+/// e.g. `info_hz!(1.0, temperature = 21.5, pressure = 101)`. Like the format arguments, the values are only
+/// evaluated when the message is actually emitted.
+///
+/// With the `site-fields` feature (which enables `kv`), every emitted record also carries its call site as `hz_file`
+/// and `hz_line` key-value fields, so a log aggregator can group throttled messages by origin. The fields are only
+/// added to messages that pass the throttle.
+///
+/// Starting the arguments with `handle:` makes the call evaluate to the site's [ThrottleHandle], to reset or inspect
+/// the site from elsewhere, e.g. an admin command:
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// let handle = log_hz!(handle: Level::Warn, 1.0, "Disk almost full");
+/// assert_eq!(handle.line(), line!() - 1);
+/// handle.reset();
+/// ```
+///
+/// The first call always logs by default. Passing `first: false` before the message makes the first call
+/// start the timer instead, so the first message is only emitted once a full interval has passed:
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// info_hz!(1.0, first: false, "Only logged once the loop has been running for a second");
+/// log_hz!(Level::Info, 1.0, first: false, "Same for the generic macro");
+/// ```
 #[macro_export]
 macro_rules! log_hz {
-    ($level:expr, $rate:expr, $($arg:tt)+) => {
+    (@site $group:expr, $first:expr, $target:expr, $level:expr, $rate:expr, $($arg:tt)+) => {
         // Inner scope to encapsulate static variables
         {
-            use std::sync::atomic::{AtomicU64, Ordering};
             use std::sync::LazyLock;
 
-            #[cfg(not(feature = "coarsetime"))]
-            use std::time::Instant;
-            #[cfg(feature = "coarsetime")]
-            use coarsetime::Instant;
-
-            // A shared, static start time for the process.
-            // Using LazyLock ensures it's initialized only once.
-            static START_TIME: LazyLock<Instant> = LazyLock::new(Instant::now);
-
             // The interval between log messages in nanoseconds.
             // Calculated once and cached. A rate of 0 or less disables logging.
             static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| {
-                let rate_f64 = $rate as f64;
-                if rate_f64 > 0.0 {
-                    (1.0 / rate_f64 * 1_000_000_000.0) as u64
+                let interval_ns = $crate::Rate::interval_ns($rate);
+                // Debug builds warn if this location was initialized before with another rate, e.g. before a reload.
+                #[cfg(debug_assertions)]
+                $crate::__private::check_site_interval(interval_ns, file!(), line!(), column!());
+                interval_ns
+            });
+
+            // The timestamp of the last log for this call site.
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::with_first($first);
+
+            // Lets `boost_site` override the rate for a while.
+            static BOOST: $crate::RateBoost = $crate::RateBoost::new();
+
+            // Returned by the `handle:` form so the site can be reset or inspected elsewhere.
+            static HANDLE: $crate::ThrottleHandle =
+                $crate::ThrottleHandle::new(&STATE, || *INTERVAL_NS, &BOOST, file!(), line!());
+            if $crate::__private::REGISTRY {
+                static REGISTERED: std::sync::Once = std::sync::Once::new();
+                REGISTERED.call_once(|| $crate::__private::register_site(&HANDLE, $level, $group));
+            }
+            $crate::__log_hz_site_metadata!($level, $rate);
+
+            // Debug builds catch sites of one group that disagree on its rate.
+            #[cfg(debug_assertions)]
+            {
+                static GROUP_CHECKED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+                if !GROUP_CHECKED.swap(true, std::sync::atomic::Ordering::Relaxed) {
+                    $crate::__private::check_group_rate($group, *INTERVAL_NS, file!(), line!());
+                }
+            }
+
+            let (level, target): ($crate::Level, Option<&str>) = ($level, $target);
+            if $crate::__private::level_enabled(level) && $crate::__private::target_enabled(level, target) {
+                let interval_ns = *INTERVAL_NS;
+                if interval_ns == u64::MAX && !BOOST.is_set() {
+                    // A disabled rate can never log, so don't read the clock.
+                    $crate::__private::count_suppressed(&STATE);
+                } else if $crate::__private::WAIT_FOR_LOGGER
+                    && !$crate::__private::first_call_ready(&STATE, level, target.unwrap_or(module_path!()))
+                {
+                    $crate::__private::count_suppressed(&STATE);
                 } else {
-                    u64::MAX
+                    let now_ns = $crate::__private::now_ns();
+                    let interval_ns = $crate::__private::limit_interval(BOOST.interval_ns(now_ns, interval_ns));
+                    let emitted = $crate::__private::site_allows(level, now_ns, interval_ns, |interval_ns| {
+                        $crate::__private::span_should_log(&STATE, now_ns, interval_ns)
+                    });
+                    if emitted {
+                        $crate::__private::spread_phase(&HANDLE, now_ns, interval_ns);
+                        $crate::__private::emit_once(&STATE, || {
+                            $crate::__log_hz_emit!(target: target.unwrap_or(module_path!()), level, $($arg)+)
+                        });
+                    }
+                    if $crate::__private::DECISION_TRACE {
+                        static TRACE: $crate::TraceSite = $crate::TraceSite::new();
+                        TRACE.record(&HANDLE, now_ns, emitted);
+                    }
+                    if $crate::__private::CHECKED {
+                        static STARVATION: $crate::StarvationCheck = $crate::StarvationCheck::new();
+                        STARVATION.observe(&HANDLE, now_ns, interval_ns);
+                    }
                 }
-            });
+            } else if $crate::__private::COUNT_DISABLED {
+                $crate::__private::count_suppressed(&STATE);
+            }
+            HANDLE
+        }
+    };
+    (@args $target:expr, $level:expr, $rate:expr, first: $first:expr, $($key:ident = $value:expr),+ $(,)?) => {
+        $crate::log_hz!(@site None, $first, $target, $level, $rate, $($key = $value),+; "")
+    };
+    (@args $target:expr, $level:expr, $rate:expr, first: $first:expr, $($arg:tt)+) => {
+        $crate::log_hz!(@site None, $first, $target, $level, $rate, $($arg)+)
+    };
+    (@args $target:expr, $level:expr, $rate:expr, $($key:ident = $value:expr),+ $(,)?) => {
+        $crate::log_hz!(@site None, true, $target, $level, $rate, $($key = $value),+; "")
+    };
+    (@args $target:expr, $level:expr, $rate:expr, $($arg:tt)+) => {
+        $crate::log_hz!(@site None, true, $target, $level, $rate, $($arg)+)
+    };
+    (@state $state:expr, $interval_ns:expr, $level:expr, $($arg:tt)+) => {
+        {
+            let state: &$crate::ThrottleState = $state;
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level) {
+                let now_ns = $crate::__private::now_ns();
+                if $crate::__private::site_allows(level, now_ns, $interval_ns, |interval_ns| {
+                    state.should_log(now_ns, interval_ns)
+                }) {
+                    $crate::__private::emit_once(state, || $crate::__log_hz_emit!(level, $($arg)+));
+                }
+            }
+        }
+    };
+    // The interval of a literal rate is computed at compile time.
+    (state: $state:expr, $rate:literal, $level:expr, $($arg:tt)+) => {
+        {
+            $crate::__log_hz_rate_ceiling!($rate);
+            const INTERVAL_NS: u64 = $crate::interval_from_rate($rate as f64);
+            $crate::log_hz!(@state $state, INTERVAL_NS, $level, $($arg)+)
+        }
+    };
+    (state: $state:expr, $rate:expr, $level:expr, $($arg:tt)+) => {
+        $crate::log_hz!(@state $state, $crate::Rate::interval_ns($rate), $level, $($arg)+)
+    };
+    // A literal rate can be checked against the `strict-rate-ceiling` feature's ceiling at compile time.
+    (@entry $target:expr, $level:expr, $rate:literal, $($arg:tt)+) => {
+        {
+            $crate::__log_hz_rate_ceiling!($rate);
+            $crate::log_hz!(@args $target, $level, $rate, $($arg)+)
+        }
+    };
+    (@entry $target:expr, $level:expr, $rate:expr, $($arg:tt)+) => {
+        $crate::log_hz!(@args $target, $level, $rate, $($arg)+)
+    };
+    (handle: target: $target:expr, $($arg:tt)+) => {
+        $crate::log_hz!(@entry Some($target), $($arg)+)
+    };
+    (handle: $($arg:tt)+) => {
+        $crate::log_hz!(@entry None, $($arg)+)
+    };
+    (target: $target:expr, $($arg:tt)+) => {
+        {
+            $crate::log_hz!(@entry Some($target), $($arg)+);
+        }
+    };
+    ($($arg:tt)+) => {
+        {
+            $crate::log_hz!(@entry None, $($arg)+);
+        }
+    };
+}
+
+/// Log a message at the specified level at a throttled rate that is evaluated on every call.
+///
+/// Unlike [log_hz!], the rate can be any runtime expression. The interval is recomputed whenever
+/// the rate differs from the one seen on the previous call, so changes take effect immediately.
+/// Like [log_hz!], starting with `handle:` returns the site's [ThrottleHandle].
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// let mut rate = 1.0;
+/// for _ in 0..10 {
+///     log_hz_dyn!(Level::Info, rate, "Hello, world!");
+///     rate *= 2.0;
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_hz_dyn {
+    (handle: $level:expr, $rate:expr, $($key:ident = $value:expr),+ $(,)?) => {
+        $crate::log_hz_dyn!(@site $level, $rate, $($key = $value),+; "")
+    };
+    (handle: $level:expr, $rate:expr, $($arg:tt)+) => {
+        $crate::log_hz_dyn!(@site $level, $rate, $($arg)+)
+    };
+    (@site $level:expr, $rate:expr, $($arg:tt)+) => {
+        {
+            static INTERVAL: $crate::DynamicInterval = $crate::DynamicInterval::new();
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+            static BOOST: $crate::RateBoost = $crate::RateBoost::new();
+            static HANDLE: $crate::ThrottleHandle =
+                $crate::ThrottleHandle::new(&STATE, || INTERVAL.cached(), &BOOST, file!(), line!());
+            if $crate::__private::REGISTRY {
+                static REGISTERED: std::sync::Once = std::sync::Once::new();
+                REGISTERED.call_once(|| $crate::__private::register_site(&HANDLE, $level, None));
+            }
+            $crate::__log_hz_site_metadata!($level, $rate);
 
-            // The timestamp of the last log, stored as nanoseconds since START_TIME.
-            // Initialized to 0, which ensures the first log message always gets through.
-            static LAST_LOG_NS: AtomicU64 = AtomicU64::new(0);
-
-            // --- Fast Path ---
-            // This is the most common path, executed on every call to the macro.
-            // It's designed to be as cheap as possible.
-
-            // First, perform a quick, optimistic check to see if we should log.
-            // We use `Relaxed` ordering because it's the cheapest, and we're not
-            // yet synchronizing memory. We just want to bail out early if possible.
-            let last_ns = LAST_LOG_NS.load(Ordering::Relaxed);
-            let now = Instant::now();
-            let elapsed_ns = now.duration_since(*START_TIME).as_nanos() as u64;
-
-            // Check if enough time has passed since the last log.
-            // `saturating_sub` prevents a panic in the rare case of time moving backward.
-            if elapsed_ns.saturating_sub(last_ns) >= *INTERVAL_NS {
-                // --- Slow Path ---
-                // We might get to log. Now we need to ensure only one thread does.
-                // We use a `compare_exchange` to atomically update the timestamp.
-                // This operation attempts to replace `last_ns` with `elapsed_ns` only if
-                // the current value is still `last_ns`.
-                //
-                // Ordering::AcqRel (Acquire-Release):
-                //   - If successful, this creates a memory barrier that ensures:
-                //     1. (Acquire) Any writes from other threads that happened before are visible now.
-                //     2. (Release) The log message we are about to write will be visible to
-                //        other threads that later access this atomic variable.
-                // Ordering::Relaxed (on failure):
-                //   - If we fail, it means another thread won the race. We don't need to
-                //     synchronize memory, so we use the cheapest ordering.
-                if LAST_LOG_NS.compare_exchange(last_ns, elapsed_ns, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
-                    // We successfully updated the timestamp, so we have the "right" to log.
-                    $crate::log!($level, $($arg)+);
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level) {
+                let interval_ns = INTERVAL.interval_for($rate);
+                if interval_ns == u64::MAX && !BOOST.is_set() {
+                    // A disabled rate can never log, so don't read the clock.
+                    $crate::__private::count_suppressed(&STATE);
+                } else if $crate::__private::WAIT_FOR_LOGGER
+                    && !$crate::__private::first_call_ready(&STATE, level, module_path!())
+                {
+                    $crate::__private::count_suppressed(&STATE);
+                } else {
+                    let now_ns = $crate::__private::now_ns();
+                    let interval_ns = $crate::__private::limit_interval(BOOST.interval_ns(now_ns, interval_ns));
+                    let emitted = $crate::__private::site_allows(level, now_ns, interval_ns, |interval_ns| {
+                        STATE.should_log(now_ns, interval_ns)
+                    });
+                    if emitted {
+                        $crate::__private::spread_phase(&HANDLE, now_ns, interval_ns);
+                        $crate::__private::emit_once(&STATE, || $crate::__log_hz_emit!(level, $($arg)+));
+                    }
+                    if $crate::__private::DECISION_TRACE {
+                        static TRACE: $crate::TraceSite = $crate::TraceSite::new();
+                        TRACE.record(&HANDLE, now_ns, emitted);
+                    }
+                    if $crate::__private::CHECKED {
+                        static STARVATION: $crate::StarvationCheck = $crate::StarvationCheck::new();
+                        STARVATION.observe(&HANDLE, now_ns, interval_ns);
+                    }
                 }
-                // If the `compare_exchange` failed, another thread logged in the tiny
-                // window between our `load` and `compare_exchange`. We simply do nothing,
-                // which correctly throttles the message.
+            } else if $crate::__private::COUNT_DISABLED {
+                $crate::__private::count_suppressed(&STATE);
             }
+            HANDLE
+        }
+    };
+    ($level:expr, $rate:expr, $($key:ident = $value:expr),+ $(,)?) => {
+        {
+            $crate::log_hz_dyn!(@site $level, $rate, $($key = $value),+; "");
+        }
+    };
+    ($level:expr, $rate:expr, $($arg:tt)+) => {
+        {
+            $crate::log_hz_dyn!(@site $level, $rate, $($arg)+);
         }
     };
 }
 
+/// Emit a message that passed the throttle, routing it through [ThrottledLogger] so the secondary sink sees it too.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_hz_emit {
+    (target: $target:expr, $level:expr, $($arg:tt)+) => {
+        $crate::__private::log!(logger: $crate::ThrottledLogger, target: $target, $level, $($arg)+)
+    };
+    ($level:expr, $($arg:tt)+) => {
+        $crate::__private::log!(logger: $crate::ThrottledLogger, $level, $($arg)+)
+    };
+}
+
+/// Record a call site in [THROTTLE_SITES] with the `link-section-metadata` feature. The feature is checked here, as a
+/// `cfg` in the calling macro would see the calling crate's features.
+#[cfg(feature = "link-section-metadata")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_hz_site_metadata {
+    ($level:expr, $rate:expr) => {
+        #[$crate::__private::linkme::distributed_slice($crate::THROTTLE_SITES)]
+        #[linkme(crate = $crate::__private::linkme)]
+        #[allow(unsafe_code)]
+        static METADATA: $crate::SiteMetadata = $crate::SiteMetadata {
+            file: file!(),
+            line: line!(),
+            level: $crate::__private::level_name(stringify!($level)),
+            rate: stringify!($rate),
+        };
+    };
+}
+
+/// Fail compilation if a constant rate exceeds [STRICT_RATE_CEILING_HZ] with the `strict-rate-ceiling` feature. The
+/// feature is checked here, as a `cfg` in the calling macro would see the calling crate's features.
+#[cfg(feature = "strict-rate-ceiling")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_hz_rate_ceiling {
+    ($rate:expr) => {
+        const _: () = assert!(
+            $rate as f64 <= $crate::STRICT_RATE_CEILING_HZ as f64,
+            concat!("log_hz: the rate ", stringify!($rate), " exceeds the strict rate ceiling"),
+        );
+    };
+}
+
+/// Without the `strict-rate-ceiling` feature any rate is allowed.
+#[cfg(not(feature = "strict-rate-ceiling"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_hz_rate_ceiling {
+    ($rate:expr) => {};
+}
+
+/// Without the `link-section-metadata` feature call sites leave no record.
+#[cfg(not(feature = "link-section-metadata"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_hz_site_metadata {
+    ($level:expr, $rate:expr) => {};
+}
+
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::accumulate::StatArg;
+    pub use crate::clock::now_ns;
+    pub use crate::global::{limit_interval, site_allows, site_allows_without_backpressure, spread_phase};
+    pub use crate::handle::stats_line;
+    pub use crate::state::check_site_interval;
+    pub use crate::keyed::merged_should_log;
+    pub use crate::sink::{
+        emit_guarded, emit_once, first_call_ready, logger_installed, target_enabled, write_to_stderr,
+    };
+    pub use crate::variants::{band_rate, check_group_rate, jitter_offset, rate_from_env, suppressed_suffix};
+    #[cfg(feature = "wall-clock")]
+    pub use crate::wall::wall_secs;
+    #[cfg(feature = "tracing")]
+    pub use crate::span::{span_should_log, tracing_enabled, tracing_level};
+    #[cfg(feature = "tracing")]
+    pub use tracing;
+    #[cfg(feature = "registry")]
+    pub use crate::registry::{adjacent_handle, register_site};
+    #[cfg(feature = "link-section-metadata")]
+    pub use crate::metadata::level_name;
+    #[cfg(feature = "link-section-metadata")]
+    pub use linkme;
+    // Through here rather than the crate root, which doesn't re-export `log` with the `no-log-reexport` feature.
+    pub use log::{STATIC_MAX_LEVEL, log, max_level};
+
+    /// Whether call sites should register themselves, i.e. whether the `registry` feature is enabled.
+    ///
+    /// Checked in the macros rather than with `cfg`, which would see the calling crate's features.
+    pub const REGISTRY: bool = cfg!(feature = "registry");
+
+    /// Whether calls at a disabled level are counted, i.e. whether the `count-disabled` feature is enabled.
+    pub const COUNT_DISABLED: bool = cfg!(feature = "count-disabled");
+
+    /// Whether sites watch for a throttle that never suppresses anything, i.e. whether the `checked` feature is enabled.
+    pub const CHECKED: bool = cfg!(feature = "checked");
+
+    /// Whether a site's first call waits for a logger that accepts it, i.e. whether the `wait-for-logger` feature is
+    /// enabled.
+    pub const WAIT_FOR_LOGGER: bool = cfg!(feature = "wait-for-logger");
+
+    /// Whether sites record their decisions in the trace, i.e. whether the `decision-trace` feature is enabled.
+    pub const DECISION_TRACE: bool = cfg!(feature = "decision-trace");
+
+    /// Whether `level` passes both the static and the runtime maximum level, like the check in `log!`, and with the
+    /// `tracing` feature the level filter of the current `tracing` subscriber.
+    #[inline(always)]
+    pub fn level_enabled(level: crate::Level) -> bool {
+        level <= STATIC_MAX_LEVEL && level <= max_level() && tracing_enabled(level)
+    }
+
+    /// Without the `tracing` feature only the `log` levels apply.
+    #[cfg(not(feature = "tracing"))]
+    #[inline(always)]
+    pub fn tracing_enabled(_level: crate::Level) -> bool {
+        true
+    }
+
+    /// Count a call that can't log without reading the clock, e.g. at a disabled level (see [COUNT_DISABLED]).
+    pub fn count_suppressed(state: &crate::ThrottleState) {
+        state.count_suppressed();
+    }
+
+    /// Give back the suppressed count taken by a site whose emission the process-wide limits then held back.
+    pub fn restore_suppressed(state: &crate::ThrottleState, count: u64) {
+        state.restore_suppressed(count);
+    }
+
+    /// Without the `registry` feature there is nothing to register with.
+    #[cfg(not(feature = "registry"))]
+    #[inline(always)]
+    pub fn register_site(_handle: &'static crate::ThrottleHandle, _level: crate::Level, _group: Option<&'static str>) {}
+
+    /// Without the `tracing` feature there are no spans, so the site's own timer is used.
+    #[cfg(not(feature = "tracing"))]
+    #[inline(always)]
+    pub fn span_should_log(site: &crate::ThrottleState, now_ns: u64, interval_ns: u64) -> bool {
+        site.should_log(now_ns, interval_ns)
+    }
+
+    /// Start a timer that never logged at `last_ns`, unless another call beat this one to it.
+    pub fn seed_last_log(state: &crate::ThrottleState, last_ns: u64) {
+        state.seed_last_log(last_ns);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -243,4 +862,213 @@ mod tests {
     fn integer_literals_acceptable_for_rate() {
         info_hz!(1, "Hello, world!");
     }
+
+    #[test]
+    fn first_call_logs_by_default() {
+        testing_logger::setup();
+        for _ in 0..10 {
+            info_hz!(1.0, first: true, "Hello, world!");
+        }
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 1));
+    }
+
+    #[test]
+    fn first_call_can_wait_a_full_interval() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            for i in 0..10 {
+                if i == 9 {
+                    clock.advance(std::time::Duration::from_millis(100));
+                }
+                info_hz!(10.0, first: false, "Hello, world!");
+            }
+        });
+        testing_logger::validate(|captured_logs| {
+            // Only the call after a full interval logs
+            assert_eq!(captured_logs.len(), 1);
+        });
+    }
+
+    // The strict rate ceiling would clamp the 1 THz rate below to 1 kHz.
+    #[cfg(not(feature = "strict-rate-ceiling"))]
+    struct Reentrant<'a> {
+        rate: f64,
+        depth: &'a std::cell::Cell<u32>,
+    }
+
+    #[cfg(not(feature = "strict-rate-ceiling"))]
+    impl std::fmt::Display for Reentrant<'_> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            self.depth.set(self.depth.get() + 1);
+            // Bail out rather than overflow the stack if re-entrancy isn't handled
+            if self.depth.get() < 10 {
+                log_reentrant(self);
+            }
+            write!(f, "reentrant")
+        }
+    }
+
+    #[cfg(not(feature = "strict-rate-ceiling"))]
+    fn log_reentrant(value: &Reentrant) {
+        log_hz_dyn!(Level::Info, value.rate, "{}", value);
+    }
+
+    #[cfg(not(feature = "strict-rate-ceiling"))]
+    #[test]
+    fn reentrant_call_is_throttled() {
+        testing_logger::setup();
+        for rate in [1.0, 1e12] {
+            let depth = std::cell::Cell::new(0);
+            log_reentrant(&Reentrant { rate, depth: &depth });
+            // Formatted once, the inner call didn't emit (and so didn't format again)
+            assert_eq!(depth.get(), 1);
+        }
+        testing_logger::validate(|captured_logs| {
+            // 1 Hz logs the first call, and the rate change to 1 THz logs immediately as well
+            assert_eq!(captured_logs.len(), 2);
+        });
+    }
+
+    struct PanickingClock;
+
+    impl Clock for PanickingClock {
+        fn now(&self) -> std::time::Instant {
+            panic!("a disabled site read the clock");
+        }
+    }
+
+    #[test]
+    fn disabled_rate_never_reads_the_clock() {
+        testing_logger::setup();
+        with_clock(PanickingClock, || {
+            for _ in 0..3 {
+                info_hz!(0.0, "disabled");
+                log_hz_dyn!(Level::Info, -1.0, "disabled");
+            }
+        });
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 0));
+    }
+
+    #[test]
+    fn long_stall_does_not_catch_up() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        let tick = || {
+            info_hz!(10.0, "tick");
+        };
+        with_clock(clock.clone(), || {
+            tick();
+            // A stall of a thousand intervals, e.g. in a debugger
+            clock.advance(std::time::Duration::from_secs(100));
+            for _ in 0..10 {
+                tick();
+                clock.advance(std::time::Duration::from_millis(10));
+            }
+        });
+        // The interval restarts from the first call after the stall instead of emitting the missed ones
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 2));
+    }
+
+    /// Messages logged by `log` called every millisecond for 10 simulated seconds.
+    fn emissions_over_ten_seconds(log: impl Fn()) -> usize {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            for _ in 0..10_000 {
+                log();
+                clock.advance(std::time::Duration::from_millis(1));
+            }
+        });
+        let emitted = std::cell::Cell::new(0);
+        testing_logger::validate(|captured_logs| emitted.set(captured_logs.len()));
+        emitted.get()
+    }
+
+    #[test]
+    fn sustained_rate_is_accurate() {
+        let cases: [(f64, &dyn Fn()); 5] = [
+            (1.0, &|| log_hz!(Level::Info, 1.0, "1Hz")),
+            (3.0, &|| log_hz!(Level::Info, 3.0, "3Hz")),
+            (7.0, &|| log_hz!(Level::Info, 7.0, "7Hz")),
+            (10.0, &|| log_hz!(Level::Info, 10.0, "10Hz")),
+            (100.0, &|| log_hz!(Level::Info, 100.0, "100Hz")),
+        ];
+        for (rate, log) in cases {
+            let emitted = emissions_over_ten_seconds(log);
+            let expected = rate * 10.0;
+            assert!((emitted as f64 - expected).abs() <= 1.0, "{rate}Hz emitted {emitted} times, expected {expected}");
+        }
+    }
+
+    #[test]
+    fn integer_rate_keeps_exact_cadence() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        let log = || info_hz!(handle: 3, "three times a second");
+        with_clock(clock.clone(), || {
+            let handle = log();
+            let until_next = handle.time_until_next();
+            assert!(until_next >= std::time::Duration::from_nanos(333_333_333));
+            assert!(until_next <= std::time::Duration::from_nanos(333_333_334));
+            log();
+            clock.advance(until_next);
+            log();
+        });
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 2));
+    }
+
+    #[test]
+    fn dynamic_rate_change_is_picked_up() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        let log = |rate: f64| with_clock(clock.clone(), || log_hz_dyn!(Level::Info, rate, "Hello, world!"));
+        // Disabled rate never logs, not even the first call
+        for _ in 0..10 {
+            log(0.0);
+        }
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 0));
+        // Switching to a real rate takes effect on the next call
+        for _ in 0..10 {
+            log(1.0);
+        }
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 1));
+        clock.advance(std::time::Duration::from_millis(20));
+        // 1 Hz would throttle this, but 1 kHz lets it through
+        log(1_000.0);
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 1));
+    }
+
+    #[test]
+    fn dynamic_interval_tracks_each_rate_change() {
+        testing_logger::setup();
+        let interval_after = |rate: f64| dynamic_interval_for(&log_hz_dyn!(handle: Level::Trace, rate, "Hello, world!"));
+        assert_eq!(interval_after(1.0), 1_000_000_000);
+        assert_eq!(interval_after(1.0), 1_000_000_000);
+        assert_eq!(interval_after(4.0), 250_000_000);
+        assert_eq!(interval_after(0.0), u64::MAX);
+        assert_eq!(interval_after(2.0), 500_000_000);
+    }
+
+    #[test]
+    fn explicit_state_is_used_instead_of_a_static() {
+        testing_logger::setup();
+        let (on_stack, on_heap) = (ThrottleState::new(), Box::new(ThrottleState::new()));
+        let rate = 1.0;
+        for _ in 0..5 {
+            log_hz!(state: &on_stack, 1.0, Level::Info, "stack");
+            log_hz!(state: &on_heap, rate, Level::Info, "heap");
+        }
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["stack", "heap"]);
+        });
+        assert_eq!((on_stack.total_emitted(), on_stack.total_suppressed()), (1, 4));
+        assert_eq!((on_heap.total_emitted(), on_heap.total_suppressed()), (1, 4));
+
+        // A fresh state logs again from the same site
+        on_stack.reset();
+        log_hz!(state: &on_stack, 1.0, Level::Info, "stack");
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 1));
+    }
 }
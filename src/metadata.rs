@@ -0,0 +1,66 @@
+//! A record of every throttled call site in the binary's link section, enabled by the `link-section-metadata` feature.
+
+/// A throttled call site as written in the source, collected in [THROTTLE_SITES].
+#[derive(Debug, Clone, Copy)]
+pub struct SiteMetadata {
+    /// The source file of the call site.
+    pub file: &'static str,
+    /// The line of the call site.
+    pub line: u32,
+    /// The level's name (e.g. `Warn`), or its expression as written if it isn't a path.
+    pub level: &'static str,
+    /// The rate expression, as written (e.g. `10.0` or `CONFIG_HZ`).
+    pub rate: &'static str,
+}
+
+/// The last segment of `level` if it is a path like `Level::Warn` (as rendered by `stringify!`), otherwise `level`.
+#[doc(hidden)]
+pub const fn level_name(level: &'static str) -> &'static str {
+    let bytes = level.as_bytes();
+    let mut i = 0;
+    let mut start = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b':' | b' ' => start = i + 1,
+            b'$' | b'_' | b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => {}
+            _ => return level,
+        }
+        i += 1;
+    }
+    level.split_at(start).1
+}
+
+/// Every `log_hz!` and `log_hz_dyn!` call site compiled into the binary, whether it has run or not.
+#[linkme::distributed_slice]
+pub static THROTTLE_SITES: [SiteMetadata];
+
+#[cfg(test)]
+mod tests {
+    use super::level_name;
+    use crate::*;
+
+    #[test]
+    fn sites_are_listed_without_running() {
+        let rate = 10;
+        if rate == 0 {
+            warn_hz!(2.5, "never run");
+            log_hz_dyn!(Level::Info, rate, "never run");
+        }
+        let line = line!();
+        let site = |line| {
+            THROTTLE_SITES
+                .iter()
+                .find(|site| site.file == file!() && site.line == line)
+                .map(|site| (site.level, site.rate))
+        };
+        assert_eq!(site(line - 3), Some(("Warn", "2.5")));
+        assert_eq!(site(line - 2), Some(("Info", "rate")));
+    }
+
+    #[test]
+    fn level_name_keeps_expressions_that_are_not_paths() {
+        assert_eq!(level_name("level"), "level");
+        let expression = "if quiet { Level::Debug } else { Level::Info }";
+        assert_eq!(level_name(expression), expression);
+    }
+}
@@ -0,0 +1,45 @@
+//! The throttled logging macros and the `log` items most code needs alongside them.
+//!
+//! The crate root re-exports all of `log`, which can clash with other glob imports and hides which items come from
+//! which crate. `use log_hz::prelude::*` brings in a curated set instead:
+//!
+//! ```rust
+//! use log_hz::prelude::*;
+//!
+//! info!("Starting up");
+//! log_hz!(Level::Warn, 1.0, "Queue is {}% full", 90);
+//! ```
+
+pub use crate::{
+    count_only_hz, debug_hz, error_hz, force_log_hz, info_hz, log_every_dyn, log_hz, log_hz_accumulate,
+    log_hz_banded, log_hz_batched, log_hz_caller, log_hz_capture, log_hz_coalesce_errors, log_hz_const,
+    log_hz_dedup_numeric, log_hz_dedup_window, log_hz_dyn, log_hz_edge, log_hz_escalate, log_hz_every_nth_or_hz,
+    log_hz_group, log_hz_histogram, log_hz_jittered, log_hz_merge, log_hz_ms, log_hz_of_loop, log_hz_on,
+    log_hz_ordered, log_hz_panic_guard, log_hz_probe, log_hz_rate_from_env, log_hz_ratio, log_hz_seq, log_hz_smart,
+    log_hz_stats, log_hz_stderr_fallback, log_hz_try, log_hz_us, log_hz_with_context, log_hz_with_rate_fn,
+    log_once_per_args, trace_hz, warn_hz,
+};
+#[cfg(feature = "wall-clock")]
+pub use crate::log_hz_on_nth_second;
+#[cfg(feature = "registry")]
+pub use crate::throttle_handle;
+#[cfg(feature = "tracing")]
+pub use crate::log_hz_trace_span;
+pub use log::{Level, LevelFilter, debug, error, info, log, log_enabled, trace, warn};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prelude_covers_hz_and_plain_macros() {
+        testing_logger::setup();
+        warn_hz!(1.0, "throttled");
+        warn!("plain");
+        log!(Level::Info, "generic");
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["throttled", "plain", "generic"]);
+        });
+    }
+}
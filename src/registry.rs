@@ -0,0 +1,715 @@
+//! Process-wide list of throttled call sites, enabled by the `registry` feature.
+
+use crate::clock::now_ns;
+use crate::{Level, Rate, ThrottleHandle, ThrottledLogger};
+use log::{Log, Record};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// A call site in the registry.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RegisteredSite {
+    pub(crate) handle: &'static ThrottleHandle,
+    /// The level of the site's first call.
+    pub(crate) level: Level,
+    /// The group the site was bound to with [log_hz_group!](crate::log_hz_group), if any.
+    group: Option<&'static str>,
+    /// The site's totals as of the last [report_suppression].
+    reported_emitted: u64,
+    reported_suppressed: u64,
+}
+
+/// Every call site that has run at least once, in the order they first ran.
+static SITES: Mutex<Vec<RegisteredSite>> = Mutex::new(Vec::new());
+
+/// Add a call site to the registry, called once per site on its first call.
+pub fn register_site(handle: &'static ThrottleHandle, level: Level, group: Option<&'static str>) {
+    SITES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(RegisteredSite {
+            handle,
+            level,
+            group,
+            reported_emitted: 0,
+            reported_suppressed: 0,
+        });
+}
+
+/// A copy of the registered call sites, so callers don't hold the lock while touching their state.
+pub(crate) fn sites() -> Vec<RegisteredSite> {
+    SITES.lock().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// The handle of the nearest registered call site at or above `line` in `file`, see [throttle_handle!].
+#[doc(hidden)]
+pub fn adjacent_handle(file: &str, line: u32) -> Option<ThrottleHandle> {
+    SITES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .filter(|site| site.handle.line() <= line && site.handle.file() == file)
+        .max_by_key(|site| site.handle.line())
+        .map(|site| *site.handle)
+}
+
+/// The [ThrottleHandle] of the throttled call site just above, for resetting or inspecting it from elsewhere.
+///
+/// Place it right after the call: it evaluates to the handle of the nearest site above it in the same file (or on
+/// the same line) that has already run, and to `None` if none has. Sites register on their first call, so if the
+/// adjacent site hasn't run yet an earlier one in the file is found instead; use it on a path that just called the
+/// site. Requires the `registry` feature. The `handle:` form of the macros returns the same handle without a lookup.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// warn_hz!(1.0, "Disk almost full");
+/// let handle = throttle_handle!().unwrap();
+/// assert_eq!(handle.line(), line!() - 2);
+/// handle.reset();
+/// ```
+#[macro_export]
+macro_rules! throttle_handle {
+    () => {
+        $crate::__private::adjacent_handle(file!(), line!())
+    };
+}
+
+/// Counters of one call site, returned by [site_stats].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SiteStats {
+    /// The source file of the call site.
+    pub file: &'static str,
+    /// The line of the call site.
+    pub line: u32,
+    /// The level of the site's first call.
+    pub level: Level,
+    /// Messages emitted since the site first ran.
+    pub emitted: u64,
+    /// Calls suppressed since the site first ran.
+    pub suppressed: u64,
+    /// Calls made since the site first ran, `emitted + suppressed`.
+    ///
+    /// Calls at a disabled level return before reaching the throttle and aren't counted, unless the `count-disabled`
+    /// feature is enabled, in which case they count as suppressed.
+    pub attempts: u64,
+}
+
+/// The emitted and suppressed counts of every call site that has run so far.
+///
+/// The suppressed counts are kept in per-thread shards and summed here, so the logging hot path doesn't
+/// contend on a shared counter. Reading them costs a little more in exchange. Every count saturates at `u64::MAX`
+/// instead of wrapping, a ceiling that a site suppressing a billion calls a second would reach after 584 years.
+pub fn site_stats() -> Vec<SiteStats> {
+    sites()
+        .into_iter()
+        .map(|site| {
+            let (emitted, suppressed) = (site.handle.state().total_emitted(), site.handle.state().total_suppressed());
+            SiteStats {
+                file: site.handle.file(),
+                line: site.handle.line(),
+                level: site.level,
+                emitted,
+                suppressed,
+                attempts: emitted.saturating_add(suppressed),
+            }
+        })
+        .collect()
+}
+
+/// Combined counters of the call sites in a group, returned by [group_stats].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GroupStats {
+    /// Sites in the group that have run so far.
+    pub sites: usize,
+    /// Messages emitted by the group's sites since they first ran.
+    pub emitted: u64,
+    /// Calls suppressed at the group's sites since they first ran.
+    pub suppressed: u64,
+}
+
+/// The registered call sites bound to `group` with [log_hz_group!](crate::log_hz_group).
+fn group_sites(group: &str) -> impl Iterator<Item = RegisteredSite> {
+    sites().into_iter().filter(move |site| site.group == Some(group))
+}
+
+/// Reset every call site in `group` so its next call logs immediately, returning how many sites were reset.
+///
+/// Only sites that have already run are registered, so a site in the group that hasn't run yet isn't counted
+/// (its first call logs anyway).
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// log_hz_group!("startup", Level::Info, 1.0, "Loading configuration");
+/// log_hz_group!("startup", Level::Info, 1.0, "Connecting to the database");
+/// assert_eq!(reset_group("startup"), 2);
+/// ```
+pub fn reset_group(group: &str) -> usize {
+    group_sites(group).map(|site| site.handle.reset()).count()
+}
+
+/// The emitted and suppressed counts of every call site in `group`, summed.
+pub fn group_stats(group: &str) -> GroupStats {
+    group_sites(group).fold(GroupStats::default(), |stats, site| GroupStats {
+        sites: stats.sites + 1,
+        emitted: stats.emitted.saturating_add(site.handle.state().total_emitted()),
+        suppressed: stats.suppressed.saturating_add(site.handle.state().total_suppressed()),
+    })
+}
+
+/// Override the rate of the call site at `file_line` (e.g. `"src/main.rs:42"`) for `duration`.
+///
+/// After `duration` the site reverts to its compiled rate on its own. Boosting again replaces the previous override,
+/// and a zero `duration` ends it early. Like the compiled rate, a `rate` of 0 or less disables the site. Returns the
+/// number of sites the override was applied to: 0 if no registered site is at `file_line`, or more than 1 if several
+/// sites share the line.
+///
+/// ```rust
+/// use log_hz::*;
+/// use std::time::Duration;
+///
+/// let site = format!("{}:{}", file!(), line!() + 1);
+/// info_hz!(1.0, "Queue depth: {}", 3);
+/// assert_eq!(boost_site(&site, 100.0, Duration::from_secs(60)), 1);
+/// ```
+pub fn boost_site(file_line: &str, rate: f32, duration: Duration) -> usize {
+    let Some((file, line)) = file_line.rsplit_once(':') else {
+        return 0;
+    };
+    let Ok(line) = line.parse::<u32>() else {
+        return 0;
+    };
+    let interval_ns = rate.interval_ns();
+    let until_ns = now_ns().saturating_add(duration.as_nanos().min(u64::MAX as u128) as u64);
+    let mut boosted = 0;
+    for site in sites() {
+        if site.handle.line() == line && site.handle.file() == file {
+            site.handle.boost().set(interval_ns, until_ns);
+            boosted += 1;
+        }
+    }
+    boosted
+}
+
+/// Log how many messages each call site has suppressed since it last logged, then flush the logger.
+///
+/// Each site with a non-zero count gets one summary at its own level, and its count is reset so the same
+/// messages aren't reported twice. Useful before shutting down, see also [install_panic_flush_hook].
+pub fn flush_suppressed() {
+    for site in sites() {
+        let suppressed = site.handle.state().take_suppressed();
+        if suppressed == 0 {
+            continue;
+        }
+        ThrottledLogger.log(
+            &Record::builder()
+                .args(format_args!("log_hz: suppressed {suppressed} messages since last logged"))
+                .level(site.level)
+                .target("log_hz")
+                .file(Some(site.handle.file()))
+                .line(Some(site.handle.line()))
+                .build(),
+        );
+    }
+    ThrottledLogger.flush();
+}
+
+/// One site's activity between two reports.
+#[cfg_attr(feature = "json-reports", derive(serde::Serialize))]
+struct SiteReport {
+    /// `file:line`
+    site: String,
+    level: &'static str,
+    /// The compiled rate in Hz, 0 if disabled.
+    rate: f64,
+    emitted: u64,
+    suppressed: u64,
+}
+
+/// Log how many messages each call site emitted and suppressed since the previous report.
+///
+/// By default each site that suppressed anything gets a line of prose. With the `json-reports` feature a single
+/// line is logged instead, holding a JSON array with an object for every registered site:
+/// `{"site": "src/main.rs:42", "level": "WARN", "rate": 1.0, "emitted": 3, "suppressed": 290}`.
+/// Reports are logged at [Level::Info] with the target `log_hz`. See [spawn_suppression_reporter] to report periodically.
+pub fn report_suppression() {
+    let reports: Vec<SiteReport> = SITES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter_mut()
+        .map(|site| {
+            let state = site.handle.state();
+            let (emitted, suppressed) = (state.total_emitted(), state.total_suppressed());
+            let report = SiteReport {
+                site: format!("{}:{}", site.handle.file(), site.handle.line()),
+                level: site.level.as_str(),
+                rate: match site.handle.compiled_interval_ns() {
+                    u64::MAX => 0.0,
+                    interval_ns => 1_000_000_000.0 / interval_ns as f64,
+                },
+                // A total read while another thread takes the suppressed count can be briefly behind the last one.
+                emitted: emitted.saturating_sub(site.reported_emitted),
+                suppressed: suppressed.saturating_sub(site.reported_suppressed),
+            };
+            site.reported_emitted = site.reported_emitted.max(emitted);
+            site.reported_suppressed = site.reported_suppressed.max(suppressed);
+            report
+        })
+        .collect();
+    log_reports(&reports);
+}
+
+#[cfg(not(feature = "json-reports"))]
+fn log_reports(reports: &[SiteReport]) {
+    for report in reports.iter().filter(|report| report.suppressed > 0) {
+        log::info!(
+            target: "log_hz",
+            "log_hz: {} ({} at {}Hz) emitted {} and suppressed {} messages since the last report",
+            report.site,
+            report.level,
+            report.rate,
+            report.emitted,
+            report.suppressed
+        );
+    }
+}
+
+#[cfg(feature = "json-reports")]
+fn log_reports(reports: &[SiteReport]) {
+    match serde_json::to_string(reports) {
+        Ok(json) => log::info!(target: "log_hz", "{json}"),
+        Err(error) => log::warn!(target: "log_hz", "log_hz: failed to serialize suppression report: {error}"),
+    }
+}
+
+/// A reporter thread started with [start_suppression_reporter] or [spawn_suppression_reporter].
+struct Reporter {
+    id: u64,
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+/// Every reporter thread that hasn't been stopped yet.
+static REPORTERS: Mutex<Vec<Reporter>> = Mutex::new(Vec::new());
+static NEXT_REPORTER_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Stops the reporter thread it was returned with when dropped, see [start_suppression_reporter].
+#[derive(Debug)]
+#[must_use = "dropping the guard stops the reporter straight away"]
+pub struct ReporterGuard {
+    id: u64,
+}
+
+impl Drop for ReporterGuard {
+    fn drop(&mut self) {
+        stop_reporters(|reporter| reporter.id == self.id);
+    }
+}
+
+/// Call [report_suppression] every `period` from a background thread, until the returned guard is dropped.
+///
+/// Stopping the thread, by dropping the guard or with [stop_reporter], wakes it immediately for one last report of
+/// what was suppressed since the previous one, then flushes the logger and waits for the thread to finish. A thread
+/// that is still running at process exit is killed without that final report, so hold the guard in `main`:
+///
+/// ```rust
+/// use log_hz::*;
+/// use std::time::Duration;
+///
+/// let _reporter = start_suppression_reporter(Duration::from_secs(60));
+/// // ... run the application, the final report is logged when `_reporter` goes out of scope
+/// ```
+pub fn start_suppression_reporter(period: Duration) -> ReporterGuard {
+    let id = NEXT_REPORTER_ID.fetch_add(1, Ordering::Relaxed);
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread = std::thread::Builder::new()
+        .name("log_hz-reporter".to_string())
+        .spawn({
+            let stop = Arc::clone(&stop);
+            move || {
+                let mut next_report = Instant::now() + period;
+                while !stop.load(Ordering::Acquire) {
+                    // Woken early by `stop_reporters`, or spuriously, in which case the deadline isn't up yet.
+                    std::thread::park_timeout(next_report.saturating_duration_since(Instant::now()));
+                    if Instant::now() >= next_report {
+                        report_suppression();
+                        next_report += period;
+                    }
+                }
+                report_suppression();
+                log::logger().flush();
+            }
+        })
+        .expect("failed to spawn the log_hz reporter thread");
+    REPORTERS.lock().unwrap_or_else(PoisonError::into_inner).push(Reporter { id, stop, thread });
+    ReporterGuard { id }
+}
+
+/// Call [report_suppression] every `period` from a background thread, for the rest of the process or until
+/// [stop_reporter] is called.
+pub fn spawn_suppression_reporter(period: Duration) {
+    std::mem::forget(start_suppression_reporter(period));
+}
+
+/// Stop every reporter thread still running, each logging a final report, and wait for them to finish.
+///
+/// Meant for a clean shutdown of a program that started its reporter with [spawn_suppression_reporter], which
+/// returns no guard.
+pub fn stop_reporter() {
+    stop_reporters(|_| true);
+}
+
+fn stop_reporters(mut matches: impl FnMut(&Reporter) -> bool) {
+    let stopped: Vec<Reporter> = {
+        let mut reporters = REPORTERS.lock().unwrap_or_else(PoisonError::into_inner);
+        let (stopped, running) = std::mem::take(&mut *reporters).into_iter().partition(&mut matches);
+        *reporters = running;
+        stopped
+    };
+    for reporter in stopped {
+        reporter.stop.store(true, Ordering::Release);
+        reporter.thread.thread().unpark();
+        // The thread only panics if the logger did, which was already reported on that thread.
+        let _ = reporter.thread.join();
+    }
+}
+
+/// Install a panic hook that calls [flush_suppressed] before running the previously installed hook.
+///
+/// Without it, suppressed counts are lost when the program panics, hiding how much was being dropped right
+/// before the crash. Install it after any other panic hook, as a hook set later replaces this one.
+pub fn install_panic_flush_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        flush_suppressed();
+        previous(info);
+    }));
+}
+
+/// One call site's timer in a [ThrottleSnapshot].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SiteSnapshot {
+    /// The source file of the call site.
+    pub file: String,
+    /// The line of the call site.
+    pub line: u32,
+    /// When the site last logged, in nanoseconds since [process_start](crate::process_start), if it has.
+    pub last_log_ns: Option<u64>,
+}
+
+/// The throttle timers of every registered call site, taken with [snapshot_throttle_state].
+///
+/// Sites are keyed by `file:line`, so a snapshot can be restored into another instance of the same binary.
+/// It can be written out with its `Display` impl and read back with [FromStr], one `file:line=last_log_ns`
+/// entry per line (`-` for a site that hasn't logged).
+///
+/// Timestamps are relative to [process_start](crate::process_start). A forked child shares its parent's start,
+/// but a new process does not, so a snapshot restored there is only approximately right.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThrottleSnapshot {
+    sites: Vec<SiteSnapshot>,
+}
+
+impl ThrottleSnapshot {
+    /// The saved call sites.
+    pub fn sites(&self) -> &[SiteSnapshot] {
+        &self.sites
+    }
+}
+
+impl fmt::Display for ThrottleSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for site in &self.sites {
+            match site.last_log_ns {
+                Some(last_ns) => writeln!(f, "{}:{}={}", site.file, site.line, last_ns)?,
+                None => writeln!(f, "{}:{}=-", site.file, site.line)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error returned when parsing a [ThrottleSnapshot] fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSnapshotError {
+    line: usize,
+}
+
+impl fmt::Display for ParseSnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid throttle snapshot entry on line {}", self.line)
+    }
+}
+
+impl std::error::Error for ParseSnapshotError {}
+
+impl FromStr for ThrottleSnapshot {
+    type Err = ParseSnapshotError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let sites = s
+            .lines()
+            .enumerate()
+            .filter(|(_, entry)| !entry.trim().is_empty())
+            .map(|(index, entry)| {
+                let error = ParseSnapshotError { line: index + 1 };
+                // Split from the right, file names may contain ':' (e.g. Windows drive letters)
+                let (site, last_ns) = entry.trim().rsplit_once('=').ok_or(error.clone())?;
+                let (file, line) = site.rsplit_once(':').ok_or(error.clone())?;
+                Ok(SiteSnapshot {
+                    file: file.to_string(),
+                    line: line.parse().map_err(|_| error.clone())?,
+                    last_log_ns: match last_ns {
+                        "-" => None,
+                        last_ns => Some(last_ns.parse().map_err(|_| error)?),
+                    },
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { sites })
+    }
+}
+
+/// Save the throttle timer of every call site that has run so far.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// info_hz!(1.0, "Before the checkpoint");
+/// let snapshot = snapshot_throttle_state();
+/// // ...
+/// restore_throttle_state(&snapshot);
+/// ```
+pub fn snapshot_throttle_state() -> ThrottleSnapshot {
+    let sites = sites()
+        .into_iter()
+        .map(|site| site.handle)
+        .map(|handle| SiteSnapshot {
+            file: handle.file().to_string(),
+            line: handle.line(),
+            last_log_ns: handle.state().last_log_ns(),
+        })
+        .collect();
+    ThrottleSnapshot { sites }
+}
+
+/// Restore the throttle timers saved in `snapshot`, clearing the restored sites' suppressed counts.
+///
+/// Only sites that have already run in this process are registered, so entries for other sites (or sites that
+/// no longer exist) are ignored, as are registered sites missing from the snapshot.
+pub fn restore_throttle_state(snapshot: &ThrottleSnapshot) {
+    for RegisteredSite { handle, .. } in sites() {
+        let saved = snapshot
+            .sites
+            .iter()
+            .find(|site| site.line == handle.line() && site.file == handle.file());
+        if let Some(saved) = saved {
+            handle.state().restore(saved.last_log_ns);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn restored_timer_suppresses_the_next_call() {
+        testing_logger::setup();
+        let log = || info_hz!(handle: 1.0, "checkpointed");
+        let handle = log();
+        let snapshot = snapshot_throttle_state();
+        handle.reset();
+        restore_throttle_state(&snapshot);
+        log();
+        assert_eq!(handle.suppressed_count(), 1);
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 1));
+    }
+
+    #[test]
+    fn adjacent_handle_resets_the_site_above() {
+        testing_logger::setup();
+        let log = || {
+            info_hz!(1.0, "adjacent");
+            throttle_handle!().expect("the site above has run")
+        };
+        let handle = log();
+        log();
+        log();
+        assert_eq!(handle.suppressed_count(), 2);
+        assert!(handle.time_until_next() > Duration::ZERO);
+
+        handle.reset();
+        log();
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 2));
+    }
+
+    #[test]
+    fn stats_without_a_handle_report_the_site_above() {
+        testing_logger::setup();
+        for _ in 0..10 {
+            error_hz!(1.0, "driven");
+        }
+        log_hz_stats!();
+        testing_logger::validate(|captured_logs| {
+            let stats = &captured_logs.last().unwrap().body;
+            assert_eq!(captured_logs.len(), 2);
+            assert!(stats.contains(&format!("registry.rs:{}", line!() - 6)), "{stats}");
+            assert!(stats.contains("emitted 1, suppressed 9 (9 since last), next allowed in "), "{stats}");
+        });
+    }
+
+    #[test]
+    fn boosted_site_logs_faster_until_the_boost_expires() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        let log = || info_hz!(handle: 1.0, "boosted");
+        with_clock(clock.clone(), || {
+            let handle = log();
+            let site = format!("{}:{}", handle.file(), handle.line());
+            assert_eq!(boost_site(&site, 1_000.0, Duration::from_millis(200)), 1);
+            assert_eq!(boost_site("src/nowhere.rs:1", 1_000.0, Duration::from_millis(200)), 0);
+            // Each step is past the boosted interval, so its first call logs and the second is throttled
+            for _ in 0..20 {
+                clock.advance(Duration::from_millis(5));
+                log();
+                log();
+            }
+        });
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 21));
+
+        // Back to 1Hz, and the last boosted message was just now (validate clears the captured logs)
+        with_clock(clock.clone(), || {
+            clock.advance(Duration::from_millis(150));
+            for _ in 0..10 {
+                log();
+            }
+        });
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 0));
+    }
+
+    fn drive_reported_site() -> String {
+        let log = || warn_hz!(handle: 1.0, "reported");
+        for _ in 0..9 {
+            log();
+        }
+        let handle = log();
+        format!("{}:{}", handle.file(), handle.line())
+    }
+
+    #[cfg(not(feature = "json-reports"))]
+    #[test]
+    fn report_counts_the_activity_since_the_last_one() {
+        testing_logger::setup();
+        let site = drive_reported_site();
+        report_suppression();
+        report_suppression();
+        testing_logger::validate(|captured_logs| {
+            let reports: Vec<_> = captured_logs.iter().filter(|log| log.body.contains(&site)).collect();
+            assert_eq!(reports.len(), 1);
+            assert_eq!(
+                reports[0].body,
+                format!("log_hz: {site} (WARN at 1Hz) emitted 1 and suppressed 9 messages since the last report")
+            );
+        });
+    }
+
+    #[cfg(feature = "json-reports")]
+    #[test]
+    fn json_report_counts_the_activity_since_the_last_one() {
+        testing_logger::setup();
+        let site = drive_reported_site();
+        report_suppression();
+        report_suppression();
+        testing_logger::validate(|captured_logs| {
+            let entries: Vec<serde_json::Value> = captured_logs
+                .iter()
+                .filter(|log| log.target == "log_hz")
+                .map(|log| serde_json::from_str::<Vec<serde_json::Value>>(&log.body).expect("valid JSON"))
+                .map(|entries| {
+                    entries
+                        .into_iter()
+                        .find(|entry| entry["site"] == site.as_str())
+                        .expect("site is reported")
+                })
+                .collect();
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0]["level"], "WARN");
+            assert_eq!(entries[0]["rate"], 1.0);
+            assert_eq!((&entries[0]["emitted"], &entries[0]["suppressed"]), (&1.into(), &9.into()));
+            assert_eq!((&entries[1]["emitted"], &entries[1]["suppressed"]), (&0.into(), &0.into()));
+        });
+    }
+
+    #[test]
+    fn site_stats_merge_counts_from_all_threads() {
+        testing_logger::setup();
+        let log = || debug_hz!(handle: 1.0, "from many threads");
+        let handle = log();
+        std::thread::scope(|scope| {
+            for _ in 0..16 {
+                scope.spawn(|| {
+                    for _ in 0..1_000 {
+                        log();
+                    }
+                });
+            }
+        });
+        let stats = site_stats()
+            .into_iter()
+            .find(|stats| stats.file == handle.file() && stats.line == handle.line())
+            .expect("site is registered");
+        assert_eq!((stats.level, stats.emitted, stats.suppressed), (Level::Debug, 1, 16_000));
+    }
+
+    #[test]
+    fn reset_group_repeats_the_first_call_of_every_site() {
+        testing_logger::setup();
+        let drive = || {
+            for _ in 0..5 {
+                log_hz_group!("test-reset-group", Level::Info, 1.0, "first");
+                log_hz_group!("test-reset-group", Level::Warn, 1.0, "second");
+                log_hz_group!("test-reset-group", Level::Error, 1.0, "third");
+            }
+        };
+        drive();
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 3));
+        let stats = group_stats("test-reset-group");
+        assert_eq!((stats.sites, stats.emitted, stats.suppressed), (3, 3, 12));
+
+        assert_eq!(reset_group("test-reset-group"), 3);
+        drive();
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["first", "second", "third"]);
+        });
+        assert_eq!(group_stats("test-reset-group").emitted, 6);
+        assert_eq!(group_stats("no-such-group"), GroupStats::default());
+    }
+
+    #[test]
+    fn snapshot_round_trips_through_text() {
+        let snapshot = ThrottleSnapshot {
+            sites: vec![
+                SiteSnapshot {
+                    file: "C:\\src\\main.rs".to_string(),
+                    line: 12,
+                    last_log_ns: Some(1_500),
+                },
+                SiteSnapshot {
+                    file: "src/lib.rs".to_string(),
+                    line: 3,
+                    last_log_ns: None,
+                },
+            ],
+        };
+        assert_eq!(snapshot.to_string().parse(), Ok(snapshot));
+        assert!("src/lib.rs=12".parse::<ThrottleSnapshot>().is_err());
+    }
+}
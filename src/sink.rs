@@ -0,0 +1,208 @@
+//! Routing of throttled emissions, including the optional secondary sink.
+
+use crate::ThrottleState;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::cell::Cell;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{PoisonError, RwLock};
+
+thread_local! {
+    /// The call site currently emitting on this thread, used to detect re-entrant calls.
+    static EMITTING: Cell<*const ()> = const { Cell::new(ptr::null()) };
+}
+
+/// Run `emit` for a call site that passed its throttle, unless this thread is already emitting for that site.
+///
+/// A message's format arguments are evaluated after the site claims its slot, so if one of them (e.g. a
+/// `Display` impl) calls back into the same site, that inner call is normally throttled by the timestamp
+/// just written. This guard also covers intervals shorter than the time it takes to format the message,
+/// where the inner call would otherwise emit again and could recurse without bound.
+///
+/// `site` identifies the call site, and may be any static of the site's that isn't zero-sized, usually its
+/// [ThrottleState]. Every call site macro emits through this, so none of them emits again from within its own message.
+#[inline]
+pub fn emit_once<T>(site: &T, emit: impl FnOnce()) {
+    let site = ptr::from_ref(site).cast::<()>();
+    let outer = EMITTING.with(|emitting| emitting.replace(site));
+    if outer != site {
+        emit();
+    }
+    EMITTING.with(|emitting| emitting.set(outer));
+}
+
+/// Run `emit`, containing a panic from formatting the message or from the logger, for logging from `Drop` impls.
+///
+/// A thread that is already unwinding aborts on a second panic before it could be caught, so there `emit` is just
+/// called, and only the throttle itself is guaranteed not to panic.
+#[inline]
+pub fn emit_guarded(emit: impl FnOnce()) {
+    if std::thread::panicking() {
+        emit();
+    } else {
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(emit));
+    }
+}
+
+/// Whether a logger appears to be installed, judged by the maximum level no longer being `Off`.
+///
+/// The `log` crate doesn't say whether a logger was set, but its maximum level stays `Off` until something raises it,
+/// which loggers do when they are installed. A single atomic load.
+#[inline]
+pub fn logger_installed() -> bool {
+    log::max_level() != LevelFilter::Off
+}
+
+/// Whether a site that hasn't emitted yet may use its first call, i.e. the installed logger would accept the message.
+///
+/// With the `wait-for-logger` feature, checked before the throttle so the first message isn't spent on the no-op
+/// logger `log` uses until one is set, which accepts nothing. Once the site has emitted it is a single atomic load.
+#[inline]
+pub fn first_call_ready(state: &ThrottleState, level: Level, target: &str) -> bool {
+    state.last_log_ns().is_some() || log::logger().enabled(&Metadata::builder().level(level).target(target).build())
+}
+
+/// Whether the logger accepts messages at `level` for `target`, if one was given explicitly.
+///
+/// Checked before the throttle, so a message filtered out by its target doesn't use up the site's slot. Only a site
+/// with a `target:` pays for the call into the logger.
+#[inline]
+pub fn target_enabled(level: Level, target: Option<&str>) -> bool {
+    target.is_none_or(|target| log::logger().enabled(&Metadata::builder().level(level).target(target).build()))
+}
+
+/// Write a throttled message to stderr, for when there is no logger to send it to.
+#[cold]
+pub fn write_to_stderr(level: Level, target: &str, args: std::fmt::Arguments) {
+    eprintln!("[{level} {target}] {args}");
+}
+
+/// Set while a secondary sink is installed, so emissions can skip the lock when there isn't one.
+static HAS_SECONDARY: AtomicBool = AtomicBool::new(false);
+static SECONDARY: RwLock<Option<Box<dyn Log>>> = RwLock::new(None);
+
+/// Install a logger that also receives every throttled message that is emitted, replacing any previous one.
+///
+/// The secondary sink sees records after they pass the throttle, in addition to the global logger set up
+/// through the `log` crate. Suppressed messages never reach it. This is useful for keeping recent throttled
+/// activity in memory, e.g. a ring buffer behind a debug UI. Plain `log` macros are not forwarded.
+pub fn set_secondary_sink(sink: Box<dyn Log>) {
+    *SECONDARY.write().unwrap_or_else(PoisonError::into_inner) = Some(sink);
+    HAS_SECONDARY.store(true, Ordering::Release);
+}
+
+/// Remove the secondary sink installed with [set_secondary_sink], returning it.
+pub fn remove_secondary_sink() -> Option<Box<dyn Log>> {
+    HAS_SECONDARY.store(false, Ordering::Release);
+    SECONDARY.write().unwrap_or_else(PoisonError::into_inner).take()
+}
+
+/// The logger every throttled emission goes through.
+///
+/// Forwards to the global logger, and to the secondary sink if one is installed.
+#[derive(Debug, Clone, Copy)]
+pub struct ThrottledLogger;
+
+impl Log for ThrottledLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        log::logger().enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        #[cfg(feature = "site-fields")]
+        let fields = SiteFields { record };
+        #[cfg(feature = "site-fields")]
+        let record = &record.to_builder().key_values(&fields).build();
+        #[cfg(any(test, feature = "testing"))]
+        crate::testing::record_emission();
+        log::logger().log(record);
+        if !HAS_SECONDARY.load(Ordering::Acquire) {
+            return;
+        }
+        if let Some(sink) = SECONDARY.read().unwrap_or_else(PoisonError::into_inner).as_ref()
+            && sink.enabled(record.metadata())
+        {
+            sink.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        log::logger().flush();
+        if let Some(sink) = SECONDARY.read().unwrap_or_else(PoisonError::into_inner).as_ref() {
+            sink.flush();
+        }
+    }
+}
+
+/// A record's key-value fields followed by its file and line as `hz_file` and `hz_line`, with the `site-fields`
+/// feature.
+///
+/// The macros set the record's location to the call site, so aggregators can group throttled messages by origin
+/// without parsing the logger's formatting of it.
+#[cfg(feature = "site-fields")]
+struct SiteFields<'a, 'r> {
+    record: &'r Record<'a>,
+}
+
+#[cfg(feature = "site-fields")]
+impl log::kv::Source for SiteFields<'_, '_> {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn log::kv::VisitSource<'kvs>) -> Result<(), log::kv::Error> {
+        use log::kv::{Key, Value};
+
+        self.record.key_values().visit(visitor)?;
+        if let Some(file) = self.record.file() {
+            visitor.visit_pair(Key::from_str("hz_file"), Value::from(file))?;
+        }
+        if let Some(line) = self.record.line() {
+            visitor.visit_pair(Key::from_str("hz_line"), Value::from(line))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+
+    struct CountingSink(Arc<AtomicUsize>);
+
+    impl Log for CountingSink {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            // Other tests log concurrently, only count this test's messages
+            if record.args().to_string() == "to the secondary sink" {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn secondary_sink_sees_only_emitted_messages() {
+        testing_logger::setup();
+        let count = Arc::new(AtomicUsize::new(0));
+        set_secondary_sink(Box::new(CountingSink(count.clone())));
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            for i in 0..10 {
+                if i == 9 {
+                    clock.advance(std::time::Duration::from_millis(100));
+                }
+                info_hz!(10.0, "to the secondary sink");
+            }
+        });
+        // Plain log macros are not forwarded
+        log::info!("to the secondary sink");
+        remove_secondary_sink();
+
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 3));
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,171 @@
+//! Throttles kept per `tracing` span, so a call site throttles separately within each span, and the `tracing` level
+//! filter, enabled by the `tracing` feature.
+
+use crate::{Level, ThrottleState};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{PoisonError, RwLock};
+use tracing::Subscriber;
+use tracing::span::Id;
+use tracing_subscriber::layer::{Context, Layer};
+
+/// How many (span, call site) timers are kept at once. Past it, calls in spans without a timer yet share their
+/// site's timer until spans close.
+pub const SPAN_THROTTLE_CAPACITY: usize = 4096;
+
+/// Number of shards the timers are spread over, so calls in different spans rarely wait on the same lock.
+const SHARDS: usize = 16;
+
+/// The timer of each call site within each open span, keyed by the span's id and the address of the site's state.
+///
+/// A span's timers all live in the shard picked by its id, so closing it only locks that one.
+static SPAN_STATES: [RwLock<BTreeMap<(u64, usize), ThrottleState>>; SHARDS] =
+    [const { RwLock::new(BTreeMap::new()) }; SHARDS];
+
+/// Number of timers across all shards.
+static SPAN_STATE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+fn shard(span: u64) -> &'static RwLock<BTreeMap<(u64, usize), ThrottleState>> {
+    &SPAN_STATES[span as usize % SHARDS]
+}
+
+/// Like [ThrottleState::should_log], using the timer `site` has within the current span, if there is one.
+///
+/// A decision made on a span's timer is also counted on `site`, so the site's statistics cover every span.
+#[doc(hidden)]
+pub fn span_should_log(site: &ThrottleState, now_ns: u64, interval_ns: u64) -> bool {
+    let Some(span) = tracing::Span::current().id() else {
+        return site.should_log(now_ns, interval_ns);
+    };
+    let span = span.into_u64();
+    let key = (span, site as *const ThrottleState as usize);
+    // Every call after a span's first at a site finds its timer under the shared lock.
+    let found = {
+        let states = shard(span).read().unwrap_or_else(PoisonError::into_inner);
+        states.get(&key).map(|state| state.should_log(now_ns, interval_ns))
+    };
+    let emitted = match found {
+        Some(emitted) => emitted,
+        None => {
+            let mut states = shard(span).write().unwrap_or_else(PoisonError::into_inner);
+            let is_new = !states.contains_key(&key);
+            if is_new && SPAN_STATE_COUNT.fetch_add(1, Ordering::Relaxed) >= SPAN_THROTTLE_CAPACITY {
+                SPAN_STATE_COUNT.fetch_sub(1, Ordering::Relaxed);
+                drop(states);
+                return site.should_log(now_ns, interval_ns);
+            }
+            let state = states.entry(key).or_insert_with(|| ThrottleState::with_first(site.logs_first()));
+            state.should_log(now_ns, interval_ns)
+        }
+    };
+    site.count_decision(emitted);
+    emitted
+}
+
+/// Whether `tracing` enables events at `level`, like `tracing::level_enabled!`, or no subscriber is set.
+///
+/// Without a subscriber there is no `tracing` filter to follow, and messages only go through `log`.
+#[doc(hidden)]
+#[inline]
+pub fn tracing_enabled(level: Level) -> bool {
+    let level = tracing_level(level);
+    tracing::level_enabled!(level)
+        || tracing::dispatcher::get_default(|dispatch| dispatch.is::<tracing::subscriber::NoSubscriber>())
+}
+
+/// The `tracing` level matching `level`, `const` so a span's static metadata can use it.
+#[doc(hidden)]
+pub const fn tracing_level(level: Level) -> tracing::Level {
+    match level {
+        Level::Error => tracing::Level::ERROR,
+        Level::Warn => tracing::Level::WARN,
+        Level::Info => tracing::Level::INFO,
+        Level::Debug => tracing::Level::DEBUG,
+        Level::Trace => tracing::Level::TRACE,
+    }
+}
+
+/// Open and enter a `tracing` span at a throttled rate, for instrumenting a section only some of the times it runs.
+///
+/// Evaluates to `Some` with the entered span when the throttle lets the call through, and to `None` otherwise, so
+/// the span isn't even created: an expensive instrumented section (e.g. one with timing attached) runs at most at
+/// `rate`. The span is exited when the returned guard is dropped. `level` is a [Level](crate::Level) known at compile
+/// time, and `name` and the optional fields are given as to `tracing::span!`. Calls at a level disabled in `log` or
+/// filtered out by the subscriber return `None` without using up the slot.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// # let batch = [1, 2, 3];
+/// // Traces one batch a second, however many are processed
+/// let _span = log_hz_trace_span!(1.0, Level::Info, "process_batch", size = batch.len());
+/// ```
+#[macro_export]
+macro_rules! log_hz_trace_span {
+    ($rate:expr, $level:expr, $name:expr $(, $($fields:tt)*)?) => {
+        {
+            use std::sync::LazyLock;
+
+            static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| $crate::Rate::interval_ns($rate));
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level) && {
+                let now_ns = $crate::__private::now_ns();
+                $crate::__private::site_allows(level, now_ns, *INTERVAL_NS, |interval_ns| {
+                    STATE.should_log(now_ns, interval_ns)
+                })
+            } {
+                let span = $crate::__private::tracing::span!(
+                    $crate::__private::tracing_level($level),
+                    $name
+                    $(, $($fields)*)?
+                );
+                Some(span.entered())
+            } else {
+                None
+            }
+        }
+    };
+}
+
+/// Number of (span, call site) timers currently kept.
+pub fn span_throttle_count() -> usize {
+    SPAN_STATE_COUNT.load(Ordering::Relaxed)
+}
+
+/// A `tracing_subscriber` layer that drops the throttles of spans as they close.
+///
+/// With the `tracing` feature, a `log_hz!` (or `info_hz!`, etc.) call made inside a `tracing` span is throttled on a
+/// timer of its own for that span, so the same message from different spans (e.g. one per request) doesn't
+/// suppress each other. Calls outside any span use the site's timer as usual. Add this layer to the subscriber so
+/// the timers are removed when their span closes: span ids are reused afterwards, and a new span would otherwise
+/// inherit a closed one's timers. At most [SPAN_THROTTLE_CAPACITY] timers are kept at once.
+///
+/// ```rust
+/// use log_hz::*;
+/// use tracing_subscriber::layer::SubscriberExt;
+///
+/// let subscriber = tracing_subscriber::registry().with(SpanThrottleLayer);
+/// tracing::subscriber::with_default(subscriber, || {
+///     for request in 0..3 {
+///         tracing::info_span!("request", request).in_scope(|| {
+///             info_hz!(1.0, "Handling request");
+///         });
+///     }
+/// });
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SpanThrottleLayer;
+
+impl<S: Subscriber> Layer<S> for SpanThrottleLayer {
+    fn on_close(&self, id: Id, _ctx: Context<'_, S>) {
+        let span = id.into_u64();
+        let mut states = shard(span).write().unwrap_or_else(PoisonError::into_inner);
+        let sites: Vec<_> = states.range((span, 0)..=(span, usize::MAX)).map(|(&key, _)| key).collect();
+        for key in &sites {
+            states.remove(key);
+        }
+        SPAN_STATE_COUNT.fetch_sub(sites.len(), Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,890 @@
+//! Throttle state shared by the logging macros.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+/// Value of `last_log_ns` for a throttle that has never emitted.
+///
+/// Real timestamps are clamped to at least 1ns when stored, so 0 is free to mean "never".
+const NEVER_LOGGED: u64 = 0;
+
+/// Value of `last_log_ns` for a throttle whose first call should start the timer instead of logging.
+///
+/// Real timestamps would take centuries to reach this value.
+const AWAITING_FIRST_CALL: u64 = u64::MAX;
+
+/// How an interval that isn't a whole number of nanoseconds is rounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntervalRounding {
+    /// Round to the nearest nanosecond, so the effective rate is as close as possible to the requested one.
+    Nearest,
+    /// Round down, so messages are allowed at least as often as requested.
+    Floor,
+    /// Round up, so messages are never allowed more often than requested.
+    Ceil,
+}
+
+/// The rounding used by [interval_from_rate] and the macros.
+///
+/// Round-to-nearest by default. The `interval-floor` and `interval-ceil` features select the other modes; if both
+/// are enabled (e.g. with `--all-features`), `interval-ceil` wins, as never exceeding the requested rate is the safer
+/// of the two.
+pub const INTERVAL_ROUNDING: IntervalRounding = if cfg!(feature = "interval-ceil") {
+    IntervalRounding::Ceil
+} else if cfg!(feature = "interval-floor") {
+    IntervalRounding::Floor
+} else {
+    IntervalRounding::Nearest
+};
+
+/// Convert a rate in Hz into the interval between messages in nanoseconds, rounded with [INTERVAL_ROUNDING].
+///
+/// A rate of 0 or less (or NaN) disables logging, represented by an interval of `u64::MAX`.
+pub const fn interval_from_rate(rate: f64) -> u64 {
+    interval_from_rate_rounded(rate, INTERVAL_ROUNDING)
+}
+
+/// Like [interval_from_rate], with an explicit rounding mode.
+///
+/// ```rust
+/// use log_hz::{IntervalRounding, interval_from_rate_rounded};
+///
+/// assert_eq!(interval_from_rate_rounded(3.0, IntervalRounding::Nearest), 333_333_333);
+/// assert_eq!(interval_from_rate_rounded(3.0, IntervalRounding::Ceil), 333_333_334);
+/// ```
+pub const fn interval_from_rate_rounded(rate: f64, rounding: IntervalRounding) -> u64 {
+    if rate > 0.0 {
+        let interval_ns = 1_000_000_000.0 / rate;
+        // `f64::floor` and friends aren't const, but the cast truncates (and saturates) positive values
+        let floor = interval_ns as u64;
+        let round_up = match rounding {
+            IntervalRounding::Nearest => interval_ns - floor as f64 >= 0.5,
+            IntervalRounding::Floor => false,
+            IntervalRounding::Ceil => (floor as f64) < interval_ns,
+        };
+        if round_up { floor.saturating_add(1) } else { floor }
+    } else {
+        u64::MAX
+    }
+}
+
+/// Whether a call site with this rate logs on every call, i.e. the rate is infinite or so high its interval rounds
+/// to zero (which never happens for a finite rate with [IntervalRounding::Ceil]).
+///
+/// Like [would_never_log], this is a `const fn` so audits and lints can flag degenerate rates at build time:
+///
+/// ```rust
+/// use log_hz::{would_always_log, would_never_log};
+///
+/// const MOTOR_STATUS_HZ: f32 = 2.0;
+/// const _: () = assert!(!would_always_log(MOTOR_STATUS_HZ) && !would_never_log(MOTOR_STATUS_HZ));
+/// ```
+pub const fn would_always_log(rate: f32) -> bool {
+    interval_from_rate(rate as f64) == 0
+}
+
+/// Whether a call site with this rate never logs, i.e. the rate is 0 or less, NaN, or so low its interval
+/// saturates to the disabled value.
+pub const fn would_never_log(rate: f32) -> bool {
+    interval_from_rate(rate as f64) == u64::MAX
+}
+
+/// Divide a second into `rate` intervals using integer math, rounded with [INTERVAL_ROUNDING].
+fn integer_interval(rate: u128) -> u64 {
+    const SECOND_NS: u128 = 1_000_000_000;
+    if rate == 0 {
+        return u64::MAX;
+    }
+    let interval_ns = match INTERVAL_ROUNDING {
+        IntervalRounding::Nearest => (SECOND_NS + rate / 2) / rate,
+        IntervalRounding::Floor => SECOND_NS / rate,
+        IntervalRounding::Ceil => SECOND_NS.div_ceil(rate),
+    };
+    interval_ns as u64
+}
+
+/// A rate in Hz, as accepted by the throttled logging macros.
+///
+/// Implemented for all primitive numeric types. Integer rates are converted with integer division rounded with
+/// [INTERVAL_ROUNDING], so a rate of 3 gives exactly 333_333_333ns by default, while floating point rates go
+/// through [interval_from_rate]. A rate of 0 or less disables logging either way. A [Duration] is taken as the
+/// interval between messages instead, see [log_hz_ms!](crate::log_hz_ms).
+pub trait Rate: Copy {
+    /// The interval between messages in nanoseconds, or `u64::MAX` if the rate disables logging.
+    fn interval_ns(self) -> u64;
+
+    /// The rate in Hz as a float.
+    fn as_hz(self) -> f64;
+}
+
+impl Rate for f64 {
+    fn interval_ns(self) -> u64 {
+        interval_from_rate(self)
+    }
+
+    fn as_hz(self) -> f64 {
+        self
+    }
+}
+
+impl Rate for f32 {
+    fn interval_ns(self) -> u64 {
+        interval_from_rate(self as f64)
+    }
+
+    fn as_hz(self) -> f64 {
+        self as f64
+    }
+}
+
+macro_rules! impl_unsigned_rate {
+    ($($t:ty),*) => {$(
+        impl Rate for $t {
+            fn interval_ns(self) -> u64 {
+                integer_interval(self as u128)
+            }
+
+            fn as_hz(self) -> f64 {
+                self as f64
+            }
+        }
+    )*};
+}
+
+macro_rules! impl_signed_rate {
+    ($($t:ty),*) => {$(
+        impl Rate for $t {
+            fn interval_ns(self) -> u64 {
+                if self <= 0 { u64::MAX } else { (self as u128).interval_ns() }
+            }
+
+            fn as_hz(self) -> f64 {
+                self as f64
+            }
+        }
+    )*};
+}
+
+impl_unsigned_rate!(u8, u16, u32, u64, u128, usize);
+impl_signed_rate!(i8, i16, i32, i64, i128, isize);
+
+/// A [Duration] is the interval itself rather than a rate, so its nanoseconds are used exactly. Unlike a rate of 0,
+/// [Duration::ZERO] logs every call.
+impl Rate for Duration {
+    fn interval_ns(self) -> u64 {
+        self.as_nanos().min(u64::MAX as u128) as u64
+    }
+
+    fn as_hz(self) -> f64 {
+        1e9 / self.as_nanos() as f64
+    }
+}
+
+/// Number of shards in a [ShardedCounter].
+const SHARDS: usize = 8;
+
+/// One shard of a [ShardedCounter], on its own cache line so threads on different shards don't contend.
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct Shard(AtomicU64);
+
+thread_local! {
+    /// The shard this thread increments, assigned round-robin as threads first use a counter.
+    static SHARD: usize = {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        NEXT.fetch_add(1, Ordering::Relaxed) % SHARDS
+    };
+}
+
+/// Add `n` to a statistics counter, stopping at `u64::MAX` instead of wrapping around.
+///
+/// The add itself still wraps, and a counter that went past the ceiling is set back to it, so the hot path stays a
+/// single `fetch_add`. A read racing with that repair can see the wrapped value once, in a counter that has already
+/// counted over 18 quintillion calls.
+#[inline]
+fn saturating_add(counter: &AtomicU64, n: u64) {
+    if counter.fetch_add(n, Ordering::Relaxed) > u64::MAX - n {
+        counter.store(u64::MAX, Ordering::Relaxed);
+    }
+}
+
+/// A counter that many threads can increment without contending on one cache line.
+///
+/// Each thread adds to one of several shards, and reads sum them, so increments stay cheap on hot paths
+/// while reads (which are rare) do a little more work. Shards and their sum saturate at `u64::MAX`.
+#[derive(Default)]
+struct ShardedCounter {
+    shards: [Shard; SHARDS],
+}
+
+impl ShardedCounter {
+    const fn new() -> Self {
+        Self {
+            shards: [const { Shard(AtomicU64::new(0)) }; SHARDS],
+        }
+    }
+
+    #[inline]
+    fn increment(&self) {
+        self.add(1);
+    }
+
+    #[inline]
+    fn add(&self, n: u64) {
+        let shard = SHARD.with(|shard| *shard);
+        saturating_add(&self.shards[shard].0, n);
+    }
+
+    fn sum(&self) -> u64 {
+        self.shards.iter().fold(0, |sum, shard| sum.saturating_add(shard.0.load(Ordering::Relaxed)))
+    }
+
+    /// Reset the counter, returning its value. Increments racing with this land either in the result or the counter.
+    fn take(&self) -> u64 {
+        self.shards.iter().fold(0, |sum, shard| sum.saturating_add(shard.0.swap(0, Ordering::Relaxed)))
+    }
+}
+
+impl std::fmt::Debug for ShardedCounter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.sum().fmt(f)
+    }
+}
+
+/// The lock-free timer behind every throttled call site.
+///
+/// Each `log_hz!` invocation owns a static `ThrottleState`. It can also be used directly to
+/// throttle anything that isn't a macro call site.
+///
+/// ```rust
+/// use log_hz::{ThrottleState, interval_from_rate};
+///
+/// let state = ThrottleState::new();
+/// let interval = interval_from_rate(1.0);
+/// assert!(state.should_log(1_000, interval)); // First call always passes
+/// assert!(!state.should_log(2_000, interval));
+/// assert!(state.should_log(1_000_001_000, interval));
+/// ```
+///
+/// A check with [should_log](Self::should_log) or [try_log](Self::try_log) never locks or retries: it is a few
+/// atomic loads, at most one compare-and-swap and a counter increment, in a bounded number of steps. The increment
+/// also reads a thread-local picking the shard of the suppressed counter, initialized on the thread's first check.
+/// With the timestamp supplied by the caller, e.g. from a free-running hardware counter, a static `ThrottleState`
+/// can decide whether to log from code that must not block, such as a real-time thread, leaving the message itself
+/// to be handed off elsewhere. Thread-local access isn't async-signal-safe though, so a check must not be made from
+/// a signal handler. The macros do more than this: a site's first call initializes its interval and the process clock through
+/// `LazyLock`s, and the `registry` feature takes a lock. Keyed throttles like [KeyedThrottle](crate::KeyedThrottle)
+/// and [log_hz_merge!](crate::log_hz_merge) lock a map on every call. The `testing` feature's `Clock` trait doesn't
+/// change this: it is installed per thread through a thread-local and an `Arc`, and is meant for tests rather than
+/// as a time source for interrupt handlers. The crate needs `std`, so there is no `no_std` or `critical-section`
+/// build.
+#[derive(Debug, Default)]
+pub struct ThrottleState {
+    /// The timestamp of the last log in nanoseconds, or [NEVER_LOGGED].
+    last_log_ns: AtomicU64,
+    /// Calls rejected since the last emission. Incremented on every suppressed call, so sharded to avoid contention.
+    ///
+    /// This and the other counters saturate at `u64::MAX` rather than wrapping. The suppressed total can still read
+    /// low for a moment while [take_suppressed](Self::take_suppressed) moves the count into `suppressed_before`.
+    suppressed: ShardedCounter,
+    /// Calls rejected before the last emission (or reset), so totals survive clearing `suppressed`.
+    suppressed_before: AtomicU64,
+    /// Calls that were allowed to log.
+    emitted: AtomicU64,
+    /// The interval used by [check](Self::check), see [set_interval](Self::set_interval).
+    interval_ns: AtomicU64,
+    /// Whether the first call logs, or only starts the timer.
+    log_first: bool,
+}
+
+impl ThrottleState {
+    /// Create a throttle that will allow its first call through.
+    pub const fn new() -> Self {
+        Self::with_first(true)
+    }
+
+    /// Create a throttle that logs on its first call if `log_first` is true.
+    ///
+    /// Otherwise the first call only starts the timer, and the first message is emitted a full
+    /// interval later. This is useful when only steady-state behavior is worth logging.
+    pub const fn with_first(log_first: bool) -> Self {
+        Self {
+            last_log_ns: AtomicU64::new(if log_first { NEVER_LOGGED } else { AWAITING_FIRST_CALL }),
+            suppressed: ShardedCounter::new(),
+            suppressed_before: AtomicU64::new(0),
+            emitted: AtomicU64::new(0),
+            interval_ns: AtomicU64::new(0),
+            log_first,
+        }
+    }
+
+    /// Create a throttle that lets its first call through, with `interval_ns` between messages for
+    /// [check](Self::check).
+    pub const fn with_interval(interval_ns: u64) -> Self {
+        Self { interval_ns: AtomicU64::new(interval_ns), ..Self::new() }
+    }
+
+    /// Whether the first call (and the first call after a [reset](Self::reset)) logs.
+    pub fn logs_first(&self) -> bool {
+        self.log_first
+    }
+
+    /// The timestamp of the last call that was allowed to log (or that started the timer), if any.
+    pub fn last_log_ns(&self) -> Option<u64> {
+        match self.last_log_ns.load(Ordering::Relaxed) {
+            NEVER_LOGGED | AWAITING_FIRST_CALL => None,
+            last_ns => Some(last_ns),
+        }
+    }
+
+    /// Number of calls that were throttled since the last emission.
+    pub fn suppressed_count(&self) -> u64 {
+        self.suppressed.sum()
+    }
+
+    /// Total number of calls that were allowed to log.
+    pub fn total_emitted(&self) -> u64 {
+        self.emitted.load(Ordering::Relaxed)
+    }
+
+    /// Total number of calls that were throttled, including those before the last emission or reset.
+    ///
+    /// A read racing with an emission on another thread can miss the calls suppressed just before it, so the total
+    /// isn't guaranteed to only ever grow between reads. Take differences between reads with saturating math.
+    pub fn total_suppressed(&self) -> u64 {
+        self.suppressed_before.load(Ordering::Relaxed).saturating_add(self.suppressed.sum())
+    }
+
+    /// Take the number of calls throttled since the last emission, resetting it to 0.
+    ///
+    /// The shards are cleared before the count is added to `suppressed_before`, so a concurrent
+    /// [total_suppressed](Self::total_suppressed) can briefly miss it.
+    pub(crate) fn take_suppressed(&self) -> u64 {
+        let suppressed = self.suppressed.take();
+        saturating_add(&self.suppressed_before, suppressed);
+        suppressed
+    }
+
+    /// Forget the last emission, so the next call behaves as if it were the first.
+    ///
+    /// The totals are kept.
+    pub fn reset(&self) {
+        let initial = if self.log_first { NEVER_LOGGED } else { AWAITING_FIRST_CALL };
+        self.last_log_ns.store(initial, Ordering::Relaxed);
+        self.take_suppressed();
+    }
+
+    /// The interval between messages used by [check](Self::check), in nanoseconds.
+    ///
+    /// 0 (every call logs) unless set with [with_interval](Self::with_interval) or [set_interval](Self::set_interval).
+    pub fn interval(&self) -> u64 {
+        self.interval_ns.load(Ordering::Relaxed)
+    }
+
+    /// Change the interval used by [check](Self::check), taking effect from the next call.
+    ///
+    /// The time of the last emission is kept, so after shortening the interval the next message may be allowed
+    /// straight away. `u64::MAX` disables logging, like a rate of 0.
+    pub fn set_interval(&self, interval_ns: u64) {
+        self.interval_ns.store(interval_ns, Ordering::Relaxed);
+    }
+
+    /// Like [should_log](Self::should_log), with the interval stored in the state, so it can be tuned at runtime
+    /// without reconstructing the state or threading the interval through every call.
+    ///
+    /// ```rust
+    /// use log_hz::{ThrottleState, interval_from_rate};
+    ///
+    /// let state = ThrottleState::with_interval(interval_from_rate(1.0));
+    /// assert!(state.check(1_000));
+    /// assert!(!state.check(500_001_000));
+    /// state.set_interval(interval_from_rate(2.0));
+    /// assert!(state.check(500_001_000));
+    /// ```
+    #[inline]
+    pub fn check(&self, now_ns: u64) -> bool {
+        self.should_log(now_ns, self.interval())
+    }
+
+    /// Decide whether a message at time `now_ns` may be emitted given `interval_ns` between messages.
+    ///
+    /// Returns `true` for at most one caller per interval, and always for the first call unless the
+    /// interval is `u64::MAX` (logging disabled) or the state was created with
+    /// [with_first(false)](Self::with_first). `now_ns` should come from a monotonic clock.
+    #[inline]
+    pub fn should_log(&self, now_ns: u64, interval_ns: u64) -> bool {
+        self.try_log(now_ns, interval_ns).is_some()
+    }
+
+    /// Like [should_log](Self::should_log), but on emission returns how many calls were suppressed since
+    /// the previous emission.
+    #[inline]
+    pub fn try_log(&self, now_ns: u64, interval_ns: u64) -> Option<u64> {
+        // A rate of 0 or less disables logging entirely, including the first call.
+        if interval_ns == u64::MAX {
+            self.suppressed.increment();
+            return None;
+        }
+
+        // --- Fast Path ---
+        // This is the most common path, executed on every call to the macro.
+        // It's designed to be as cheap as possible.
+
+        // First, perform a quick, optimistic check to see if we should log.
+        // We use `Relaxed` ordering because it's the cheapest, and we're not
+        // yet synchronizing memory. We just want to bail out early if possible.
+        let last_ns = self.last_log_ns.load(Ordering::Relaxed);
+
+        // The first call of a `with_first(false)` throttle starts the timer instead of logging.
+        if last_ns == AWAITING_FIRST_CALL {
+            let _ = self.last_log_ns.compare_exchange(
+                AWAITING_FIRST_CALL,
+                now_ns.max(1),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+            self.suppressed.increment();
+            return None;
+        }
+
+        // Check if enough time has passed since the last log.
+        // `saturating_sub` prevents a panic in the rare case of time moving backward.
+        if last_ns != NEVER_LOGGED && now_ns.saturating_sub(last_ns) < interval_ns {
+            self.suppressed.increment();
+            return None;
+        }
+
+        // --- Slow Path ---
+        // We might get to log. Now we need to ensure only one thread does.
+        // We use a `compare_exchange` to atomically update the timestamp.
+        // This operation attempts to replace `last_ns` with `now_ns` only if
+        // the current value is still `last_ns`.
+        //
+        // Ordering::Relaxed (on success and failure):
+        //   At most one emission per interval follows from the atomicity of the compare-and-swap alone: every
+        //   read-modify-write of `last_log_ns` is ordered in its single modification order, so only one caller can
+        //   replace a given `last_ns`, whatever the memory ordering. No other data is published through the
+        //   timestamp (the message is handed to the logger, which synchronizes its own state, and the counters are
+        //   atomics of their own), so there is nothing for Acquire or Release to order. The `loom` test in
+        //   `tests/loom.rs` checks this protocol under every interleaving.
+        //
+        // If the `compare_exchange` fails, another thread logged in the tiny
+        // window between our `load` and `compare_exchange`, which correctly throttles the message.
+        //
+        // With the `single-threaded` feature there is no other thread to race, so a plain store claims the slot
+        // without the read-modify-write. Racing threads can then both pass the check above and both emit.
+        #[cfg(not(feature = "single-threaded"))]
+        let claimed =
+            self.last_log_ns.compare_exchange(last_ns, now_ns.max(1), Ordering::Relaxed, Ordering::Relaxed).is_ok();
+        #[cfg(feature = "single-threaded")]
+        let claimed = {
+            self.last_log_ns.store(now_ns.max(1), Ordering::Relaxed);
+            true
+        };
+        if claimed {
+            saturating_add(&self.emitted, 1);
+            Some(self.take_suppressed())
+        } else {
+            self.suppressed.increment();
+            None
+        }
+    }
+
+    /// Let a message through at `now_ns` regardless of the interval, restarting the interval from it.
+    ///
+    /// Always returns `true`. Unlike logging around the throttle, the forced message counts as the last emission, so
+    /// throttling resumes from it, e.g. after a "log now" button in a debug UI. See
+    /// [force_log_hz!](crate::force_log_hz).
+    ///
+    /// ```rust
+    /// use log_hz::{ThrottleState, interval_from_rate};
+    ///
+    /// let state = ThrottleState::new();
+    /// let interval = interval_from_rate(1.0);
+    /// assert!(state.should_log(1_000, interval));
+    /// assert!(state.force(500_000_000));
+    /// assert!(!state.should_log(1_000_000_000, interval)); // A second after the first, but not the forced one
+    /// ```
+    pub fn force(&self, now_ns: u64) -> bool {
+        saturating_add(&self.emitted, 1);
+        self.mark_logged(now_ns);
+        true
+    }
+
+    /// Overwrite the last emission with one saved from [last_log_ns](Self::last_log_ns), clearing the suppressed count.
+    #[cfg(feature = "registry")]
+    pub(crate) fn restore(&self, last_log_ns: Option<u64>) {
+        match last_log_ns {
+            Some(last_ns) => self.mark_logged(last_ns),
+            None => self.reset(),
+        }
+    }
+
+    /// Count a call suppressed before reaching the throttle (e.g. at a disabled level), without touching the timer.
+    pub(crate) fn count_suppressed(&self) {
+        self.suppressed.increment();
+    }
+
+    /// Give back `count` suppressed calls taken by an emission that was then held back, so the next one reports them.
+    pub(crate) fn restore_suppressed(&self, count: u64) {
+        self.suppressed.add(count);
+    }
+
+    /// Count a decision made on this site's behalf by another timer, e.g. one kept per `tracing` span, leaving this
+    /// timer alone.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn count_decision(&self, emitted: bool) {
+        if emitted {
+            saturating_add(&self.emitted, 1);
+            self.take_suppressed();
+        } else {
+            self.suppressed.increment();
+        }
+    }
+
+    /// Move the last emission to `last_ns`, shifting when the next one is allowed without touching the counts.
+    pub(crate) fn shift_last_log(&self, last_ns: u64) {
+        self.last_log_ns.store(last_ns.max(1), Ordering::Relaxed);
+    }
+
+    /// Start the timer at `last_ns` if the state never logged, as if it had emitted then.
+    ///
+    /// Unlike [mark_logged](Self::mark_logged) this is a compare-and-swap, so of several racing first calls only one
+    /// seeds the timer, and an emission that got in first isn't overwritten.
+    pub(crate) fn seed_last_log(&self, last_ns: u64) {
+        let _ = self.last_log_ns.compare_exchange(NEVER_LOGGED, last_ns.max(1), Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    /// Record an emission at `now_ns` that bypassed the throttle, restarting the interval from it.
+    pub(crate) fn mark_logged(&self, now_ns: u64) {
+        self.last_log_ns.store(now_ns.max(1), Ordering::Relaxed);
+        self.take_suppressed();
+    }
+}
+
+/// A [ThrottleState] whose interval is a type parameter instead of being passed on every call.
+///
+/// With the interval known at compile time, a call site needs no runtime storage or initialization guard for it,
+/// see [log_hz_const!](crate::log_hz_const). Compute the parameter with [interval_from_rate], which is `const`.
+///
+/// ```rust
+/// use log_hz::{ConstThrottleState, interval_from_rate};
+///
+/// static STATE: ConstThrottleState<{ interval_from_rate(2.0) }> = ConstThrottleState::new();
+/// assert!(STATE.should_log(1_000));
+/// assert!(!STATE.should_log(2_000));
+/// assert!(STATE.should_log(500_001_000));
+/// ```
+#[derive(Debug, Default)]
+pub struct ConstThrottleState<const INTERVAL_NS: u64> {
+    state: ThrottleState,
+}
+
+impl<const INTERVAL_NS: u64> ConstThrottleState<INTERVAL_NS> {
+    /// Create a throttle that will allow its first call through.
+    pub const fn new() -> Self {
+        Self {
+            state: ThrottleState::new(),
+        }
+    }
+
+    /// Like [ThrottleState::should_log] with an interval of `INTERVAL_NS`.
+    #[inline]
+    pub fn should_log(&self, now_ns: u64) -> bool {
+        self.state.should_log(now_ns, INTERVAL_NS)
+    }
+
+    /// The underlying throttle state.
+    pub fn state(&self) -> &ThrottleState {
+        &self.state
+    }
+}
+
+/// Interval cache for call sites whose rate is only known at runtime.
+///
+/// Holds the last rate seen (as `f64` bits) and the interval computed from it, so the reciprocal
+/// is only recalculated when the rate actually changes.
+#[derive(Debug, Default)]
+pub struct DynamicInterval {
+    rate_bits: AtomicU64,
+    interval_ns: AtomicU64,
+}
+
+impl DynamicInterval {
+    /// Create an empty cache; the first lookup always computes the interval.
+    pub const fn new() -> Self {
+        Self {
+            // NaN bits never compare equal to a real rate, so the first lookup always misses.
+            rate_bits: AtomicU64::new(u64::MAX),
+            interval_ns: AtomicU64::new(u64::MAX),
+        }
+    }
+
+    /// Get the interval for `rate`, recomputing it if the rate differs from the cached one.
+    #[inline]
+    pub fn interval_for(&self, rate: impl Rate) -> u64 {
+        let bits = rate.as_hz().to_bits();
+        if self.rate_bits.load(Ordering::Acquire) == bits {
+            return self.interval_ns.load(Ordering::Relaxed);
+        }
+        self.recompute(rate, bits)
+    }
+
+    /// The most recently computed interval, or `u64::MAX` if no rate has been seen yet.
+    pub fn cached(&self) -> u64 {
+        self.interval_ns.load(Ordering::Relaxed)
+    }
+
+    fn recompute(&self, rate: impl Rate, bits: u64) -> u64 {
+        let interval_ns = rate.interval_ns();
+        self.interval_ns.store(interval_ns, Ordering::Relaxed);
+        // Release pairs with the Acquire above so a matching rate implies the new interval is visible.
+        self.rate_bits.store(bits, Ordering::Release);
+        interval_ns
+    }
+}
+
+/// The interval each call site location computed when its cached interval was initialized, in debug builds.
+///
+/// Keyed by owned strings, as a location's `file!()` lives in code that may have been unloaded since.
+static SITE_INTERVALS: Mutex<BTreeMap<(String, u32, u32), u64>> = Mutex::new(BTreeMap::new());
+
+/// Warn if the site at `file`:`line`:`column` cached a different interval when it was initialized before.
+///
+/// Called by debug builds when a site's cached interval is initialized, which normally happens once per location.
+/// A second initialization means a fresh copy of the site's statics, e.g. from a reloaded library, while the old
+/// copy may still be running with its old interval.
+#[doc(hidden)]
+pub fn check_site_interval(interval_ns: u64, file: &str, line: u32, column: u32) {
+    let previous_ns = SITE_INTERVALS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert((file.to_string(), line, column), interval_ns);
+    if let Some(previous_ns) = previous_ns.filter(|&previous_ns| previous_ns != interval_ns) {
+        log::warn!(
+            target: "log_hz",
+            "log_hz: the call site at {file}:{line}:{column} was initialized again with an interval of {:?} \
+             instead of {:?}, so copies of it from code loaded earlier may still use the old one",
+            Duration::from_nanos(interval_ns),
+            Duration::from_nanos(previous_ns),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const _: () = assert!(would_never_log(0.0));
+    const _: () = assert!(would_never_log(-1.0));
+    const _: () = assert!(would_never_log(f32::NAN));
+    const _: () = assert!(would_never_log(f32::NEG_INFINITY));
+    const _: () = assert!(would_always_log(f32::INFINITY));
+    const _: () = assert!(!would_always_log(1.0) && !would_never_log(1.0));
+    // Finite rates at the extremes saturate to the same sentinels, except that rounding up never reaches zero
+    const _: () = assert!(would_never_log(f32::MIN_POSITIVE));
+    const _: () = assert!(would_always_log(f32::MAX) != matches!(INTERVAL_ROUNDING, IntervalRounding::Ceil));
+    // Ceil wins over Floor when both features are enabled
+    #[cfg(feature = "interval-ceil")]
+    const _: () = assert!(matches!(INTERVAL_ROUNDING, IntervalRounding::Ceil));
+
+    #[test]
+    fn interval_from_rate_handles_disabled_rates() {
+        assert_eq!(interval_from_rate(1.0), 1_000_000_000);
+        assert_eq!(interval_from_rate(0.0), u64::MAX);
+        assert_eq!(interval_from_rate(-1.0), u64::MAX);
+        assert_eq!(interval_from_rate(f64::NAN), u64::MAX);
+    }
+
+    #[test]
+    fn fractional_intervals_follow_the_rounding_mode() {
+        // 333_333_333.33ns and 142_857_142.86ns
+        assert_eq!(interval_from_rate_rounded(3.0, IntervalRounding::Nearest), 333_333_333);
+        assert_eq!(interval_from_rate_rounded(7.0, IntervalRounding::Nearest), 142_857_143);
+        assert_eq!(interval_from_rate_rounded(3.0, IntervalRounding::Floor), 333_333_333);
+        assert_eq!(interval_from_rate_rounded(7.0, IntervalRounding::Floor), 142_857_142);
+        assert_eq!(interval_from_rate_rounded(3.0, IntervalRounding::Ceil), 333_333_334);
+        assert_eq!(interval_from_rate_rounded(7.0, IntervalRounding::Ceil), 142_857_143);
+        // Whole intervals aren't affected
+        assert_eq!(interval_from_rate_rounded(10.0, IntervalRounding::Ceil), 100_000_000);
+        assert_eq!(interval_from_rate(3.0), interval_from_rate_rounded(3.0, INTERVAL_ROUNDING));
+    }
+
+    #[test]
+    fn integer_rates_use_integer_math() {
+        if INTERVAL_ROUNDING == IntervalRounding::Nearest {
+            assert_eq!(3.interval_ns(), 333_333_333);
+            assert_eq!(7u32.interval_ns(), 142_857_143);
+        }
+        // Integer math agrees with the float path in every rounding mode
+        assert_eq!(3.interval_ns(), 3.0.interval_ns());
+        assert_eq!(7u32.interval_ns(), 7.0.interval_ns());
+        assert_eq!(1i64.interval_ns(), 1_000_000_000);
+        assert_eq!(0u8.interval_ns(), u64::MAX);
+        assert_eq!((-1).interval_ns(), u64::MAX);
+    }
+
+    #[test]
+    fn const_interval_is_a_type_parameter() {
+        const INTERVAL_NS: u64 = interval_from_rate(4.0);
+        assert_eq!(INTERVAL_NS, 250_000_000);
+        let state = ConstThrottleState::<INTERVAL_NS>::new();
+        assert!(state.should_log(10));
+        assert!(!state.should_log(250_000_000));
+        assert!(state.should_log(250_000_010));
+        assert_eq!(state.state().suppressed_count(), 0);
+        assert!(!ConstThrottleState::<{ interval_from_rate(0.0) }>::new().should_log(0));
+    }
+
+    #[test]
+    fn first_call_always_logs() {
+        let state = ThrottleState::new();
+        // Even at time zero, before a full interval could possibly have elapsed
+        assert!(state.should_log(0, 1_000));
+        assert!(!state.should_log(0, 1_000));
+    }
+
+    #[test]
+    fn disabled_interval_never_logs() {
+        let state = ThrottleState::new();
+        assert!(!state.should_log(0, u64::MAX));
+        assert!(!state.should_log(u64::MAX - 1, u64::MAX));
+    }
+
+    #[test]
+    fn suppressed_calls_are_counted_until_reset() {
+        let state = ThrottleState::new();
+        assert!(state.should_log(10, 1_000));
+        assert!(!state.should_log(20, 1_000));
+        assert!(!state.should_log(30, 1_000));
+        assert_eq!(state.suppressed_count(), 2);
+        assert_eq!(state.last_log_ns(), Some(10));
+        state.reset();
+        assert_eq!(state.suppressed_count(), 0);
+        assert_eq!((state.total_emitted(), state.total_suppressed()), (1, 2));
+        assert_eq!(state.last_log_ns(), None);
+        assert!(state.should_log(40, 1_000));
+    }
+
+    #[test]
+    fn counts_are_exact_across_threads() {
+        static STATE: ThrottleState = ThrottleState::new();
+        let start = std::time::Instant::now();
+        std::thread::scope(|scope| {
+            for _ in 0..SHARDS + 3 {
+                scope.spawn(|| {
+                    for _ in 0..10_000 {
+                        STATE.should_log(start.elapsed().as_nanos() as u64, 100_000);
+                    }
+                });
+            }
+        });
+        assert!(STATE.total_emitted() > 1);
+        assert_eq!(STATE.total_emitted() + STATE.total_suppressed(), (SHARDS as u64 + 3) * 10_000);
+    }
+
+    #[test]
+    fn counters_saturate_instead_of_wrapping() {
+        let state = ThrottleState::new();
+        state.emitted.store(u64::MAX - 1, Ordering::Relaxed);
+        state.suppressed_before.store(u64::MAX - 1, Ordering::Relaxed);
+        for shard in &state.suppressed.shards {
+            shard.0.store(u64::MAX - 1, Ordering::Relaxed);
+        }
+        for now_ns in 0..5 {
+            state.should_log(now_ns, 0);
+        }
+        assert_eq!(state.total_emitted(), u64::MAX);
+        assert_eq!(state.suppressed_count(), 0);
+        assert_eq!(state.total_suppressed(), u64::MAX);
+
+        for shard in &state.suppressed.shards {
+            shard.0.store(u64::MAX - 1, Ordering::Relaxed);
+        }
+        state.count_suppressed();
+        state.count_suppressed();
+        assert_eq!(state.suppressed_count(), u64::MAX);
+    }
+
+    #[test]
+    fn stored_interval_can_be_changed() {
+        let state = ThrottleState::with_interval(100);
+        assert_eq!(state.interval(), 100);
+        let emitted = |from: u64| (from..from + 1_000).step_by(10).filter(|&now_ns| state.check(now_ns)).count();
+        assert_eq!(emitted(1_000), 10);
+        state.set_interval(50);
+        assert_eq!(state.interval(), 50);
+        assert_eq!(emitted(2_000), 20);
+        state.set_interval(u64::MAX);
+        assert_eq!(emitted(3_000), 0);
+    }
+
+    #[test]
+    fn try_log_reports_suppressed_calls() {
+        let state = ThrottleState::new();
+        assert_eq!(state.try_log(10, 1_000), Some(0));
+        assert_eq!(state.try_log(20, 1_000), None);
+        assert_eq!(state.try_log(30, 1_000), None);
+        assert_eq!(state.try_log(1_010, 1_000), Some(2));
+    }
+
+    #[test]
+    fn first_call_can_start_the_timer_instead() {
+        let state = ThrottleState::with_first(false);
+        assert!(!state.should_log(10, 1_000));
+        assert!(!state.should_log(500, 1_000));
+        assert!(state.should_log(1_010, 1_000));
+        state.reset();
+        assert!(!state.should_log(5_000, 1_000));
+    }
+
+    #[test]
+    fn dynamic_interval_picks_up_rate_changes() {
+        let cache = DynamicInterval::new();
+        assert_eq!(cache.interval_for(1.0), 1_000_000_000);
+        assert_eq!(cache.interval_for(1.0), 1_000_000_000);
+        assert_eq!(cache.interval_for(4.0), 250_000_000);
+        assert_eq!(cache.interval_for(0.0), u64::MAX);
+        assert_eq!(cache.interval_for(2.0), 500_000_000);
+    }
+
+    #[test]
+    fn reinitialized_site_with_another_interval_warns() {
+        testing_logger::setup();
+        // As if a reloaded library initialized the same location again
+        check_site_interval(1_000_000_000, "src/plugin.rs", 12, 5);
+        check_site_interval(1_000_000_000, "src/plugin.rs", 12, 5);
+        check_site_interval(1_000_000_000, "src/plugin.rs", 12, 9);
+        check_site_interval(500_000_000, "src/plugin.rs", 12, 5);
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(
+                bodies,
+                ["log_hz: the call site at src/plugin.rs:12:5 was initialized again with an interval of 500ms \
+                  instead of 1s, so copies of it from code loaded earlier may still use the old one"]
+            );
+        });
+    }
+
+    #[test]
+    fn static_state_throttles_a_handler_fed_by_a_tick_counter() {
+        // A free-running 1MHz counter, as an interrupt handler would read from a hardware timer
+        static TICKS: AtomicU64 = AtomicU64::new(5_000);
+        static STATE: ThrottleState = ThrottleState::new();
+        const INTERVAL_NS: u64 = interval_from_rate(10.0);
+        let handler = || STATE.should_log(TICKS.load(Ordering::Relaxed) * 1_000, INTERVAL_NS);
+
+        // A second of interrupts every millisecond
+        let mut fired = Vec::new();
+        for ms in 0..1_000 {
+            if handler() {
+                fired.push(ms);
+            }
+            TICKS.fetch_add(1_000, Ordering::Relaxed);
+        }
+        assert_eq!(fired, [0, 100, 200, 300, 400, 500, 600, 700, 800, 900]);
+        assert_eq!(STATE.total_suppressed(), 990);
+    }
+}
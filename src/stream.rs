@@ -0,0 +1,162 @@
+//! Throttled logging from inside async streams, enabled by the `futures` feature.
+
+use crate::__private::level_enabled;
+use crate::clock::now_ns;
+use crate::global::site_allows;
+use crate::sink::target_enabled;
+use crate::{Level, ThrottleState, ThrottledLogger, interval_from_rate};
+use futures_core::Stream;
+use std::fmt::Display;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Extension trait adding throttled logging to any [Stream], mirroring [crate::ThrottleIterExt].
+///
+/// ```rust
+/// use futures::{StreamExt, stream};
+/// use log_hz::*;
+///
+/// # futures::executor::block_on(async {
+/// let items: Vec<u32> = stream::iter(0..100)
+///     .log_every_hz(1.0, Level::Info, |i| format!("Received item {i}"))
+///     .with_target(module_path!())
+///     .collect()
+///     .await;
+/// # });
+/// ```
+pub trait ThrottleStreamExt: Stream + Sized {
+    /// Log a message built from the current item at most `rate` times per second.
+    ///
+    /// Items are passed through unchanged. `msg_fn` is only called when a message is actually emitted,
+    /// and a poll that returns [Poll::Pending] neither logs nor reads the clock. Neither do items at a disabled level.
+    fn log_every_hz<F, M>(self, rate: f64, level: Level, msg_fn: F) -> LogEveryHzStream<Self, F>
+    where
+        F: FnMut(&Self::Item) -> M,
+        M: Display,
+    {
+        LogEveryHzStream {
+            stream: self,
+            msg_fn,
+            level,
+            target: None,
+            interval_ns: interval_from_rate(rate),
+            state: ThrottleState::new(),
+        }
+    }
+}
+
+impl<S: Stream> ThrottleStreamExt for S {}
+
+pin_project_lite::pin_project! {
+    /// Stream returned by [ThrottleStreamExt::log_every_hz].
+    #[derive(Debug)]
+    pub struct LogEveryHzStream<S, F> {
+        #[pin]
+        stream: S,
+        msg_fn: F,
+        level: Level,
+        target: Option<&'static str>,
+        interval_ns: u64,
+        state: ThrottleState,
+    }
+}
+
+impl<S, F> LogEveryHzStream<S, F> {
+    /// Log with `target` instead of this module's path, like [LogEveryHz::with_target](crate::LogEveryHz::with_target).
+    pub fn with_target(mut self, target: &'static str) -> Self {
+        self.target = Some(target);
+        self
+    }
+}
+
+impl<S, F, M> Stream for LogEveryHzStream<S, F>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> M,
+    M: Display,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let item = match this.stream.poll_next(cx) {
+            Poll::Ready(Some(item)) => item,
+            other => return other,
+        };
+        let (level, target) = (*this.level, *this.target);
+        if level_enabled(level)
+            && target_enabled(level, target)
+            && {
+                let now_ns = now_ns();
+                site_allows(level, now_ns, *this.interval_ns, |interval_ns| this.state.should_log(now_ns, interval_ns))
+            }
+        {
+            let target = target.unwrap_or(module_path!());
+            log::log!(logger: ThrottledLogger, target: target, level, "{}", (this.msg_fn)(&item));
+        }
+        Poll::Ready(Some(item))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{StreamExt, stream};
+
+    #[tokio::test]
+    async fn items_pass_through_while_logs_are_throttled() {
+        testing_logger::setup();
+        let items: Vec<u32> = stream::iter(0..100)
+            .log_every_hz(1.0, Level::Info, |i| format!("item {i}"))
+            .collect()
+            .await;
+        assert_eq!(items, (0..100).collect::<Vec<_>>());
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(captured_logs.len(), 1);
+            assert_eq!(captured_logs[0].body, "item 0");
+        });
+    }
+
+    #[tokio::test]
+    async fn pending_polls_do_not_log() {
+        testing_logger::setup();
+        // Yield Pending before every item, which must not log on its own
+        let items: Vec<u32> = stream::iter(0..100)
+            .then(|i| async move {
+                tokio::task::yield_now().await;
+                i
+            })
+            .log_every_hz(1.0, Level::Info, |i| format!("item {i}"))
+            .collect()
+            .await;
+        assert_eq!(items, (0..100).collect::<Vec<_>>());
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(captured_logs.len(), 1);
+            assert_eq!(captured_logs[0].body, "item 0");
+        });
+    }
+
+    #[test]
+    fn adapter_follows_the_crate_clock_and_target() {
+        testing_logger::setup();
+        let clock = crate::ManualClock::default();
+        let items: Vec<u32> = crate::with_clock(clock.clone(), || {
+            futures::executor::block_on(
+                stream::iter(0..30)
+                    .inspect(|_| clock.advance(std::time::Duration::from_millis(100)))
+                    .log_every_hz(1.0, Level::Info, |i| format!("item {i}"))
+                    .with_target("app::items")
+                    .collect(),
+            )
+        });
+        assert_eq!(items.len(), 30);
+        testing_logger::validate(|captured_logs| {
+            let logs: Vec<_> = captured_logs.iter().map(|log| (log.target.as_str(), log.body.as_str())).collect();
+            assert_eq!(logs, [("app::items", "item 0"), ("app::items", "item 10"), ("app::items", "item 20")]);
+        });
+    }
+}
@@ -0,0 +1,224 @@
+//! Hooks for tests of code that uses log_hz, enabled by the `testing` feature.
+
+use crate::ThrottleHandle;
+use log::{LevelFilter, Log, Metadata, Record};
+use std::cell::{Cell, RefCell};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+thread_local! {
+    /// The clock installed on this thread by [with_clock], if any.
+    static CLOCK: RefCell<Option<Arc<dyn Clock>>> = const { RefCell::new(None) };
+
+    /// Throttled messages emitted on this thread, and when the first and last of them were.
+    static EMISSIONS: Cell<(u64, Option<Instant>, Option<Instant>)> = const { Cell::new((0, None, None)) };
+}
+
+/// Count a throttled message emitted on this thread.
+pub(crate) fn record_emission() {
+    EMISSIONS.with(|emissions| {
+        let (count, first, _) = emissions.get();
+        let now = Instant::now();
+        emissions.set((count + 1, first.or(Some(now)), Some(now)));
+    });
+}
+
+/// Used if no logger is installed, since `log` drops every message before it reaches log_hz without one.
+struct NoopLogger;
+
+impl Log for NoopLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, _record: &Record) {}
+
+    fn flush(&self) {}
+}
+
+/// Install [NoopLogger] with the maximum level set to `Trace`, unless a logger is already installed.
+fn ensure_logger() {
+    if log::set_logger(&NoopLogger).is_ok() {
+        log::set_max_level(LevelFilter::Trace);
+    }
+}
+
+/// Run `f` and return how many throttled messages it emitted on the current thread.
+///
+/// Like [assert_emission_rate], this installs a no-op logger if there isn't one. Combined with [with_clock] and a
+/// [ManualClock] it makes the emissions of any throttled call site deterministic:
+///
+/// ```rust
+/// use log_hz::*;
+/// use std::time::Duration;
+///
+/// let clock = ManualClock::default();
+/// let emitted = with_clock(clock.clone(), || {
+///     count_emissions(|| {
+///         for _ in 0..100 {
+///             info_hz!(10.0, "Hello, world!");
+///             clock.advance(Duration::from_millis(10));
+///         }
+///     })
+/// });
+/// // One second at 10Hz
+/// assert_eq!(emitted, 10);
+/// ```
+pub fn count_emissions(f: impl FnOnce()) -> u64 {
+    ensure_logger();
+    let before = EMISSIONS.with(|emissions| emissions.get().0);
+    f();
+    EMISSIONS.with(|emissions| emissions.get().0) - before
+}
+
+/// Call `f` repeatedly for `duration` and assert that the throttled messages it emits arrive at `expected_hz`.
+///
+/// The observed rate is measured between the first and last message, so the first call logging immediately doesn't
+/// skew it, and must be within `tolerance` (a fraction, e.g. 0.1 for 10%) of `expected_hz`. Only messages emitted on
+/// the current thread are counted, so tests running in parallel don't affect each other.
+///
+/// If no logger is installed, a no-op logger is installed with the maximum level set to `Trace`. Otherwise messages
+/// disabled by the installed logger's maximum level aren't emitted, and so aren't counted.
+///
+/// ```rust,no_run
+/// use log_hz::*;
+/// use std::time::Duration;
+///
+/// assert_emission_rate(2.0, 0.1, Duration::from_secs(2), || {
+///     info_hz!(2.0, "Hello, world!");
+/// });
+/// ```
+///
+/// # Panics
+/// Panics if the observed rate is outside the tolerance, or fewer than two messages were emitted.
+#[track_caller]
+pub fn assert_emission_rate(expected_hz: f64, tolerance: f64, duration: Duration, mut f: impl FnMut()) {
+    ensure_logger();
+    EMISSIONS.with(|emissions| emissions.set((0, None, None)));
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        f();
+    }
+    let (count, first, last) = EMISSIONS.with(|emissions| emissions.get());
+    let (Some(first), Some(last)) = (first, last) else {
+        panic!("expected messages at {expected_hz}Hz, but none were emitted in {duration:?}");
+    };
+    assert!(count >= 2, "expected messages at {expected_hz}Hz, but only one was emitted in {duration:?}");
+    let observed_hz = (count - 1) as f64 / (last - first).as_secs_f64();
+    assert!(
+        (observed_hz - expected_hz).abs() <= tolerance * expected_hz,
+        "expected messages at {expected_hz}Hz (±{}%), but observed {observed_hz:.3}Hz ({count} messages in {duration:?})",
+        tolerance * 100.0
+    );
+}
+
+/// The interval a call site currently throttles with, ignoring any [boost](crate::boost_site).
+///
+/// For a [log_hz_dyn!](crate::log_hz_dyn) site this is the interval cached for the last rate it was called with, so a
+/// test can check that a rate change was picked up. Get the handle from the site's `handle:` form.
+pub fn dynamic_interval_for(handle: &ThrottleHandle) -> u64 {
+    handle.compiled_interval_ns()
+}
+
+/// A time source log_hz can read instead of the system clock, see [with_clock].
+pub trait Clock: Send + Sync {
+    /// The current time.
+    fn now(&self) -> Instant;
+
+    /// The current wall-clock time, read by `log_hz_on_nth_second!` (with the `wall-clock` feature).
+    ///
+    /// Defaults to the Unix epoch plus the time [now](Self::now) is past [process_start](crate::process_start), so
+    /// moving a [ManualClock] moves both, and a default `ManualClock` starts at 1 second past the epoch.
+    fn wall_now(&self) -> SystemTime {
+        UNIX_EPOCH + self.now().saturating_duration_since(crate::process_start())
+    }
+}
+
+/// A [Clock] that only moves when told to, including backwards.
+///
+/// Clones share the same time, so a test can keep one to move the clock while another is installed.
+///
+/// ```rust
+/// use log_hz::*;
+/// use std::time::Duration;
+///
+/// let clock = ManualClock::default();
+/// with_clock(clock.clone(), || {
+///     info_hz!(1.0, "logged");
+///     clock.advance(Duration::from_millis(500));
+///     info_hz!(1.0, "suppressed");
+/// });
+/// ```
+#[derive(Debug, Clone)]
+pub struct ManualClock(Arc<Mutex<Instant>>);
+
+impl ManualClock {
+    /// A clock stopped at `now`.
+    pub fn new(now: Instant) -> Self {
+        Self(Arc::new(Mutex::new(now)))
+    }
+
+    /// Move the clock to `now`, which may be earlier than its current time.
+    pub fn set(&self, now: Instant) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = now;
+    }
+
+    /// Move the clock forwards by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        *now += duration;
+    }
+
+    /// Move the clock backwards by `duration`, stopping at the earliest time the platform can represent.
+    pub fn rewind(&self, duration: Duration) {
+        let mut now = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(earlier) = now.checked_sub(duration) {
+            *now = earlier;
+        }
+    }
+}
+
+impl Default for ManualClock {
+    /// A clock stopped one second after [process_start](crate::process_start).
+    ///
+    /// Throttle timestamps of 0 are reserved, so a clock starting exactly at the process start would have its first
+    /// timestamp nudged by a nanosecond. Starting later keeps every interval exact.
+    fn default() -> Self {
+        Self::new(crate::process_start() + Duration::from_secs(1))
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Run `f` with every throttled call site on the current thread reading time from `clock`.
+///
+/// Times are measured from [process_start](crate::process_start), and a clock reading before it counts as no
+/// time having passed. The previous clock is restored when `f` returns or panics. Other threads are unaffected.
+pub fn with_clock<R>(clock: impl Clock + 'static, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<Arc<dyn Clock>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CLOCK.with(|clock| *clock.borrow_mut() = previous);
+        }
+    }
+
+    let _restore = Restore(CLOCK.with(|current| current.replace(Some(Arc::new(clock)))));
+    f()
+}
+
+/// The time from the clock installed on this thread by [with_clock], if any.
+pub(crate) fn clock_now() -> Option<Instant> {
+    CLOCK.with(|clock| clock.borrow().as_ref().map(|clock| clock.now()))
+}
+
+/// The wall-clock time from the clock installed on this thread by [with_clock], if any.
+#[cfg(feature = "wall-clock")]
+pub(crate) fn wall_clock_now() -> Option<SystemTime> {
+    CLOCK.with(|clock| clock.borrow().as_ref().map(|clock| clock.wall_now()))
+}
@@ -0,0 +1,572 @@
+//! A value-based throttle whose strategy is chosen at runtime.
+
+use crate::clock::now_ns;
+use crate::{ConstThrottleState, ThrottleState, interval_from_rate};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+/// How a [Throttler] decides which checks pass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    /// At most `rate` checks per second, like [log_hz!](crate::log_hz). A rate of 0 or less never passes.
+    Rate(f64),
+    /// Every `n`th check, starting with the first. An `n` of 0 passes every check.
+    EveryN(u64),
+    /// Bursts of up to `burst` checks, refilled at `rate` per second. The bucket starts full.
+    TokenBucket {
+        /// Tokens added per second. A rate of 0 or less never refills.
+        rate: f64,
+        /// The most tokens the bucket holds.
+        burst: u32,
+    },
+    /// At most `max` checks in each fixed `window`, starting from the first check.
+    Window {
+        /// The length of each window.
+        window: Duration,
+        /// Checks allowed per window.
+        max: u32,
+    },
+}
+
+/// The bookkeeping of the active strategy.
+#[derive(Debug)]
+enum StrategyState {
+    Rate { interval_ns: u64, last_ns: Option<u64> },
+    EveryN { n: u64, calls: u64 },
+    TokenBucket { interval_ns: u64, capacity_ns: u64, credit_ns: u64, last_ns: Option<u64> },
+    /// A token bucket that never refills, with the tokens left of its initial burst.
+    Burst { remaining: u32 },
+    Window { window_ns: u64, max: u32, start_ns: Option<u64>, count: u32 },
+}
+
+impl StrategyState {
+    fn new(strategy: Strategy) -> Self {
+        match strategy {
+            Strategy::Rate(rate) => Self::Rate {
+                interval_ns: interval_from_rate(rate),
+                last_ns: None,
+            },
+            Strategy::EveryN(n) => Self::EveryN { n, calls: 0 },
+            Strategy::TokenBucket { rate, burst } => {
+                // Tokens are kept as nanoseconds of credit, one interval per token, so refills are exact.
+                let interval_ns = interval_from_rate(rate);
+                if interval_ns == u64::MAX {
+                    return Self::Burst { remaining: burst };
+                }
+                let capacity_ns = interval_ns.saturating_mul(burst as u64);
+                Self::TokenBucket {
+                    interval_ns,
+                    capacity_ns,
+                    credit_ns: capacity_ns,
+                    last_ns: None,
+                }
+            }
+            Strategy::Window { window, max } => Self::Window {
+                window_ns: window.as_nanos().min(u64::MAX as u128) as u64,
+                max,
+                start_ns: None,
+                count: 0,
+            },
+        }
+    }
+
+    fn check(&mut self, now_ns: u64) -> bool {
+        match self {
+            Self::Rate { interval_ns, last_ns } => {
+                let pass = *interval_ns != u64::MAX
+                    && last_ns.is_none_or(|last_ns| now_ns.saturating_sub(last_ns) >= *interval_ns);
+                if pass {
+                    *last_ns = Some(now_ns);
+                }
+                pass
+            }
+            Self::EveryN { n, calls } => {
+                let pass = *n == 0 || *calls % *n == 0;
+                *calls += 1;
+                pass
+            }
+            Self::TokenBucket {
+                interval_ns,
+                capacity_ns,
+                credit_ns,
+                last_ns,
+            } => {
+                *credit_ns = refilled_credit(*credit_ns, *capacity_ns, *last_ns, now_ns);
+                *last_ns = Some(now_ns);
+                let pass = *credit_ns >= *interval_ns;
+                if pass {
+                    *credit_ns -= *interval_ns;
+                }
+                pass
+            }
+            Self::Burst { remaining } => {
+                let pass = *remaining > 0;
+                if pass {
+                    *remaining -= 1;
+                }
+                pass
+            }
+            Self::Window {
+                window_ns,
+                max,
+                start_ns,
+                count,
+            } => {
+                if start_ns.is_none_or(|start_ns| now_ns.saturating_sub(start_ns) >= *window_ns) {
+                    *start_ns = Some(now_ns);
+                    *count = 0;
+                }
+                let pass = *count < *max;
+                if pass {
+                    *count += 1;
+                }
+                pass
+            }
+        }
+    }
+
+    /// How many checks made now in a row would pass, without counting any of them.
+    fn available(&self, now_ns: u64) -> u32 {
+        match *self {
+            Self::Rate { interval_ns, last_ns } => {
+                (interval_ns != u64::MAX && last_ns.is_none_or(|last_ns| now_ns.saturating_sub(last_ns) >= interval_ns))
+                    as u32
+            }
+            Self::EveryN { n: 0, .. } => u32::MAX,
+            Self::EveryN { n, calls } => (calls % n == 0) as u32,
+            // An infinite rate refills instantly, so there is no limit
+            Self::TokenBucket { interval_ns: 0, .. } => u32::MAX,
+            Self::TokenBucket {
+                interval_ns,
+                capacity_ns,
+                credit_ns,
+                last_ns,
+            } => {
+                let tokens = refilled_credit(credit_ns, capacity_ns, last_ns, now_ns) / interval_ns;
+                tokens.min(u32::MAX as u64) as u32
+            }
+            Self::Burst { remaining } => remaining,
+            Self::Window {
+                window_ns,
+                max,
+                start_ns,
+                count,
+            } => {
+                if start_ns.is_none_or(|start_ns| now_ns.saturating_sub(start_ns) >= window_ns) {
+                    max
+                } else {
+                    max.saturating_sub(count)
+                }
+            }
+        }
+    }
+
+    /// Count up to `n` passing checks at once, returning how many were granted.
+    fn consume(&mut self, now_ns: u64, n: u32) -> u32 {
+        let granted = self.available(now_ns).min(n);
+        if granted == 0 {
+            return 0;
+        }
+        match self {
+            Self::Rate { last_ns, .. } => *last_ns = Some(now_ns),
+            Self::EveryN { calls, .. } => *calls += granted as u64,
+            Self::TokenBucket {
+                interval_ns,
+                capacity_ns,
+                credit_ns,
+                last_ns,
+            } => {
+                let credit = refilled_credit(*credit_ns, *capacity_ns, *last_ns, now_ns);
+                *credit_ns = credit - granted as u64 * *interval_ns;
+                *last_ns = Some(now_ns);
+            }
+            Self::Burst { remaining } => *remaining -= granted,
+            Self::Window {
+                window_ns,
+                start_ns,
+                count,
+                ..
+            } => {
+                if start_ns.is_none_or(|start_ns| now_ns.saturating_sub(start_ns) >= *window_ns) {
+                    *start_ns = Some(now_ns);
+                    *count = 0;
+                }
+                *count += granted;
+            }
+        }
+        granted
+    }
+}
+
+/// Token bucket credit after refilling it for the time since `last_ns`, capped at `capacity_ns`.
+///
+/// The cap is applied before adding, so a bucket left idle for any length of time holds exactly its capacity
+/// rather than overflowing (or, with float math, drifting above it).
+fn refilled_credit(credit_ns: u64, capacity_ns: u64, last_ns: Option<u64>, now_ns: u64) -> u64 {
+    let elapsed_ns = last_ns.map_or(0, |last_ns| now_ns.saturating_sub(last_ns));
+    if elapsed_ns >= capacity_ns - credit_ns {
+        capacity_ns
+    } else {
+        credit_ns + elapsed_ns
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    strategy: Strategy,
+    state: StrategyState,
+}
+
+/// A throttle covering all of the crate's modes behind one [check](Self::check), for library authors who want a
+/// single type that users can configure.
+///
+/// Unlike the macros, a `Throttler` is a value, so it can live in a struct or be created per connection, and its
+/// [Strategy] can be replaced while it is in use. Every check takes a lock, so it is slower than a macro's call site.
+///
+/// ```rust
+/// use log_hz::*;
+/// # #[cfg(feature = "no-log-reexport")] use log::warn;
+/// use std::time::Duration;
+///
+/// let throttler = Throttler::new(Strategy::TokenBucket { rate: 1.0, burst: 5 });
+/// if throttler.check() {
+///     warn!("Request rejected");
+/// }
+/// // Quieter during maintenance
+/// throttler.set_strategy(Strategy::Window { window: Duration::from_secs(60), max: 1 });
+/// ```
+///
+/// The strategies side by side, on a `ManualClock` from the `testing` feature:
+///
+#[cfg_attr(feature = "testing", doc = "```rust")]
+#[cfg_attr(not(feature = "testing"), doc = "```rust,ignore")]
+/// use log_hz::*;
+/// use std::time::Duration;
+///
+/// let clock = ManualClock::default();
+/// let bucket = Throttler::new(Strategy::TokenBucket { rate: 1.0, burst: 3 });
+/// let window = Throttler::new(Strategy::Window { window: Duration::from_secs(1), max: 2 });
+/// let passed = |throttler: &Throttler| with_clock(clock.clone(), || (0..10).filter(|_| throttler.check()).count());
+/// assert_eq!((passed(&bucket), passed(&window)), (3, 2));
+/// clock.advance(Duration::from_secs(1));
+/// // One token refilled, and a fresh window
+/// assert_eq!((passed(&bucket), passed(&window)), (1, 2));
+/// ```
+#[derive(Debug)]
+pub struct Throttler {
+    inner: Mutex<Inner>,
+}
+
+impl Throttler {
+    /// Create a throttler that hasn't been checked yet.
+    pub fn new(strategy: Strategy) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                strategy,
+                state: StrategyState::new(strategy),
+            }),
+        }
+    }
+
+    /// The active strategy.
+    pub fn strategy(&self) -> Strategy {
+        self.lock().strategy
+    }
+
+    /// Replace the strategy, starting it fresh as if the throttler was just created.
+    ///
+    /// The old strategy's bookkeeping doesn't carry over, so the next check is treated as the first one (and a
+    /// token bucket starts full).
+    pub fn set_strategy(&self, strategy: Strategy) {
+        *self.lock() = Inner {
+            strategy,
+            state: StrategyState::new(strategy),
+        };
+    }
+
+    /// The tokens a [Strategy::TokenBucket] holds right now, `None` for the other strategies.
+    ///
+    /// This doesn't count as a check, and never exceeds the bucket's `burst`.
+    pub fn available_tokens(&self) -> Option<u64> {
+        let now_ns = now_ns();
+        let inner = self.lock();
+        match (inner.strategy, &inner.state) {
+            // An infinite rate refills instantly, so the bucket is always full
+            (Strategy::TokenBucket { burst, .. }, StrategyState::TokenBucket { interval_ns: 0, .. }) => {
+                Some(burst as u64)
+            }
+            (
+                _,
+                &StrategyState::TokenBucket {
+                    interval_ns,
+                    capacity_ns,
+                    credit_ns,
+                    last_ns,
+                },
+            ) => Some(refilled_credit(credit_ns, capacity_ns, last_ns, now_ns) / interval_ns),
+            (_, &StrategyState::Burst { remaining }) => Some(remaining as u64),
+            _ => None,
+        }
+    }
+
+    /// How many checks made right now would pass, e.g. how many queued messages may be emitted in one go.
+    ///
+    /// This doesn't count as a check. A token bucket reports its tokens (or `u32::MAX` for an infinite rate) and a
+    /// window what is left of its `max`, while [Strategy::Rate] and [Strategy::EveryN] allow at most one (or any
+    /// number, for an `n` of 0).
+    pub fn available(&self) -> u32 {
+        let now_ns = now_ns();
+        self.lock().state.available(now_ns)
+    }
+
+    /// Count up to `n` passing checks at once, returning how many were granted, at most [available](Self::available).
+    ///
+    /// Checking and consuming happen under one lock, so concurrent callers never get more than was available
+    /// between them:
+    ///
+    /// ```rust
+    /// use log_hz::*;
+    /// # #[cfg(feature = "no-log-reexport")] use log::info;
+    ///
+    /// let throttler = Throttler::new(Strategy::TokenBucket { rate: 100.0, burst: 50 });
+    /// let mut queue: Vec<String> = (0..80).map(|i| format!("event {i}")).collect();
+    /// let granted = throttler.consume(queue.len() as u32) as usize;
+    /// for message in queue.drain(..granted) {
+    ///     info!("{message}");
+    /// }
+    /// ```
+    pub fn consume(&self, n: u32) -> u32 {
+        let now_ns = now_ns();
+        self.lock().state.consume(now_ns, n)
+    }
+
+    /// Returns true if a message should be emitted now, counting this check against the strategy.
+    pub fn check(&self) -> bool {
+        let now_ns = now_ns();
+        self.lock().state.check(now_ns)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Anything that decides on each check whether a message may be emitted, so code can be generic over how.
+///
+/// Implemented for a [Throttler] (whatever its [Strategy]), for a [ThrottleState] with the interval stored in it (see
+/// [ThrottleState::set_interval]), and for a [ConstThrottleState]. Each reads the process clock on every check.
+///
+/// ```rust
+/// use log_hz::*;
+/// # #[cfg(feature = "no-log-reexport")] use log::warn;
+///
+/// fn report(throttle: &impl Throttle, queued: usize) {
+///     if throttle.check() {
+///         warn!("{queued} jobs queued");
+///     }
+/// }
+///
+/// report(&ThrottleState::with_interval(interval_from_rate(1.0)), 10);
+/// report(&Throttler::new(Strategy::EveryN(100)), 10);
+/// ```
+pub trait Throttle {
+    /// Returns true if a message should be emitted now, counting this check against the throttle.
+    fn check(&self) -> bool;
+
+    /// Start over as if the throttle had never been checked.
+    fn reset(&self);
+}
+
+impl Throttle for Throttler {
+    fn check(&self) -> bool {
+        Throttler::check(self)
+    }
+
+    fn reset(&self) {
+        self.set_strategy(self.strategy());
+    }
+}
+
+impl Throttle for ThrottleState {
+    fn check(&self) -> bool {
+        ThrottleState::check(self, now_ns())
+    }
+
+    fn reset(&self) {
+        ThrottleState::reset(self);
+    }
+}
+
+impl<const INTERVAL_NS: u64> Throttle for ConstThrottleState<INTERVAL_NS> {
+    fn check(&self) -> bool {
+        self.should_log(now_ns())
+    }
+
+    fn reset(&self) {
+        self.state().reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ManualClock, with_clock};
+
+    /// Check `throttler` once every `step` for `calls` calls on a manual clock, returning which checks passed.
+    fn drive(throttler: &impl Throttle, clock: &ManualClock, calls: usize, step: Duration) -> Vec<bool> {
+        with_clock(clock.clone(), || {
+            (0..calls)
+                .map(|_| {
+                    let pass = throttler.check();
+                    clock.advance(step);
+                    pass
+                })
+                .collect()
+        })
+    }
+
+    fn passed(checks: &[bool]) -> Vec<usize> {
+        checks.iter().enumerate().filter(|(_, pass)| **pass).map(|(i, _)| i).collect()
+    }
+
+    #[test]
+    fn rate_passes_once_per_interval() {
+        let clock = ManualClock::default();
+        let throttler = Throttler::new(Strategy::Rate(10.0));
+        let checks = drive(&throttler, &clock, 30, Duration::from_millis(25));
+        assert_eq!(passed(&checks), [0, 4, 8, 12, 16, 20, 24, 28]);
+    }
+
+    #[test]
+    fn every_n_passes_every_nth_check() {
+        let clock = ManualClock::default();
+        let throttler = Throttler::new(Strategy::EveryN(3));
+        assert_eq!(passed(&drive(&throttler, &clock, 10, Duration::ZERO)), [0, 3, 6, 9]);
+        throttler.set_strategy(Strategy::EveryN(0));
+        assert_eq!(passed(&drive(&throttler, &clock, 3, Duration::ZERO)), [0, 1, 2]);
+    }
+
+    #[test]
+    fn token_bucket_allows_a_burst_then_the_refill_rate() {
+        let clock = ManualClock::default();
+        let throttler = Throttler::new(Strategy::TokenBucket { rate: 10.0, burst: 3 });
+        // 3 tokens up front, then one every 4 checks as 25ms steps refill 100ms tokens
+        let checks = drive(&throttler, &clock, 20, Duration::from_millis(25));
+        assert_eq!(passed(&checks), [0, 1, 2, 4, 8, 12, 16]);
+    }
+
+    #[test]
+    fn idle_token_bucket_is_capped_at_burst() {
+        let clock = ManualClock::default();
+        for rate in [10.0, 1e9] {
+            let throttler = Throttler::new(Strategy::TokenBucket { rate, burst: 5 });
+            with_clock(clock.clone(), || {
+                while throttler.check() {}
+                assert_eq!(throttler.available_tokens(), Some(0));
+                clock.advance(Duration::from_secs(60 * 60));
+                assert_eq!(throttler.available_tokens(), Some(5));
+            });
+            // Exactly a burst gets through, not more
+            assert_eq!(passed(&drive(&throttler, &clock, 10, Duration::ZERO)), [0, 1, 2, 3, 4]);
+        }
+        let unlimited = Throttler::new(Strategy::TokenBucket { rate: f64::INFINITY, burst: 5 });
+        assert!(unlimited.check());
+        assert_eq!(unlimited.available_tokens(), Some(5));
+        assert_eq!(Throttler::new(Strategy::EveryN(2)).available_tokens(), None);
+    }
+
+    #[test]
+    fn token_bucket_without_refill_allows_only_its_burst() {
+        let clock = ManualClock::default();
+        for rate in [0.0, -1.0] {
+            let throttler = Throttler::new(Strategy::TokenBucket { rate, burst: 3 });
+            assert_eq!(throttler.available_tokens(), Some(3));
+            let checks = drive(&throttler, &clock, 10, Duration::from_secs(60 * 60));
+            assert_eq!(passed(&checks), [0, 1, 2]);
+            assert_eq!(throttler.available_tokens(), Some(0));
+        }
+    }
+
+    #[test]
+    fn refill_never_overflows() {
+        assert_eq!(refilled_credit(u64::MAX - 1, u64::MAX, Some(0), u64::MAX), u64::MAX);
+        assert_eq!(refilled_credit(10, 100, Some(5), u64::MAX), 100);
+        assert_eq!(refilled_credit(10, 100, Some(5), 50), 55);
+    }
+
+    #[test]
+    fn window_passes_up_to_max_per_window() {
+        let clock = ManualClock::default();
+        let throttler = Throttler::new(Strategy::Window {
+            window: Duration::from_millis(100),
+            max: 2,
+        });
+        let checks = drive(&throttler, &clock, 12, Duration::from_millis(20));
+        assert_eq!(passed(&checks), [0, 1, 5, 6, 10, 11]);
+    }
+
+    #[test]
+    fn switching_strategy_mid_stream_starts_the_new_one_fresh() {
+        let clock = ManualClock::default();
+        let throttler = Throttler::new(Strategy::Rate(1.0));
+        assert_eq!(passed(&drive(&throttler, &clock, 10, Duration::from_millis(10))), [0]);
+
+        throttler.set_strategy(Strategy::EveryN(2));
+        assert_eq!(throttler.strategy(), Strategy::EveryN(2));
+        assert_eq!(passed(&drive(&throttler, &clock, 6, Duration::from_millis(10))), [0, 2, 4]);
+
+        throttler.set_strategy(Strategy::Rate(0.0));
+        assert!(passed(&drive(&throttler, &clock, 5, Duration::from_secs(10))).is_empty());
+    }
+
+    #[test]
+    fn strategies_are_interchangeable_through_the_trait() {
+        let clock = ManualClock::default();
+        let state = ThrottleState::with_interval(interval_from_rate(2.0));
+        let throttler = Throttler::new(Strategy::Rate(2.0));
+        let constant = ConstThrottleState::<{ interval_from_rate(2.0) }>::new();
+        let step = Duration::from_millis(100);
+        assert_eq!(passed(&drive(&state, &clock, 10, step)), [0, 5]);
+        assert_eq!(passed(&drive(&throttler, &clock, 10, step)), [0, 5]);
+        assert_eq!(passed(&drive(&constant, &clock, 10, step)), [0, 5]);
+
+        // Reset through the trait, each passes its next check straight away
+        for throttle in [&state as &dyn Throttle, &throttler, &constant] {
+            throttle.reset();
+            assert!(with_clock(clock.clone(), || throttle.check()));
+        }
+    }
+
+    #[test]
+    fn batches_are_drained_from_what_is_available() {
+        let clock = ManualClock::default();
+        let bucket = Throttler::new(Strategy::TokenBucket { rate: 10.0, burst: 20 });
+        let window = Throttler::new(Strategy::Window {
+            window: Duration::from_secs(1),
+            max: 8,
+        });
+        with_clock(clock.clone(), || {
+            assert_eq!((bucket.available(), window.available()), (20, 8));
+            assert_eq!((bucket.consume(12), window.consume(5)), (12, 5));
+            assert_eq!((bucket.available(), window.available()), (8, 3));
+            // Only what is left is granted
+            assert_eq!((bucket.consume(100), window.consume(100)), (8, 3));
+            assert_eq!((bucket.available(), window.available()), (0, 0));
+            assert!(!bucket.check() && !window.check());
+
+            // 5 tokens refilled, and a fresh window
+            clock.advance(Duration::from_millis(500));
+            assert_eq!(bucket.available(), 5);
+            clock.advance(Duration::from_millis(500));
+            assert_eq!((bucket.consume(3), window.consume(3)), (3, 3));
+            assert_eq!((bucket.available(), window.available()), (7, 5));
+        });
+
+        let unlimited = Throttler::new(Strategy::TokenBucket { rate: f64::INFINITY, burst: 5 });
+        assert_eq!((unlimited.available(), unlimited.consume(100), unlimited.available()), (u32::MAX, 100, u32::MAX));
+
+        let every_third = Throttler::new(Strategy::EveryN(3));
+        assert_eq!((every_third.consume(5), every_third.available()), (1, 0));
+        assert_eq!(Throttler::new(Strategy::EveryN(0)).consume(5), 5);
+    }
+}
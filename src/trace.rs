@@ -0,0 +1,119 @@
+//! A record of the most recent throttle decisions for post-mortem debugging, enabled by the `decision-trace` feature.
+
+use crate::ThrottleHandle;
+use std::sync::atomic::{AtomicU64, Ordering, fence};
+use std::sync::{OnceLock, PoisonError, RwLock};
+
+/// How many decisions the trace keeps, the oldest being overwritten first.
+pub const DECISION_TRACE_CAPACITY: usize = 1024;
+
+/// One throttle decision, returned by [dump_decision_trace].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    /// The source file of the call site.
+    pub file: &'static str,
+    /// The line of the call site.
+    pub line: u32,
+    /// When the call was made, in nanoseconds since [process_start](crate::process_start).
+    pub now_ns: u64,
+    /// Whether the call emitted its message.
+    pub emitted: bool,
+}
+
+/// The file and line of every site that recorded a decision, indexed by the site's id.
+static SITES: RwLock<Vec<(&'static str, u32)>> = RwLock::new(Vec::new());
+
+/// One entry of the trace, written without locks and validated by its sequence number on read.
+struct Slot {
+    /// The position in the trace of the decision in the slot plus one, or 0 while it is being written.
+    seq: AtomicU64,
+    /// The site's id shifted left by one, with the lowest bit set if the call emitted.
+    site_emitted: AtomicU64,
+    now_ns: AtomicU64,
+}
+
+static TRACE: [Slot; DECISION_TRACE_CAPACITY] = [const {
+    Slot {
+        seq: AtomicU64::new(0),
+        site_emitted: AtomicU64::new(0),
+        now_ns: AtomicU64::new(0),
+    }
+}; DECISION_TRACE_CAPACITY];
+
+/// The position in the trace of the next decision.
+static NEXT: AtomicU64 = AtomicU64::new(0);
+
+/// A call site's id in the trace, assigned on its first decision.
+///
+/// With the `decision-trace` feature, every `log_hz!` and `log_hz_dyn!` site owns one.
+#[doc(hidden)]
+#[derive(Debug, Default)]
+pub struct TraceSite {
+    id: OnceLock<u64>,
+}
+
+impl TraceSite {
+    pub const fn new() -> Self {
+        Self { id: OnceLock::new() }
+    }
+
+    /// Record that the site behind `handle` did or didn't emit a call at `now_ns`.
+    pub fn record(&self, handle: &ThrottleHandle, now_ns: u64, emitted: bool) {
+        let id = *self.id.get_or_init(|| {
+            let mut sites = SITES.write().unwrap_or_else(PoisonError::into_inner);
+            sites.push((handle.file(), handle.line()));
+            sites.len() as u64 - 1
+        });
+        let seq = NEXT.fetch_add(1, Ordering::Relaxed);
+        let slot = &TRACE[seq as usize % DECISION_TRACE_CAPACITY];
+        slot.seq.store(0, Ordering::Relaxed);
+        // Orders the invalidation above before the writes below, for readers that see the new values.
+        fence(Ordering::Release);
+        slot.site_emitted.store(id << 1 | emitted as u64, Ordering::Relaxed);
+        slot.now_ns.store(now_ns, Ordering::Relaxed);
+        slot.seq.store(seq + 1, Ordering::Release);
+    }
+}
+
+/// The most recent throttle decisions of every `log_hz!` and `log_hz_dyn!` call site, oldest first.
+///
+/// With the `decision-trace` feature, each call that reads the clock records whether it emitted in a global ring
+/// buffer of the last [DECISION_TRACE_CAPACITY] decisions, so the pattern of emitted and suppressed calls leading up
+/// to an intermittent problem can be dumped after the fact. Calls at a disabled level or rate aren't recorded.
+/// Recording takes a few atomic operations per call without locking, so the feature is off by default. Decisions
+/// being recorded while the trace is dumped may be left out.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// for _ in 0..3 {
+///     info_hz!(1.0, "Polling");
+/// }
+/// for decision in dump_decision_trace() {
+///     let outcome = if decision.emitted { "emitted" } else { "suppressed" };
+///     eprintln!("{}:{} at {}ns: {outcome}", decision.file, decision.line, decision.now_ns);
+/// }
+/// ```
+#[cfg(feature = "decision-trace")]
+pub fn dump_decision_trace() -> Vec<Decision> {
+    let end = NEXT.load(Ordering::Acquire);
+    let start = end.saturating_sub(DECISION_TRACE_CAPACITY as u64);
+    let sites = SITES.read().unwrap_or_else(PoisonError::into_inner);
+    (start..end)
+        .filter_map(|seq| {
+            let slot = &TRACE[seq as usize % DECISION_TRACE_CAPACITY];
+            if slot.seq.load(Ordering::Acquire) != seq + 1 {
+                return None;
+            }
+            let (site_emitted, now_ns) =
+                (slot.site_emitted.load(Ordering::Relaxed), slot.now_ns.load(Ordering::Relaxed));
+            // The values are only this decision's if the slot wasn't rewritten while they were read.
+            fence(Ordering::Acquire);
+            if slot.seq.load(Ordering::Relaxed) != seq + 1 {
+                return None;
+            }
+            let (file, line) = *sites.get((site_emitted >> 1) as usize)?;
+            Some(Decision { file, line, now_ns, emitted: site_emitted & 1 == 1 })
+        })
+        .collect()
+}
@@ -0,0 +1,1692 @@
+//! Throttled logging macros with policies beyond a plain rate limit.
+
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::{Mutex, PoisonError, RwLock};
+use std::time::Duration;
+
+fn default_suppressed_format(suppressed: u64) -> String {
+    format!("(suppressed {suppressed} since last)")
+}
+
+static SUPPRESSED_FORMAT: RwLock<fn(u64) -> String> = RwLock::new(default_suppressed_format);
+
+/// Set how [log_hz_smart!](crate::log_hz_smart) words the suppressed count it appends to throttled messages.
+///
+/// `format` gets the number of calls suppressed since the last message, and its text is appended after a space.
+/// Returning an empty string appends nothing. The default produces `(suppressed N since last)`.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// set_suppressed_format(|suppressed| format!("[{suppressed} übersprungen]"));
+/// ```
+pub fn set_suppressed_format(format: fn(u64) -> String) {
+    *SUPPRESSED_FORMAT.write().unwrap_or_else(PoisonError::into_inner) = format;
+}
+
+/// The text appended to a message for `suppressed` calls, including the separating space if there is any.
+pub fn suppressed_suffix(suppressed: u64) -> String {
+    let format = *SUPPRESSED_FORMAT.read().unwrap_or_else(PoisonError::into_inner);
+    match format(suppressed) {
+        text if text.is_empty() => text,
+        text => format!(" {text}"),
+    }
+}
+
+/// The rate in the environment variable `name`, or `default` if it is unset or not a number.
+pub fn rate_from_env(name: &str, default: f64) -> f64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|rate| rate.trim().parse::<f32>().ok())
+        .map_or(default, f64::from)
+}
+
+/// The rate of the first of `bands` whose threshold is above `value`, 0 (never log) if there is none.
+pub fn band_rate(value: f64, bands: &[(f64, f64)]) -> f64 {
+    bands.iter().find(|&&(threshold, _)| value < threshold).map_or(0.0, |&(_, rate)| rate)
+}
+
+/// A random offset of up to `fraction` of `interval_ns`, different in every process.
+pub fn jitter_offset(interval_ns: u64, fraction: f64) -> u64 {
+    // RandomState is seeded randomly per process (and varies per instance), which is all the randomness needed here.
+    scale_jitter(interval_ns, fraction, RandomState::new().hash_one(interval_ns))
+}
+
+/// Map `random` uniformly onto `[0, fraction * interval_ns)`.
+fn scale_jitter(interval_ns: u64, fraction: f64, random: u64) -> u64 {
+    let unit = (random >> 11) as f64 / (1u64 << 53) as f64;
+    (interval_ns as f64 * fraction.clamp(0.0, 1.0) * unit) as u64
+}
+
+/// The interval and location of the first site of each [log_hz_group!](crate::log_hz_group) group to run.
+static GROUP_INTERVALS: Mutex<BTreeMap<&'static str, (u64, &'static str, u32)>> = Mutex::new(BTreeMap::new());
+
+/// Panic if the site at `file`:`line` in `group` has a different interval than the group's first site.
+///
+/// Called once per site in debug builds.
+pub fn check_group_rate(group: Option<&'static str>, interval_ns: u64, file: &'static str, line: u32) {
+    let Some(group) = group else {
+        return;
+    };
+    let first = *GROUP_INTERVALS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(group)
+        .or_insert((interval_ns, file, line));
+    let (first_ns, first_file, first_line) = first;
+    assert!(
+        first_ns == interval_ns,
+        "log_hz_group! group {group:?} is logged every {:?} at {file}:{line}, \
+         but every {:?} at {first_file}:{first_line}",
+        Duration::from_nanos(interval_ns),
+        Duration::from_nanos(first_ns),
+    );
+}
+
+/// Log the first `first_n` calls immediately, then throttle to `rate`, reporting how many calls were suppressed.
+///
+/// This is the "do what I mean" macro for error storms: the start of a storm is fully visible, after which
+/// one message per interval is emitted with `(suppressed N since last)` appended. The appended text can be changed
+/// with [set_suppressed_format].
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// for i in 0..100 {
+///     // Logs iterations 0 to 4, then at most once per second with a count of what was skipped
+///     log_hz_smart!(5, 1.0, Level::Error, "Request {} failed", i);
+/// }
+/// ```
+///
+/// Driven by a `ManualClock` (with the `testing` feature), a storm of one failure every 100ms:
+///
+#[cfg_attr(feature = "testing", doc = "```rust")]
+#[cfg_attr(not(feature = "testing"), doc = "```rust,ignore")]
+/// use log_hz::*;
+/// use std::time::Duration;
+///
+/// let clock = ManualClock::default();
+/// let emitted = with_clock(clock.clone(), || {
+///     count_emissions(|| {
+///         for _ in 0..100 {
+///             log_hz_smart!(3, 1.0, Level::Error, "Request failed");
+///             clock.advance(Duration::from_millis(100));
+///         }
+///     })
+/// });
+/// // The first 3, then one a second for the rest of the 10 seconds
+/// assert_eq!(emitted, 3 + 9);
+/// ```
+#[macro_export]
+macro_rules! log_hz_smart {
+    ($first_n:expr, $rate:expr, $level:expr, $($arg:tt)+) => {
+        {
+            use std::sync::LazyLock;
+            use std::sync::atomic::{AtomicU64, Ordering};
+
+            static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| $crate::Rate::interval_ns($rate));
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+            // Calls that have claimed a slot in the initial burst, stops growing once the burst is used up.
+            static BURST: AtomicU64 = AtomicU64::new(0);
+
+            let (first_n, level): (u64, $crate::Level) = ($first_n as u64, $level);
+            if $crate::__private::level_enabled(level) {
+                let now_ns = $crate::__private::now_ns();
+                let mut suppressed = None;
+                if BURST.load(Ordering::Relaxed) < first_n && BURST.fetch_add(1, Ordering::Relaxed) < first_n {
+                    // Burst emissions skip the site's interval but not the process-wide limits, and restart the
+                    // interval, so throttling picks up from the last of them.
+                    if $crate::__private::site_allows(level, now_ns, 0, |interval_ns| match interval_ns {
+                        0 => STATE.force(now_ns),
+                        interval_ns => STATE.should_log(now_ns, interval_ns),
+                    }) {
+                        $crate::__private::emit_once(&STATE, || $crate::__log_hz_emit!(level, $($arg)+));
+                    }
+                } else if $crate::__private::site_allows(level, now_ns, *INTERVAL_NS, |interval_ns| {
+                    suppressed = STATE.try_log(now_ns, interval_ns);
+                    suppressed.is_some()
+                }) {
+                    let suffix = $crate::__private::suppressed_suffix(suppressed.unwrap_or(0));
+                    $crate::__private::emit_once(&STATE, || {
+                        $crate::__log_hz_emit!(level, "{}{}", format_args!($($arg)+), suffix)
+                    });
+                } else if let Some(count) = suppressed {
+                    // Held back by the process-wide limits after taking the count, so the next message reports it.
+                    $crate::__private::restore_suppressed(&STATE, count);
+                }
+            }
+        }
+    };
+}
+
+/// Log at a throttled rate, with context that is only gathered when a message is emitted.
+///
+/// The closure-like `|ctx| expr` is evaluated once per emitted message, after the throttle and level checks pass,
+/// and its value is bound to `ctx` for the format arguments. This is meant for structured context that is
+/// expensive to collect, like a queue depth behind a lock, and that several format arguments may use.
+///
+/// ```rust
+/// use log_hz::*;
+/// # struct Queue; impl Queue { fn depth(&self) -> usize { 0 } fn oldest_age_ms(&self) -> u64 { 0 } }
+/// # let queue = Queue;
+///
+/// struct Backlog {
+///     depth: usize,
+///     oldest_ms: u64,
+/// }
+///
+/// log_hz_with_context!(
+///     1.0,
+///     Level::Warn,
+///     |ctx| Backlog { depth: queue.depth(), oldest_ms: queue.oldest_age_ms() },
+///     "Queue backed up: {} items, oldest {}ms",
+///     ctx.depth,
+///     ctx.oldest_ms
+/// );
+/// ```
+#[macro_export]
+macro_rules! log_hz_with_context {
+    ($rate:expr, $level:expr, |$ctx:ident| $context:expr, $($arg:tt)+) => {
+        {
+            use std::sync::LazyLock;
+
+            static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| $crate::Rate::interval_ns($rate));
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level)
+                && {
+                    let now_ns = $crate::__private::now_ns();
+                    $crate::__private::site_allows(level, now_ns, *INTERVAL_NS, |interval_ns| {
+                        STATE.should_log(now_ns, interval_ns)
+                    })
+                }
+            {
+                $crate::__private::emit_once(&STATE, || {
+                    let $ctx = $context;
+                    $crate::__log_hz_emit!(level, $($arg)+);
+                });
+            }
+        }
+    };
+}
+
+/// Log at a throttled rate and return what was logged, for routing it somewhere else as well.
+///
+/// When a message is emitted, the macro evaluates to `Some((level, message))` with the formatted message, and the
+/// message is also logged as usual. When the call is throttled (or the level is disabled) it evaluates to `None`, and
+/// nothing is formatted or allocated.
+///
+/// ```rust
+/// use log_hz::*;
+/// # let mut alerts: Vec<String> = Vec::new();
+///
+/// if let Some((level, message)) = log_hz_capture!(1.0, Level::Error, "Disk {} is full", "/dev/sda1") {
+///     alerts.push(format!("{level}: {message}"));
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_hz_capture {
+    ($rate:expr, $level:expr, $($arg:tt)+) => {
+        {
+            use std::sync::LazyLock;
+
+            static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| $crate::Rate::interval_ns($rate));
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+
+            let level: $crate::Level = $level;
+            let mut captured = None;
+            if $crate::__private::level_enabled(level)
+                && {
+                    let now_ns = $crate::__private::now_ns();
+                    $crate::__private::site_allows(level, now_ns, *INTERVAL_NS, |interval_ns| {
+                        STATE.should_log(now_ns, interval_ns)
+                    })
+                }
+            {
+                $crate::__private::emit_once(&STATE, || {
+                    let message = format!($($arg)+);
+                    $crate::__log_hz_emit!(level, "{}", message);
+                    captured = Some((level, message));
+                });
+            }
+            captured
+        }
+    };
+}
+
+/// Log the first call after the site has been idle immediately, and throttle continuous calls to `rate`.
+///
+/// Meant for conditions that flap, like a connection that fails, recovers, and fails again: the first failure of each
+/// fresh burst is logged right away (an edge trigger), while a continuous stream of failures is throttled as usual.
+/// The site counts as idle once no call was made for the `idle` [Duration](std::time::Duration), a tenth of the
+/// interval if left out. The edge is detected from the gap between *calls*, not emissions, so a site that is called
+/// continuously never retriggers however long it has been throttled.
+///
+/// ```rust
+/// use log_hz::*;
+/// use std::time::Duration;
+///
+/// # let connected = || false;
+/// if !connected() {
+///     // At most every 10 seconds while failing, but immediately after 100ms without failures
+///     log_hz_edge!(0.1, idle: Duration::from_millis(100), Level::Warn, "Connection lost");
+///     // Same, with the idle gap defaulting to a tenth of the interval (1 second)
+///     log_hz_edge!(0.1, Level::Warn, "Connection lost");
+/// }
+/// ```
+///
+/// Each burst after a quiet spell is reported, however recent the last report. On a `ManualClock` (with the
+/// `testing` feature):
+///
+#[cfg_attr(feature = "testing", doc = "```rust")]
+#[cfg_attr(not(feature = "testing"), doc = "```rust,ignore")]
+/// use log_hz::*;
+/// use std::time::Duration;
+///
+/// let clock = ManualClock::default();
+/// let emitted = with_clock(clock.clone(), || {
+///     count_emissions(|| {
+///         for _ in 0..3 {
+///             for _ in 0..5 {
+///                 log_hz_edge!(0.1, idle: Duration::from_millis(100), Level::Warn, "Connection lost");
+///                 clock.advance(Duration::from_millis(10));
+///             }
+///             clock.advance(Duration::from_millis(200));
+///         }
+///     })
+/// });
+/// // One per burst, though the 10 second interval never passes
+/// assert_eq!(emitted, 3);
+/// ```
+#[macro_export]
+macro_rules! log_hz_edge {
+    (@site $rate:expr, $idle_ns:expr, $level:expr, $($arg:tt)+) => {
+        {
+            use std::sync::LazyLock;
+            use std::sync::atomic::{AtomicU64, Ordering};
+
+            static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| $crate::Rate::interval_ns($rate));
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+            // When the site was last called, 0 before the first call.
+            static LAST_CALL_NS: AtomicU64 = AtomicU64::new(0);
+
+            let (interval_ns, level): (u64, $crate::Level) = (*INTERVAL_NS, $level);
+            if $crate::__private::level_enabled(level) {
+                let now_ns = $crate::__private::now_ns().max(1);
+                let idle_ns: u64 = ($idle_ns)(interval_ns);
+                let last_call_ns = LAST_CALL_NS.swap(now_ns, Ordering::Relaxed);
+                let edge = last_call_ns == 0 || now_ns.saturating_sub(last_call_ns) >= idle_ns;
+                let emitted = if interval_ns != u64::MAX && edge {
+                    // The edge skips the site's interval but not the process-wide limits, and restarts the interval,
+                    // so the burst it starts is throttled from here.
+                    $crate::__private::site_allows(level, now_ns, 0, |interval_ns| match interval_ns {
+                        0 => STATE.force(now_ns),
+                        interval_ns => STATE.should_log(now_ns, interval_ns),
+                    })
+                } else {
+                    $crate::__private::site_allows(level, now_ns, interval_ns, |interval_ns| {
+                        STATE.should_log(now_ns, interval_ns)
+                    })
+                };
+                if emitted {
+                    $crate::__private::emit_once(&STATE, || $crate::__log_hz_emit!(level, $($arg)+));
+                }
+            }
+        }
+    };
+    ($rate:expr, idle: $idle:expr, $level:expr, $($arg:tt)+) => {
+        $crate::log_hz_edge!(
+            @site $rate,
+            |_| std::time::Duration::as_nanos(&$idle).min(u64::MAX as u128) as u64,
+            $level,
+            $($arg)+
+        )
+    };
+    ($rate:expr, $level:expr, $($arg:tt)+) => {
+        $crate::log_hz_edge!(@site $rate, |interval_ns: u64| interval_ns / 10, $level, $($arg)+)
+    };
+}
+
+/// Log at a throttled rate read from an environment variable, for tuning a single call site in the field.
+///
+/// The variable is read and parsed as an `f32` once, on the site's first call. If it is unset or can't be parsed,
+/// `default_rate` is used. Changing the variable later has no effect on a site that has already run.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// // Run with `MOTOR_STATUS_HZ=20` to see more of these
+/// log_hz_rate_from_env!("MOTOR_STATUS_HZ", 1.0, Level::Debug, "Motor current: {}A", 1.2);
+/// ```
+#[macro_export]
+macro_rules! log_hz_rate_from_env {
+    ($var:expr, $default_rate:expr, $level:expr, $($arg:tt)+) => {
+        {
+            static RATE: std::sync::LazyLock<f64> =
+                std::sync::LazyLock::new(|| $crate::__private::rate_from_env($var, $default_rate as f64));
+            $crate::log_hz!($level, *RATE, $($arg)+)
+        }
+    };
+}
+
+/// Log at a throttled rate derived from the level by a policy function, so one function governs every site.
+///
+/// `rate_fn` is a `const fn(Level) -> f32` (or any function of that shape), called with the site's level once, on the
+/// site's first call, like the rate of [log_hz!]. Libraries can keep their whole logging policy in it instead of
+/// spreading rates across call sites.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// const fn rate_for(level: Level) -> f32 {
+///     match level {
+///         Level::Error | Level::Warn => 5.0,
+///         _ => 0.5,
+///     }
+/// }
+///
+/// log_hz_with_rate_fn!(rate_for, Level::Error, "Sensor read failed");
+/// log_hz_with_rate_fn!(rate_for, Level::Info, "Sensor reading: {}", 21.5);
+/// ```
+#[macro_export]
+macro_rules! log_hz_with_rate_fn {
+    ($rate_fn:expr, $level:expr, $($arg:tt)+) => {
+        {
+            use std::sync::OnceLock;
+            use std::time::Duration;
+
+            // The interval for the site's level, set on its first call before the site reads it.
+            static INTERVAL: OnceLock<Duration> = OnceLock::new();
+
+            let level: $crate::Level = $level;
+            INTERVAL.get_or_init(|| Duration::from_nanos($crate::Rate::interval_ns(($rate_fn)(level))));
+            $crate::log_hz!(level, *INTERVAL.get().unwrap_or(&Duration::MAX), $($arg)+)
+        }
+    };
+}
+
+/// Log a value at a rate that depends on which band the value is in, e.g. more often as a battery runs low.
+///
+/// `bands` is a slice of `(threshold, rate)` pairs in increasing order of threshold, and the rate of the first band
+/// whose threshold is above the value applies. Values above every threshold (and NaN) aren't logged, so end with an
+/// infinite threshold to cover them. Like [log_hz_dyn!], the interval is recomputed when the band changes, and the
+/// next message is emitted once the new band's interval has passed since the last one. The value is appended as the
+/// last argument of the format string, like the error of [log_hz_try!](crate::log_hz_try).
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// # let battery_percent = 42.0;
+/// log_hz_banded!(
+///     battery_percent,
+///     &[(10.0, 1.0), (50.0, 0.1), (f64::INFINITY, 1.0 / 60.0)],
+///     Level::Info,
+///     "Battery at {}%"
+/// );
+/// ```
+#[macro_export]
+macro_rules! log_hz_banded {
+    ($value:expr, $bands:expr, $level:expr, $fmt:literal $(, $arg:expr)* $(,)?) => {
+        {
+            let value = $value;
+            $crate::log_hz_dyn!(
+                $level,
+                $crate::__private::band_rate(value as f64, $bands),
+                $fmt,
+                $($arg,)*
+                value
+            )
+        }
+    };
+}
+
+/// Log at a throttled rate like [log_hz!], binding the call site to a named group.
+///
+/// With the `registry` feature, every site in a group can be reset at once with [reset_group](crate::reset_group),
+/// e.g. to see all of the startup diagnostics again after a reconfiguration, and [group_stats](crate::group_stats)
+/// sums their counts. Groups are identified by a `&'static str` and need no setup. Without the feature the group
+/// is ignored and this behaves exactly like [log_hz!].
+///
+/// Each site still has its own throttle, so every site in a group should pass the same rate. Debug builds panic,
+/// naming both sites, when a site runs with a different rate than the first site of its group did.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// log_hz_group!("startup", Level::Info, 1.0, "Listening on port {}", 8080);
+/// ```
+#[macro_export]
+macro_rules! log_hz_group {
+    ($group:expr, $level:expr, $rate:expr, $($arg:tt)+) => {
+        {
+            $crate::log_hz!(@site Some($group), true, None, $level, $rate, $($arg)+);
+        }
+    };
+}
+
+/// Log at most once every `millis` milliseconds, for code that thinks in periods rather than rates.
+///
+/// The interval is computed with integer math, so `log_hz_ms!(100, ...)` waits exactly 100_000_000ns where a rate
+/// of `10.0` would go through a float reciprocal. Otherwise this behaves like [log_hz!], except that a period of 0
+/// logs every call. See [log_hz_us!](crate::log_hz_us) for microseconds.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// log_hz_ms!(250, Level::Info, "Logged at most every quarter second");
+/// ```
+#[macro_export]
+macro_rules! log_hz_ms {
+    ($millis:expr, $level:expr, $($arg:tt)+) => {
+        $crate::log_hz!($level, std::time::Duration::from_millis($millis), $($arg)+)
+    };
+}
+
+/// Log at most once every `micros` microseconds, like [log_hz_ms!](crate::log_hz_ms).
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// log_hz_us!(500, Level::Debug, "Logged at most every half millisecond");
+/// ```
+#[macro_export]
+macro_rules! log_hz_us {
+    ($micros:expr, $level:expr, $($arg:tt)+) => {
+        $crate::log_hz!($level, std::time::Duration::from_micros($micros), $($arg)+)
+    };
+}
+
+/// Log at a rate expressed as a fraction of a loop's rate: once every `divisor` iterations of a `loop_hz` loop.
+///
+/// This is sugar for [log_hz!] with a rate of `loop_hz / divisor`, which reads naturally next to the loop's
+/// declared rate. The throttle is still time based, so if the loop runs slower than `loop_hz` messages are
+/// spaced by time rather than by iteration count. A `divisor` of 0 logs every call.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// const LOOP_HZ: f64 = 100.0;
+/// for _ in 0..10 {
+///     // Once every 50 iterations of a 100 Hz loop, i.e. at 2 Hz
+///     log_hz_of_loop!(LOOP_HZ, 50, Level::Info, "Control loop running");
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_hz_of_loop {
+    ($loop_hz:expr, $divisor:expr, $level:expr, $($arg:tt)+) => {
+        $crate::log_hz!($level, ($loop_hz as f64) / ($divisor as f64), $($arg)+)
+    };
+}
+
+/// Log at a throttled rate using a [ThrottleState](crate::ThrottleState) you own, rather than one per call site.
+///
+/// A `log_hz!` in a method has a single static timer shared by every instance of the type. Embedding a
+/// `ThrottleState` in each instance and logging through it throttles every instance on its own, e.g. one message per
+/// second from each of several motors running their own loop. The state is borrowed, so it can be a field, a local,
+/// or an entry in a collection.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// struct Motor {
+///     id: u32,
+///     throttle: ThrottleState,
+/// }
+///
+/// impl Motor {
+///     fn update(&self, current: f32) {
+///         log_hz_on!(self.throttle, 1.0, Level::Info, "Motor {} current: {}A", self.id, current);
+///     }
+/// }
+///
+/// let motors = [Motor { id: 1, throttle: ThrottleState::new() }, Motor { id: 2, throttle: ThrottleState::new() }];
+/// for motor in &motors {
+///     motor.update(1.5); // Both log
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_hz_on {
+    ($state:expr, $rate:expr, $level:expr, $($arg:tt)+) => {
+        {
+            let state: &$crate::ThrottleState = &$state;
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level)
+                && {
+                    let now_ns = $crate::__private::now_ns();
+                    $crate::__private::site_allows(level, now_ns, $crate::Rate::interval_ns($rate), |interval_ns| {
+                        state.should_log(now_ns, interval_ns)
+                    })
+                }
+            {
+                $crate::__private::emit_once(state, || $crate::__log_hz_emit!(level, $($arg)+));
+            }
+        }
+    };
+}
+
+/// Log at a throttled rate from a `Drop` impl or other code that may run while the thread is unwinding.
+///
+/// Cleanup that fails in a loop can flood the log from a destructor, where [log_hz!] is risky: its lazily computed
+/// interval is poisoned by a panic during initialization, and the registry takes locks. This variant decides with
+/// atomics alone, recomputing the interval on each call, so a suppressed call neither allocates nor panics. For the
+/// same reason it isn't held back by the [backpressure signal](crate::set_backpressure_signal), which takes a lock
+/// and calls back into the application, while [set_max_hz](crate::set_max_hz) and the global level caps apply. A panic
+/// while formatting or logging an emitted message is caught, unless the thread is already unwinding, where a second
+/// panic aborts the process however it is handled. The site has no [ThrottleHandle](crate::ThrottleHandle) and
+/// doesn't register with the registry.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// struct Connection;
+///
+/// impl Drop for Connection {
+///     fn drop(&mut self) {
+///         log_hz_panic_guard!(1.0, Level::Warn, "Connection closed without a clean shutdown");
+///     }
+/// }
+///
+/// for _ in 0..100 {
+///     drop(Connection);
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_hz_panic_guard {
+    ($rate:expr, $level:expr, $($arg:tt)+) => {
+        {
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level)
+                && {
+                    let now_ns = $crate::__private::now_ns();
+                    let interval_ns = $crate::Rate::interval_ns($rate);
+                    $crate::__private::site_allows_without_backpressure(level, now_ns, interval_ns, |interval_ns| {
+                        STATE.should_log(now_ns, interval_ns)
+                    })
+                }
+            {
+                $crate::__private::emit_once(&STATE, || {
+                    $crate::__private::emit_guarded(|| $crate::__log_hz_emit!(level, $($arg)+))
+                });
+            }
+        }
+    };
+}
+
+/// Log a message through a [ThrottleState](crate::ThrottleState) regardless of its interval, restarting the interval.
+///
+/// Meant for on-demand messages, like a "log now" button in a debug UI, that share a throttle with
+/// [log_hz_on!] calls: the forced message goes out immediately, and the throttled calls resume a full interval after
+/// it rather than after their own last message. See [ThrottleState::force](crate::ThrottleState::force).
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// static STATUS: ThrottleState = ThrottleState::new();
+///
+/// fn tick(queued: usize) {
+///     log_hz_on!(STATUS, 0.1, Level::Info, "{} jobs queued", queued);
+/// }
+///
+/// fn on_debug_button(queued: usize) {
+///     force_log_hz!(STATUS, Level::Info, "{} jobs queued", queued);
+/// }
+/// # tick(1);
+/// # on_debug_button(2);
+/// ```
+#[macro_export]
+macro_rules! force_log_hz {
+    ($state:expr, $level:expr, $($arg:tt)+) => {
+        {
+            let state: &$crate::ThrottleState = &$state;
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level) && state.force($crate::__private::now_ns()) {
+                $crate::__private::emit_once(state, || $crate::__log_hz_emit!(level, $($arg)+));
+            }
+        }
+    };
+}
+
+/// Log at a throttled rate like [log_hz!], writing to stderr while no logger is installed.
+///
+/// Until a logger is installed `log` silently drops every message, so throttled diagnostics from early startup (e.g.
+/// a control loop waiting for hardware before logging is configured) would be lost. This macro writes them to stderr
+/// as `[LEVEL target] message` instead, and logs normally once a logger is installed. The throttle is shared between
+/// the two, so switching doesn't let an extra message through.
+///
+/// A logger counts as installed once the [max_level](crate::max_level) is no longer `Off`, as loggers raise it when
+/// they are installed. A program that installs a logger but keeps the maximum level at `Off` gets the stderr output.
+/// Messages are still filtered by [STATIC_MAX_LEVEL](crate::STATIC_MAX_LEVEL) either way.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// # let motor_ready = || true;
+/// while !motor_ready() {
+///     // Visible even if the logger is only set up after the motor
+///     log_hz_stderr_fallback!(Level::Warn, 1.0, "Waiting for the motor controller");
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_hz_stderr_fallback {
+    ($level:expr, $rate:expr, $($arg:tt)+) => {
+        {
+            use std::sync::LazyLock;
+
+            static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| $crate::Rate::interval_ns($rate));
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+
+            let level: $crate::Level = $level;
+            let installed = $crate::__private::logger_installed();
+            let enabled = if installed {
+                $crate::__private::level_enabled(level)
+            } else {
+                level <= $crate::__private::STATIC_MAX_LEVEL
+            };
+            if enabled
+                && {
+                    let now_ns = $crate::__private::now_ns();
+                    $crate::__private::site_allows(level, now_ns, *INTERVAL_NS, |interval_ns| {
+                        STATE.should_log(now_ns, interval_ns)
+                    })
+                }
+            {
+                $crate::__private::emit_once(&STATE, || {
+                    if installed {
+                        $crate::__log_hz_emit!(level, $($arg)+);
+                    } else {
+                        $crate::__private::write_to_stderr(level, module_path!(), format_args!($($arg)+));
+                    }
+                });
+            }
+        }
+    };
+}
+
+/// Log every `n`th call, but never faster than `max_rate`.
+///
+/// Both conditions must hold for a message to be emitted: at least `n` calls since the last emission, *and* at least
+/// `1 / max_rate` seconds since it. This is an AND of a count gate and a rate cap, so it is the stricter of the two:
+/// slow call rates are limited by the count and fast ones by the rate. (Emitting when *either* condition holds would
+/// instead let through whichever comes first.) As with the other macros the first call always logs.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// for item in 0..10_000 {
+///     // Progress every 100 items, but at most 5 times a second
+///     log_hz_every_nth_or_hz!(100, 5.0, Level::Info, "Processed {} items", item);
+/// }
+/// ```
+///
+/// Which limit applies depends on how fast items arrive, as a `ManualClock` (with the `testing` feature) shows:
+///
+#[cfg_attr(feature = "testing", doc = "```rust")]
+#[cfg_attr(not(feature = "testing"), doc = "```rust,ignore")]
+/// use log_hz::*;
+/// use std::time::Duration;
+///
+/// let clock = ManualClock::default();
+/// let mut progress = |step| {
+///     with_clock(clock.clone(), || {
+///         count_emissions(|| {
+///             for _ in 0..1_000 {
+///                 log_hz_every_nth_or_hz!(100, 5.0, Level::Info, "Progress");
+///                 clock.advance(step);
+///             }
+///         })
+///     })
+/// };
+/// // 1000 items in a second are held to 5Hz, and in 100 seconds to one per 100 items
+/// assert_eq!(progress(Duration::from_millis(1)), 5);
+/// assert_eq!(progress(Duration::from_millis(100)), 10);
+/// ```
+#[macro_export]
+macro_rules! log_hz_every_nth_or_hz {
+    ($n:expr, $max_rate:expr, $level:expr, $($arg:tt)+) => {
+        {
+            use std::sync::LazyLock;
+            use std::sync::atomic::{AtomicU64, Ordering};
+
+            static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| $crate::Rate::interval_ns($max_rate));
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+            // Calls since the last emission, including this one.
+            static CALLS: AtomicU64 = AtomicU64::new(0);
+
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level) {
+                let calls = CALLS.fetch_add(1, Ordering::Relaxed) + 1;
+                if (calls >= $n as u64 || STATE.last_log_ns().is_none()) && {
+                    let now_ns = $crate::__private::now_ns();
+                    $crate::__private::site_allows(level, now_ns, *INTERVAL_NS, |interval_ns| {
+                        STATE.should_log(now_ns, interval_ns)
+                    })
+                } {
+                    // Subtract rather than reset, so calls counted by other threads since ours aren't lost.
+                    // Saturating, as a racing emission may already have subtracted some of the calls ours counted.
+                    let _ = CALLS.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                        Some(count.saturating_sub(calls))
+                    });
+                    $crate::__private::emit_once(&STATE, || $crate::__log_hz_emit!(level, $($arg)+));
+                }
+            }
+        }
+    };
+}
+
+/// Log one in every `denominator` calls, counting calls rather than time.
+///
+/// The site keeps a single counter and emits when `count % denominator == 0`, so the first call logs and exactly
+/// every `denominator`th call after it does too. Unlike random sampling the selection is fully reproducible, and it
+/// never reads the clock or allocates. A `denominator` of 0 or 1 logs every call.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// for request in 0..1_000 {
+///     // Requests 0, 4, 8, ...
+///     log_hz_ratio!(4, Level::Debug, "Handled request {}", request);
+/// }
+/// ```
+///
+/// Calls arriving in a burst can still emit several messages microseconds apart. Passing `min_gap:` (a
+/// [Duration](std::time::Duration)) after the denominator drops a selected call if less than `min_gap` has passed
+/// since the last message, so messages are never closer together than that. The dropped call isn't made up for, the
+/// next one selected is the next `denominator`th call as usual. Only selected calls read the clock:
+///
+/// ```rust
+/// use log_hz::*;
+/// use std::time::Duration;
+///
+/// for request in 0..1_000 {
+///     // Requests 0, 4, 8, ... unless the previous message was less than 10ms ago
+///     log_hz_ratio!(4, min_gap: Duration::from_millis(10), Level::Debug, "Handled request {}", request);
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_hz_ratio {
+    ($denominator:expr, min_gap: $min_gap:expr, $level:expr, $($arg:tt)+) => {
+        {
+            use std::sync::atomic::{AtomicU64, Ordering};
+
+            // Calls made at this site so far.
+            static COUNT: AtomicU64 = AtomicU64::new(0);
+            // The timestamp of the last message, to keep the next one `min_gap` away.
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+
+            let (denominator, level): (u64, $crate::Level) = ($denominator as u64, $level);
+            if $crate::__private::level_enabled(level) && {
+                let count = COUNT.fetch_add(1, Ordering::Relaxed);
+                denominator <= 1 || count % denominator == 0
+            } {
+                let gap_ns = std::time::Duration::as_nanos(&$min_gap).min(u64::MAX as u128) as u64;
+                let now_ns = $crate::__private::now_ns();
+                if $crate::__private::site_allows(level, now_ns, gap_ns, |gap_ns| STATE.should_log(now_ns, gap_ns)) {
+                    $crate::__private::emit_once(&STATE, || $crate::__log_hz_emit!(level, $($arg)+));
+                }
+            }
+        }
+    };
+    ($denominator:expr, $level:expr, $($arg:tt)+) => {
+        {
+            use std::sync::atomic::{AtomicU64, Ordering};
+
+            // Calls made at this site so far.
+            static COUNT: AtomicU64 = AtomicU64::new(0);
+
+            let (denominator, level): (u64, $crate::Level) = ($denominator as u64, $level);
+            if $crate::__private::level_enabled(level) && {
+                let count = COUNT.fetch_add(1, Ordering::Relaxed);
+                denominator <= 1 || count % denominator == 0
+            } {
+                $crate::__private::emit_once(&COUNT, || $crate::__log_hz_emit!(level, $($arg)+));
+            }
+        }
+    };
+}
+
+/// Log the messages whose sequence number is a multiple of `k`, for pipelines that number their messages.
+///
+/// Like [log_hz_ratio!], but the sequence number is supplied rather than counted at the site, so the same
+/// messages are logged however many times each is processed, and the site keeps no state at all. `seq` can be any
+/// integer castable to `u64` with `as`. Sequence numbers that skip or go backwards are fine, as only the modulo is
+/// checked. A `k` of 0 or 1 logs every message.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// # let batch = [(41_u64, "a"), (42, "b")];
+/// for (seq, message) in batch {
+///     // Messages 0, 100, 200, ...
+///     log_hz_seq!(seq, 100, Level::Debug, "Processed message {}: {}", seq, message);
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_hz_seq {
+    ($seq:expr, $k:expr, $level:expr, $($arg:tt)+) => {
+        {
+            // Identifies the site to the re-entry guard, as it keeps no state.
+            static SITE: u8 = 0;
+
+            let (k, level): (u64, $crate::Level) = ($k as u64, $level);
+            if $crate::__private::level_enabled(level) && (k <= 1 || ($seq as u64) % k == 0) {
+                $crate::__private::emit_once(&SITE, || $crate::__log_hz_emit!(level, $($arg)+));
+            }
+        }
+    };
+}
+
+/// Log at a throttled rate fixed at compile time, with the leanest possible call site.
+///
+/// Unlike [log_hz!], the interval is computed in a constant and carried in the type of the site's
+/// [ConstThrottleState](crate::ConstThrottleState), so the site has no `LazyLock` for it and no initialization check
+/// on each call. In exchange the rate must be a constant expression (a literal, a `const`, or a `const fn` call, but
+/// not a `static`), and the site has no [ThrottleHandle](crate::ThrottleHandle) and doesn't register with the registry.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// const RATE: f64 = 10.0;
+/// for _ in 0..10 {
+///     log_hz_const!(Level::Info, RATE, "Hello, world!");
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_hz_const {
+    ($level:expr, $rate:expr, $($arg:tt)+) => {
+        {
+            $crate::__log_hz_rate_ceiling!($rate);
+            static STATE: $crate::ConstThrottleState<{ $crate::interval_from_rate($rate as f64) }> =
+                $crate::ConstThrottleState::new();
+
+            let (interval_ns, level): (u64, $crate::Level) = ($crate::interval_from_rate($rate as f64), $level);
+            if $crate::__private::level_enabled(level) && {
+                let now_ns = $crate::__private::now_ns();
+                $crate::__private::site_allows(level, now_ns, interval_ns, |interval_ns| {
+                    STATE.state().should_log(now_ns, interval_ns)
+                })
+            } {
+                $crate::__private::emit_once(STATE.state(), || $crate::__log_hz_emit!(level, $($arg)+));
+            }
+        }
+    };
+}
+
+/// Log at a throttled rate, with each call site's emissions shifted by a random fraction of the interval.
+///
+/// When many identical processes log at the same rate, their messages arrive at the log aggregator in bursts.
+/// Each site picks a random offset of up to `jitter_fraction` (0 to 1) of the interval once, and its first message
+/// is delayed by that much. Later messages keep the plain interval, so the cadence and average rate are unchanged
+/// and only the phase differs between processes. A `jitter_fraction` of 0 behaves like [log_hz!].
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// for _ in 0..10 {
+///     // 1Hz, with the first message anywhere up to half a second after the first call
+///     log_hz_jittered!(1.0, 0.5, Level::Info, "Heartbeat");
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_hz_jittered {
+    ($rate:expr, $jitter_fraction:expr, $level:expr, $($arg:tt)+) => {
+        {
+            use std::sync::LazyLock;
+
+            static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| $crate::Rate::interval_ns($rate));
+            // Chosen once, so the cadence stays smooth after the first message.
+            static OFFSET_NS: LazyLock<u64> =
+                LazyLock::new(|| $crate::__private::jitter_offset(*INTERVAL_NS, $jitter_fraction as f64));
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+
+            // The throttle runs on a clock one interval ahead of `now_ns`, so that starting the timer at the
+            // first call plus the offset schedules the first message `OFFSET_NS` after that call. Only the first of
+            // racing first calls starts it, so a later one can't push back a message that was already emitted.
+            let (interval_ns, level): (u64, $crate::Level) = (*INTERVAL_NS, $level);
+            if $crate::__private::level_enabled(level) {
+                let now_ns = $crate::__private::now_ns().max(1);
+                if STATE.last_log_ns().is_none() {
+                    $crate::__private::seed_last_log(&STATE, now_ns.saturating_add(*OFFSET_NS));
+                }
+                if $crate::__private::site_allows(level, now_ns, interval_ns, |interval_ns| {
+                    STATE.should_log(now_ns.saturating_add(interval_ns), interval_ns)
+                }) {
+                    $crate::__private::emit_once(&STATE, || $crate::__log_hz_emit!(level, $($arg)+));
+                }
+            }
+        }
+    };
+}
+
+/// Log the error of a `Result` at a throttled rate, passing the `Result` through unchanged.
+///
+/// The error is appended as the last argument of the format string, so `"context {}"` prints it after the context.
+/// An `Ok` logs nothing and doesn't use up the site's slot. The `Result` is returned as is, so it can still be
+/// handled with `?` or a `match`.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// fn get_io_pin() -> Result<u8, String> {
+///     Err("Your IO Device Isn't Connected!".to_string())
+/// }
+///
+/// fn read(pin: u8) -> Result<u8, String> {
+///     let state = log_hz_try!(1.0, Level::Error, get_io_pin(), "Failed to read IO pin {}: {}", pin)?;
+///     Ok(state)
+/// }
+/// # assert!(read(3).is_err());
+/// ```
+#[macro_export]
+macro_rules! log_hz_try {
+    ($rate:expr, $level:expr, $result:expr, $fmt:literal $(, $arg:expr)* $(,)?) => {
+        {
+            let result = $result;
+            if let Err(error) = &result {
+                $crate::log_hz!($level, $rate, $fmt, $($arg,)* error);
+            }
+            result
+        }
+    };
+}
+
+/// The decision behind one [log_hz_probe!](crate::log_hz_probe) call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThrottleProbe {
+    /// Whether the call emitted its message.
+    pub emitted: bool,
+    /// Nanoseconds from the previous emission to the call.
+    pub elapsed_ns: u64,
+    /// The interval the call had to wait since the previous emission to be allowed through.
+    pub interval_ns: u64,
+    /// When the site last emitted before the call, in nanoseconds since [process_start](crate::process_start), or 0 if
+    /// it never had.
+    pub last_log_ns: u64,
+}
+
+/// Log at a throttled rate like [log_hz!], returning a [ThrottleProbe] that explains why the call did or didn't emit.
+///
+/// Meant for troubleshooting a site that logs less (or more) than expected: swap it in for the site's `log_hz!`,
+/// and compare `elapsed_ns` with `interval_ns`. A call at a disabled level reports `emitted: false` whatever the
+/// timing.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// let probe = log_hz_probe!(1.0, Level::Info, "Polling");
+/// if !probe.emitted {
+///     eprintln!("throttled: {}ns of {}ns elapsed", probe.elapsed_ns, probe.interval_ns);
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_hz_probe {
+    ($rate:expr, $level:expr, $($arg:tt)+) => {
+        {
+            use std::sync::LazyLock;
+
+            static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| $crate::Rate::interval_ns($rate));
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+
+            let level: $crate::Level = $level;
+            let (now_ns, interval_ns) = ($crate::__private::now_ns(), *INTERVAL_NS);
+            let last_log_ns = STATE.last_log_ns().unwrap_or(0);
+            let interval_ns = $crate::__private::limit_interval(interval_ns);
+            let emitted = $crate::__private::level_enabled(level)
+                && $crate::__private::site_allows(level, now_ns, interval_ns, |interval_ns| {
+                    STATE.should_log(now_ns, interval_ns)
+                });
+            if emitted {
+                $crate::__private::emit_once(&STATE, || $crate::__log_hz_emit!(level, $($arg)+));
+            }
+            $crate::ThrottleProbe { emitted, elapsed_ns: now_ns.saturating_sub(last_log_ns), interval_ns, last_log_ns }
+        }
+    };
+}
+
+/// Log at a throttled rate at `base_level`, escalating to `escalate_level` when more than `threshold` calls were
+/// suppressed since the last message.
+///
+/// A site that logs an occasional `Info` line can turn into a storm worth a `Warn`: the number of calls suppressed
+/// in between says which it is, so each emitted message picks its level from that count. The first message is always
+/// at `base_level`. If `base_level` is disabled, only escalated messages are emitted.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// # let retry = 3;
+/// // Info normally, but Warn if over 100 retries were skipped in the last second
+/// log_hz_escalate!(1.0, Level::Info, Level::Warn, 100, "Retrying request (attempt {})", retry);
+/// ```
+#[macro_export]
+macro_rules! log_hz_escalate {
+    ($rate:expr, $base_level:expr, $escalate_level:expr, $threshold:expr, $($arg:tt)+) => {
+        {
+            use std::sync::LazyLock;
+
+            static INTERVAL_NS: LazyLock<u64> = LazyLock::new(|| $crate::Rate::interval_ns($rate));
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+
+            let (base_level, escalate_level): ($crate::Level, $crate::Level) = ($base_level, $escalate_level);
+            if $crate::__private::level_enabled(base_level) || $crate::__private::level_enabled(escalate_level) {
+                // Picked from the calls suppressed so far, so the process-wide limits see the level of the message.
+                let level = if STATE.suppressed_count() > $threshold as u64 { escalate_level } else { base_level };
+                if !$crate::__private::level_enabled(level) {
+                    // A disabled base level leaves the interval to escalated messages, but still counts towards them.
+                    $crate::__private::count_suppressed(&STATE);
+                } else if {
+                    let now_ns = $crate::__private::now_ns();
+                    $crate::__private::site_allows(level, now_ns, *INTERVAL_NS, |interval_ns| {
+                        STATE.should_log(now_ns, interval_ns)
+                    })
+                } {
+                    $crate::__private::emit_once(&STATE, || $crate::__log_hz_emit!(level, $($arg)+));
+                }
+            }
+        }
+    };
+}
+
+/// Log a message at the specified level at most once every `interval`, a [Duration](std::time::Duration) that is
+/// evaluated on every call.
+///
+/// Like [log_hz_dyn!](crate::log_hz_dyn), but for code that already thinks in intervals rather than rates, e.g. an
+/// adaptive control loop that logs once per period of its own. The interval is converted on each call without any
+/// caching, as it may legitimately change every time. An interval of [Duration::ZERO](std::time::Duration::ZERO) logs
+/// every call.
+///
+/// ```rust
+/// use log_hz::*;
+/// use std::time::Duration;
+///
+/// let mut period = Duration::from_millis(100);
+/// for _ in 0..10 {
+///     log_every_dyn!(period * 10, Level::Info, "Control loop running every {:?}", period);
+///     period /= 2;
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_every_dyn {
+    ($interval:expr, $level:expr, $($arg:tt)+) => {
+        {
+            static STATE: $crate::ThrottleState = $crate::ThrottleState::new();
+
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level) {
+                let interval_ns = std::time::Duration::as_nanos(&$interval).min(u64::MAX as u128) as u64;
+                let now_ns = $crate::__private::now_ns();
+                // A zero interval always logs, even when another thread is emitting at the same instant.
+                let emitted = $crate::__private::site_allows(level, now_ns, interval_ns, |interval_ns| {
+                    match interval_ns {
+                        0 => STATE.force(now_ns),
+                        interval_ns => STATE.should_log(now_ns, interval_ns),
+                    }
+                });
+                if emitted {
+                    $crate::__private::emit_once(&STATE, || $crate::__log_hz_emit!(level, $($arg)+));
+                }
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    // The strict rate ceiling would space out the burst by 1ms.
+    #[cfg(not(feature = "strict-rate-ceiling"))]
+    #[test]
+    fn smart_logs_burst_then_throttles_with_counts() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        let log = |i: u32| log_hz_smart!(5, 10.0, Level::Error, "failure {}", i);
+        with_clock(clock.clone(), || {
+            for i in 0..12 {
+                log(i);
+            }
+            clock.advance(std::time::Duration::from_millis(100));
+            log(12);
+            log(13);
+            clock.advance(std::time::Duration::from_millis(100));
+            log(14);
+        });
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(
+                bodies,
+                [
+                    "failure 0",
+                    "failure 1",
+                    "failure 2",
+                    "failure 3",
+                    "failure 4",
+                    "failure 12 (suppressed 7 since last)",
+                    "failure 14 (suppressed 1 since last)",
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn context_is_gathered_once_per_emission() {
+        testing_logger::setup();
+        let gathered = std::cell::Cell::new(0);
+        let log = || {
+            log_hz_with_context!(
+                10.0,
+                Level::Info,
+                |ctx| {
+                    gathered.set(gathered.get() + 1);
+                    gathered.get()
+                },
+                "context {}",
+                ctx
+            );
+        };
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            for _ in 0..5 {
+                log();
+            }
+            clock.advance(std::time::Duration::from_millis(100));
+            for _ in 0..5 {
+                log();
+            }
+        });
+        assert_eq!(gathered.get(), 2);
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["context 1", "context 2"]);
+        });
+    }
+
+    #[test]
+    fn edge_logs_each_fresh_burst_immediately() {
+        testing_logger::setup();
+        let log = || {
+            log_hz_edge!(1.0, idle: std::time::Duration::from_millis(20), Level::Warn, "connection lost");
+        };
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            for _ in 0..3 {
+                for _ in 0..5 {
+                    log();
+                    clock.advance(std::time::Duration::from_millis(1));
+                }
+                // The connection is back for a while
+                clock.advance(std::time::Duration::from_millis(50));
+            }
+        });
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 3));
+
+        // Two seconds of continuous failures never go idle, so they are throttled to 1 Hz
+        with_clock(clock.clone(), || {
+            for _ in 0..1_000 {
+                log();
+                clock.advance(std::time::Duration::from_millis(2));
+            }
+        });
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 2));
+    }
+
+    #[test]
+    fn of_loop_matches_equivalent_rate() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            // 2 seconds of a 100Hz loop
+            for _ in 0..200 {
+                log_hz_of_loop!(100.0, 50, Level::Info, "of_loop");
+                info_hz!(2.0, "hz");
+                clock.advance(std::time::Duration::from_millis(10));
+            }
+        });
+        testing_logger::validate(|captured_logs| {
+            let of_loop = captured_logs.iter().filter(|log| log.body == "of_loop").count();
+            let hz = captured_logs.iter().filter(|log| log.body == "hz").count();
+            assert_eq!((of_loop, hz), (4, 4));
+        });
+    }
+
+    #[test]
+    fn jitter_is_bounded_by_the_fraction() {
+        assert_eq!(scale_jitter(1_000, 0.5, 0), 0);
+        assert_eq!(scale_jitter(1_000, 0.5, u64::MAX), 499);
+        assert_eq!(scale_jitter(1_000, 0.5, u64::MAX / 2), 249);
+        assert_eq!(scale_jitter(1_000, 0.0, u64::MAX), 0);
+        assert_eq!(scale_jitter(1_000, 7.0, u64::MAX), 999);
+        assert!(jitter_offset(1_000, 1.0) < 1_000);
+    }
+
+    #[test]
+    fn jitter_shifts_the_phase_but_keeps_the_rate() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            for elapsed_ms in 0..500 {
+                log_hz_jittered!(10.0, 0.5, Level::Info, "jittered {}", elapsed_ms);
+                log_hz_jittered!(10.0, 0.0, Level::Info, "plain {}", elapsed_ms);
+                clock.advance(std::time::Duration::from_millis(1));
+            }
+        });
+        testing_logger::validate(|captured_logs| {
+            let times = |prefix: &str| -> Vec<u64> {
+                captured_logs
+                    .iter()
+                    .filter_map(|log| log.body.strip_prefix(prefix)?.parse().ok())
+                    .collect()
+            };
+            let (jittered, plain) = (times("jittered "), times("plain "));
+            // Without jitter the first call logs
+            assert_eq!(plain[0], 0);
+            // The offset is under 50ms, but the first call at or after it can be on the 50ms step
+            assert!(jittered[0] <= 50);
+            assert!(jittered.len().abs_diff(plain.len()) <= 1);
+            assert!(jittered.windows(2).all(|pair| pair[1] - pair[0] == 100), "{jittered:?}");
+        });
+    }
+
+    #[test]
+    fn capture_returns_the_emitted_message() {
+        testing_logger::setup();
+        let captured: Vec<_> = (0..5)
+            .map(|i| log_hz_capture!(1.0, Level::Warn, "captured {}", i))
+            .collect();
+        assert_eq!(captured[0], Some((Level::Warn, "captured 0".to_string())));
+        assert!(captured[1..].iter().all(Option::is_none));
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(captured_logs.len(), 1);
+            assert_eq!((captured_logs[0].level, captured_logs[0].body.as_str()), (Level::Warn, "captured 0"));
+        });
+    }
+
+    #[test]
+    fn ratio_logs_exactly_one_in_denominator() {
+        testing_logger::setup();
+        let log = |denominator: u64, i: u32| log_hz_ratio!(denominator, Level::Info, "call {}", i);
+        for i in 0..100 {
+            log(4, i);
+        }
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(captured_logs.len(), 25);
+            assert_eq!(captured_logs[1].body, "call 4");
+        });
+        for denominator in [0, 1] {
+            let log = || log_hz_ratio!(denominator, Level::Info, "every call");
+            for _ in 0..10 {
+                log();
+            }
+        }
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 20));
+    }
+
+    #[test]
+    fn ratio_min_gap_drops_bursts_across_the_denominator() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        let log = |i: u32| log_hz_ratio!(4, min_gap: std::time::Duration::from_millis(100), Level::Info, "call {}", i);
+        with_clock(clock.clone(), || {
+            // A burst selecting calls 0 and 4 at once, and call 8 shortly after
+            for i in 0..8 {
+                log(i);
+            }
+            clock.advance(std::time::Duration::from_millis(50));
+            for i in 8..12 {
+                log(i);
+            }
+            clock.advance(std::time::Duration::from_millis(50));
+            for i in 12..16 {
+                log(i);
+            }
+        });
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["call 0", "call 12"]);
+        });
+    }
+
+    #[test]
+    fn seq_logs_multiples_of_k() {
+        testing_logger::setup();
+        let log = |seq: u64, k: u64| log_hz_seq!(seq, k, Level::Info, "seq {}", seq);
+        // A gap from 13 to 27, then a retry of 30 and a sequence going backwards
+        for seq in (0..=13).chain(27..=31).chain([30, 20]) {
+            log(seq, 5);
+        }
+        log(7, 0);
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["seq 0", "seq 5", "seq 10", "seq 30", "seq 30", "seq 20", "seq 7"]);
+        });
+    }
+
+    #[test]
+    fn stateless_site_is_not_reentered_while_emitting() {
+        /// Logs from the site it is formatted for, which selects every call.
+        struct Reentrant;
+
+        impl std::fmt::Display for Reentrant {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                log_seq();
+                f.write_str("outer")
+            }
+        }
+
+        fn log_seq() {
+            log_hz_seq!(0, 1, Level::Info, "seq {}", Reentrant);
+        }
+
+        testing_logger::setup();
+        log_seq();
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["seq outer"]);
+        });
+    }
+
+    #[test]
+    fn instances_throttle_independently() {
+        struct Worker {
+            name: &'static str,
+            throttle: ThrottleState,
+        }
+
+        impl Worker {
+            fn tick(&self) {
+                log_hz_on!(self.throttle, 10.0, Level::Info, "{} tick", self.name);
+            }
+        }
+
+        testing_logger::setup();
+        let (a, b) = (
+            Worker { name: "a", throttle: ThrottleState::new() },
+            Worker { name: "b", throttle: ThrottleState::new() },
+        );
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            for _ in 0..5 {
+                a.tick();
+                b.tick();
+            }
+            clock.advance(std::time::Duration::from_millis(100));
+            a.tick();
+            a.tick();
+        });
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["a tick", "b tick", "a tick"]);
+        });
+        assert_eq!((a.throttle.total_suppressed(), b.throttle.total_suppressed()), (5, 4));
+    }
+
+    #[test]
+    fn every_nth_or_hz_fast_arrival_is_rate_capped() {
+        testing_logger::setup();
+        for i in 0..1_000 {
+            log_hz_every_nth_or_hz!(2, 10.0, Level::Info, "item {}", i);
+        }
+        testing_logger::validate(|captured_logs| {
+            // Count gate passed hundreds of times, but the rate cap allows only the first
+            assert_eq!(captured_logs.len(), 1);
+        });
+    }
+
+    #[test]
+    fn every_nth_or_hz_slow_arrival_is_count_gated() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            for i in 0..20 {
+                log_hz_every_nth_or_hz!(5, 1_000.0, Level::Info, "item {}", i);
+                clock.advance(std::time::Duration::from_millis(2));
+            }
+        });
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["item 0", "item 5", "item 10", "item 15"]);
+        });
+    }
+    #[test]
+    fn probe_explains_a_suppressed_call() {
+        testing_logger::setup();
+        let probe = || log_hz_probe!(1.0, Level::Info, "probed");
+        let first = probe();
+        assert!(first.emitted);
+        let second = probe();
+        assert!(!second.emitted);
+        assert!(second.elapsed_ns < second.interval_ns, "{second:?}");
+        assert_eq!(second.interval_ns, 1_000_000_000);
+        assert!(second.last_log_ns > 0);
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 1));
+    }
+
+    #[test]
+    fn escalates_only_during_a_flood() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        let log = || log_hz_escalate!(10.0, Level::Info, Level::Warn, 5, "escalating");
+        with_clock(clock.clone(), || {
+            // A trickle at the site's rate
+            for _ in 0..3 {
+                log();
+                clock.advance(std::time::Duration::from_millis(100));
+            }
+            // A flood of 10 calls per interval
+            for _ in 0..30 {
+                log();
+                clock.advance(std::time::Duration::from_millis(10));
+            }
+        });
+        testing_logger::validate(|captured_logs| {
+            let levels: Vec<_> = captured_logs.iter().map(|log| log.level).collect();
+            assert_eq!(levels, [Level::Info, Level::Info, Level::Info, Level::Info, Level::Warn, Level::Warn]);
+        });
+    }
+
+    #[test]
+    fn forced_message_restarts_the_interval() {
+        testing_logger::setup();
+        let (clock, state) = (ManualClock::default(), ThrottleState::new());
+        let tick = || log_hz_on!(state, 1.0, Level::Info, "tick");
+        with_clock(clock.clone(), || {
+            tick();
+            clock.advance(std::time::Duration::from_millis(500));
+            force_log_hz!(state, Level::Info, "forced");
+            // A second after the first tick, but only 700ms after the forced message
+            clock.advance(std::time::Duration::from_millis(700));
+            tick();
+            clock.advance(std::time::Duration::from_millis(300));
+            tick();
+        });
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["tick", "forced", "tick"]);
+        });
+        assert_eq!(state.total_emitted(), 3);
+    }
+
+    #[test]
+    fn shrinking_interval_logs_more_often() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            // Each interval gets 4 seconds of calls every 100ms
+            for interval_ms in [1000, 500, 200, 0] {
+                for _ in 0..40 {
+                    log_every_dyn!(std::time::Duration::from_millis(interval_ms), Level::Info, "{interval_ms}");
+                    clock.advance(std::time::Duration::from_millis(100));
+                }
+            }
+        });
+        testing_logger::validate(|captured_logs| {
+            let per_interval: Vec<_> = ["1000", "500", "200", "0"]
+                .iter()
+                .map(|interval| captured_logs.iter().filter(|log| log.body == *interval).count())
+                .collect();
+            assert_eq!(per_interval, [4, 8, 20, 40]);
+        });
+    }
+
+    #[test]
+    fn rate_fn_sets_each_levels_cadence() {
+        const fn rate_for(level: Level) -> f32 {
+            match level {
+                Level::Error => 5.0,
+                _ => 0.5,
+            }
+        }
+
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        with_clock(clock.clone(), || {
+            // 4 seconds of calls every 100ms
+            for _ in 0..40 {
+                log_hz_with_rate_fn!(rate_for, Level::Error, "error");
+                log_hz_with_rate_fn!(rate_for, Level::Info, "info");
+                clock.advance(std::time::Duration::from_millis(100));
+            }
+        });
+        testing_logger::validate(|captured_logs| {
+            let count = |level| captured_logs.iter().filter(|log| log.level == level).count();
+            assert_eq!((count(Level::Error), count(Level::Info)), (20, 2));
+        });
+    }
+
+    #[test]
+    fn rate_fn_level_is_evaluated_once_per_call() {
+        let evaluated = std::cell::Cell::new(0);
+        let level = || {
+            evaluated.set(evaluated.get() + 1);
+            Level::Trace
+        };
+        for _ in 0..3 {
+            log_hz_with_rate_fn!(|_| 1.0, level(), "trace");
+        }
+        assert_eq!(evaluated.get(), 3);
+    }
+
+    #[test]
+    fn millisecond_period_matches_the_equivalent_rate() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        let (mut by_period, mut by_rate) = (Vec::new(), Vec::new());
+        with_clock(clock.clone(), || {
+            // Steps of 999_999_999ns, just short of the interval, land on either side of it as they accumulate
+            for _ in 0..1_000 {
+                let period = log_hz!(handle: Level::Info, std::time::Duration::from_millis(1000), "period");
+                by_period.push(period.state().total_emitted());
+                by_rate.push(info_hz!(handle: 1.0, "rate").state().total_emitted());
+                clock.advance(std::time::Duration::from_nanos(999_999_999));
+            }
+        });
+        assert_eq!(by_period, by_rate);
+        assert_eq!(by_period.last(), Some(&500));
+    }
+
+    // The strict rate ceiling clamps a period of 0 like any other rate above it
+    #[cfg(not(feature = "strict-rate-ceiling"))]
+    #[test]
+    fn zero_period_always_logs() {
+        testing_logger::setup();
+        for _ in 0..10 {
+            log_hz_us!(0, Level::Info, "every call");
+        }
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 10));
+    }
+
+    #[test]
+    fn only_errors_are_throttled() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        let results: Vec<Result<u32, String>> =
+            (0..10).map(|i| if i % 2 == 0 { Err(format!("error {i}")) } else { Ok(i) }).collect();
+        let passed_through: Vec<_> = with_clock(clock.clone(), || {
+            results
+                .iter()
+                .map(|result| {
+                    let passed = log_hz_try!(1.0, Level::Warn, result.clone(), "read failed: {}");
+                    clock.advance(std::time::Duration::from_millis(300));
+                    passed
+                })
+                .collect()
+        });
+        assert_eq!(passed_through, results);
+        // Errors at 0ms, 600ms, 1200ms, 1800ms and 2400ms, with the Ok calls in between leaving the throttle alone
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["read failed: error 0", "read failed: error 4", "read failed: error 8"]);
+        });
+    }
+
+    struct Cleanup;
+
+    impl Drop for Cleanup {
+        fn drop(&mut self) {
+            log_hz_panic_guard!(1.0, Level::Warn, "cleanup failed");
+        }
+    }
+
+    struct PanickingDisplay;
+
+    impl std::fmt::Display for PanickingDisplay {
+        fn fmt(&self, _: &mut std::fmt::Formatter) -> std::fmt::Result {
+            panic!("formatting failed");
+        }
+    }
+
+    #[test]
+    fn drop_during_unwinding_is_throttled() {
+        testing_logger::setup();
+        for _ in 0..100 {
+            drop(Cleanup);
+        }
+        // Dropped while unwinding from a panic, logging from the destructor doesn't panic again
+        for _ in 0..10 {
+            let unwound = std::panic::catch_unwind(|| {
+                let _cleanup = Cleanup;
+                panic!("error storm");
+            });
+            assert!(unwound.is_err());
+        }
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 1));
+
+        // A panic while formatting is contained outside of unwinding
+        log_hz_panic_guard!(1.0, Level::Warn, "{}", PanickingDisplay);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "is logged every 1s at src/variants.rs")]
+    fn group_sites_with_different_rates_panic() {
+        log_hz_group!("test-mismatched-rates", Level::Info, 10.0, "fast");
+        log_hz_group!("test-mismatched-rates", Level::Info, 10.0, "also fast");
+        log_hz_group!("test-mismatched-rates", Level::Info, 1.0, "slow");
+    }
+
+    #[test]
+    fn banded_rate_follows_the_value() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        let bands = [(10.0, 10.0), (50.0, 1.0), (f64::INFINITY, 0.2)];
+        with_clock(clock.clone(), || {
+            // 10 seconds in each band, calling every 10ms
+            for battery in [80, 30, 5] {
+                for _ in 0..1_000 {
+                    log_hz_banded!(battery, &bands, Level::Info, "battery {}");
+                    clock.advance(std::time::Duration::from_millis(10));
+                }
+            }
+        });
+        testing_logger::validate(|captured_logs| {
+            let emitted = |body: &str| captured_logs.iter().filter(|log| log.body == body).count();
+            assert_eq!((emitted("battery 80"), emitted("battery 30"), emitted("battery 5")), (2, 10, 100));
+        });
+        assert_eq!(band_rate(f64::NAN, &bands), 0.0);
+        assert_eq!(band_rate(10.0, &bands[..2]), 1.0);
+        assert_eq!(band_rate(50.0, &bands[..2]), 0.0);
+    }
+}
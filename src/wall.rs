@@ -0,0 +1,133 @@
+//! Messages aligned to wall-clock seconds rather than to the time of the last message, enabled by the `wall-clock`
+//! feature.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many seconds after a boundary a call still fires for it, so a clock step over the boundary second (or a call
+/// that just misses it) doesn't skip the message.
+const LATE_SECS: u64 = 2;
+
+/// How far the clock must step back before boundaries that already fired can fire again.
+///
+/// Smaller steps back, like an NTP adjustment, would otherwise fire the same boundary twice.
+const STEP_BACK_SECS: u64 = 60;
+
+/// Seconds since the Unix epoch on the wall clock, or 0 if it is set before the epoch.
+#[inline]
+pub fn wall_secs() -> u64 {
+    #[cfg(any(test, feature = "testing"))]
+    if let Some(now) = crate::testing::wall_clock_now() {
+        return secs_since_epoch(now);
+    }
+    secs_since_epoch(SystemTime::now())
+}
+
+fn secs_since_epoch(now: SystemTime) -> u64 {
+    now.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+}
+
+/// The state behind [log_hz_on_nth_second!], remembering the last boundary it fired for.
+#[doc(hidden)]
+#[derive(Debug, Default)]
+pub struct WallSecondGate {
+    /// The last boundary that fired plus one, 0 if none has.
+    fired: AtomicU64,
+}
+
+impl WallSecondGate {
+    pub const fn new() -> Self {
+        Self { fired: AtomicU64::new(0) }
+    }
+
+    /// Whether a call at `now_secs` fires for a boundary that is a multiple of `n` seconds.
+    ///
+    /// Returns true for at most one call per boundary, within [LATE_SECS] after it.
+    pub fn should_log(&self, now_secs: u64, n: u64) -> bool {
+        let n = n.max(1);
+        let boundary = now_secs - now_secs % n;
+        if now_secs - boundary > LATE_SECS {
+            return false;
+        }
+        let fired = self.fired.load(Ordering::Relaxed);
+        let is_new = match fired.checked_sub(1) {
+            None => true,
+            Some(last) => boundary > last || boundary + STEP_BACK_SECS < last,
+        };
+        is_new && self.fired.compare_exchange(fired, boundary + 1, Ordering::Relaxed, Ordering::Relaxed).is_ok()
+    }
+}
+
+/// Log at most once on each wall-clock second that is a multiple of `n`, e.g. on :00, :15, :30 and :45 for 15.
+///
+/// Meant for cron-like status lines that should line up across processes and with the timestamps in the log. The
+/// boundaries are seconds since the Unix epoch read from [SystemTime](std::time::SystemTime), so with `n` dividing 60
+/// they fall on the same seconds of every minute. The first call in the 2 seconds after a boundary fires for it, so a
+/// call that just misses the boundary second, or a clock that steps over it, doesn't skip the message. A boundary
+/// fires at most once, even if the clock is stepped back over it by less than a minute. An `n` of 0 or 1 fires on
+/// every second.
+///
+/// ```rust
+/// use log_hz::*;
+///
+/// # let connections = 3;
+/// // In a loop that runs many times a second
+/// log_hz_on_nth_second!(15, Level::Info, "{} connections open", connections);
+/// ```
+#[macro_export]
+macro_rules! log_hz_on_nth_second {
+    ($n:expr, $level:expr, $($arg:tt)+) => {
+        {
+            static GATE: $crate::WallSecondGate = $crate::WallSecondGate::new();
+
+            let level: $crate::Level = $level;
+            if $crate::__private::level_enabled(level) && GATE.should_log($crate::__private::wall_secs(), $n as u64) {
+                $crate::__private::emit_once(&GATE, || $crate::__log_hz_emit!(level, $($arg)+));
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn fires_only_on_boundary_seconds() {
+        testing_logger::setup();
+        let clock = ManualClock::default();
+        // Out of reach of the boundary at 0
+        clock.advance(Duration::from_secs(4));
+        with_clock(clock.clone(), || {
+            // Four calls a second, for a minute
+            for _ in 0..240 {
+                log_hz_on_nth_second!(15, Level::Info, "at {}", wall_secs());
+                clock.advance(Duration::from_millis(250));
+            }
+        });
+        testing_logger::validate(|captured_logs| {
+            let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+            assert_eq!(bodies, ["at 15", "at 30", "at 45", "at 60"]);
+        });
+    }
+
+    #[test]
+    fn clock_steps_neither_skip_nor_repeat_a_boundary() {
+        let gate = WallSecondGate::new();
+        assert!(!gate.should_log(14, 15));
+        // Stepping over the boundary second still fires, once
+        assert!(gate.should_log(16, 15));
+        assert!(!gate.should_log(17, 15));
+        // Stepping back onto it doesn't fire again
+        assert!(!gate.should_log(15, 15));
+        // Too late for the boundary
+        assert!(!gate.should_log(33, 15));
+        assert!(gate.should_log(45, 15));
+        assert!(gate.should_log(120, 15));
+        // A large step back is a new start
+        assert!(gate.should_log(30, 15));
+        assert!(WallSecondGate::new().should_log(7, 0));
+    }
+}
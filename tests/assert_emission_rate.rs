@@ -0,0 +1,20 @@
+//! Runs in its own process, as the helper installs a logger if there is none.
+#![cfg(feature = "testing")]
+
+use log_hz::*;
+use std::time::Duration;
+
+#[test]
+fn info_hz_emits_at_its_rate() {
+    assert_emission_rate(2.0, 0.1, Duration::from_millis(1_200), || {
+        info_hz!(2.0, "Hello, world!");
+    });
+}
+
+#[test]
+#[should_panic(expected = "expected messages at 4Hz")]
+fn wrong_rate_fails() {
+    assert_emission_rate(4.0, 0.1, Duration::from_millis(1_200), || {
+        info_hz!(2.0, "Hello, world!");
+    });
+}
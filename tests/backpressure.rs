@@ -0,0 +1,46 @@
+//! The backpressure signal holds back messages whose interval has elapsed.
+//!
+//! In its own test binary as the signal is process-wide.
+
+use log_hz::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+static OVERLOADED: AtomicBool = AtomicBool::new(false);
+
+fn tick() {
+    info_hz!(100.0, "tick");
+}
+
+#[test]
+fn overloaded_logger_suppresses_elapsed_intervals() {
+    testing_logger::setup();
+    let clock = ManualClock::default();
+    let tick = || with_clock(clock.clone(), tick);
+    set_backpressure_signal(|| OVERLOADED.load(Ordering::Relaxed));
+    OVERLOADED.store(true, Ordering::Relaxed);
+    for _ in 0..5 {
+        tick();
+        // Each call is well past the 10ms interval
+        clock.advance(Duration::from_millis(20));
+    }
+    testing_logger::validate(|captured_logs| assert!(captured_logs.is_empty()));
+    assert_eq!(backpressure_suppressed(), 5);
+
+    OVERLOADED.store(false, Ordering::Relaxed);
+    tick();
+    testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 1));
+
+    OVERLOADED.store(true, Ordering::Relaxed);
+    clear_backpressure_signal();
+    clock.advance(Duration::from_millis(20));
+    tick();
+    testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 1));
+    assert_eq!(backpressure_suppressed(), 5);
+
+    // The panic guard, which may run while unwinding, doesn't ask the signal
+    set_backpressure_signal(|| OVERLOADED.load(Ordering::Relaxed));
+    log_hz_panic_guard!(100.0, Level::Warn, "from a destructor");
+    testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 1));
+    assert_eq!(backpressure_suppressed(), 5);
+}
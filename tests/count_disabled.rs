@@ -0,0 +1,21 @@
+//! Calls at a disabled level are still counted with the `count-disabled` feature.
+//!
+//! In its own test binary as it changes the global maximum level.
+
+use log::LevelFilter;
+use log_hz::*;
+
+#[test]
+fn disabled_calls_count_as_attempts() {
+    log::set_max_level(LevelFilter::Info);
+    let log = || debug_hz!(handle: 1_000.0, "not logged at Info");
+    for _ in 0..99 {
+        log();
+    }
+    let handle = log();
+    let stats = site_stats()
+        .into_iter()
+        .find(|stats| stats.file == handle.file() && stats.line == handle.line())
+        .expect("site is registered");
+    assert_eq!((stats.attempts, stats.emitted), (100, 0));
+}
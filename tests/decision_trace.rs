@@ -0,0 +1,30 @@
+//! The decision trace records each call's emit or suppress decision in order.
+//!
+//! In its own test binary as the trace is shared by every site in the process.
+
+use log_hz::*;
+use std::time::Duration;
+
+#[test]
+fn trace_records_emits_and_suppressions_in_order() {
+    testing_logger::setup();
+    let clock = ManualClock::default();
+    let line = line!() + 4;
+    with_clock(clock.clone(), || {
+        // At 1Hz, called every 400ms
+        for _ in 0..6 {
+            info_hz!(1.0, "traced");
+            clock.advance(Duration::from_millis(400));
+        }
+        // Another site's decisions are interleaved
+        warn_hz!(1.0, "other site");
+    });
+    let decisions = dump_decision_trace();
+    assert_eq!(decisions.len(), 7);
+    let site: Vec<_> = decisions.iter().filter(|decision| decision.line == line).collect();
+    let emitted: Vec<_> = site.iter().map(|decision| decision.emitted).collect();
+    assert_eq!(emitted, [true, false, false, true, false, false]);
+    assert!(site.windows(2).all(|pair| pair[1].now_ns - pair[0].now_ns == 400_000_000));
+    let other = Decision { file: file!(), line: line + 4, now_ns: site[5].now_ns + 400_000_000, emitted: true };
+    assert_eq!(decisions[6], other);
+}
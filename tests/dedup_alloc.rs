@@ -0,0 +1,42 @@
+//! A repeat suppressed by `log_hz_dedup_window!` doesn't allocate.
+//!
+//! In its own test binary as it installs a counting global allocator.
+
+use log_hz::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::time::Duration;
+
+thread_local! {
+    /// Allocations made on this thread, so the test harness's other threads aren't counted.
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|allocations| allocations.set(allocations.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn suppressed_repeats_do_not_allocate() {
+    testing_logger::setup();
+    let log = |id: u32| log_hz_dedup_window!(Duration::from_secs(60), Level::Warn, "sensor {} out of range", id);
+    log(1);
+    let before = ALLOCATIONS.with(Cell::get);
+    for _ in 0..100 {
+        log(1);
+    }
+    assert_eq!(ALLOCATIONS.with(Cell::get) - before, 0);
+    testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 1));
+}
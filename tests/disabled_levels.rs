@@ -0,0 +1,47 @@
+//! Calls at a disabled level leave the variants' state untouched.
+//!
+//! In its own test binary as it changes the global maximum level.
+
+use log::LevelFilter;
+use log_hz::*;
+
+#[test]
+fn disabled_calls_leave_variant_state_alone() {
+    testing_logger::setup();
+    log::set_max_level(LevelFilter::Info);
+    let edge = |level| log_hz_edge!(0.001, level, "edge");
+    let nth = |level| log_hz_every_nth_or_hz!(3, 0.001, level, "nth");
+    let ratio = |level| log_hz_ratio!(2, level, "ratio");
+    let accumulate = |level, value| log_hz_accumulate!(0.001, value, level, "{count} values, max {max}");
+    for _ in 0..10 {
+        edge(Level::Debug);
+        nth(Level::Debug);
+        ratio(Level::Debug);
+        accumulate(Level::Debug, 100);
+    }
+    // The edge trigger, the call count and the ratio's count are as if the disabled calls never happened
+    for _ in 0..3 {
+        edge(Level::Info);
+        nth(Level::Info);
+        ratio(Level::Info);
+    }
+    accumulate(Level::Info, 1);
+    testing_logger::validate(|captured_logs| {
+        let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+        assert_eq!(bodies, ["edge", "nth", "ratio", "ratio", "1 values, max 1"]);
+    });
+}
+
+#[test]
+fn disabled_base_level_leaves_the_slot_to_escalations() {
+    testing_logger::setup();
+    log::set_max_level(LevelFilter::Info);
+    // The disabled Debug calls neither claim the first slot nor reset the count, so the 5th call escalates
+    for _ in 0..10 {
+        log_hz_escalate!(0.001, Level::Debug, Level::Warn, 3, "storm");
+    }
+    testing_logger::validate(|captured_logs| {
+        let logs: Vec<_> = captured_logs.iter().map(|log| (log.level, log.body.as_str())).collect();
+        assert_eq!(logs, [(Level::Warn, "storm")]);
+    });
+}
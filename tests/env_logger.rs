@@ -0,0 +1,50 @@
+//! Throttling against a real `env_logger`, whose target filters and formatting are applied to what log_hz emits.
+
+use log_hz::*;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Collects what `env_logger` writes, so the formatted lines can be checked.
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Captured {
+    fn take_lines(&self) -> Vec<String> {
+        let bytes = std::mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner));
+        String::from_utf8(bytes).unwrap().lines().map(str::to_owned).collect()
+    }
+}
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn target_filters_and_throttles_compose() {
+    let captured = Captured::default();
+    env_logger::Builder::new()
+        // What `RUST_LOG=warn,app::sub=info` would configure
+        .parse_filters("warn,app::sub=info")
+        .format(|buf, record| writeln!(buf, "{} {}: {}", record.level(), record.target(), record.args()))
+        .target(env_logger::Target::Pipe(Box::new(captured.clone())))
+        .init();
+
+    for i in 0..10 {
+        info_hz!(target: "app::sub", 1.0, "sub tick {i}");
+        info_hz!(target: "app::other", 1.0, "other tick {i}");
+        log_hz!(target: "app::other", Level::Warn, 1.0, "other warning {i}");
+    }
+    assert_eq!(captured.take_lines(), ["INFO app::sub: sub tick 0", "WARN app::other: other warning 0"]);
+
+    // Calls filtered out by their target don't use up the slot, so the first call with an enabled one still emits
+    for target in ["app::other", "app::other", "app::sub", "app::sub"] {
+        info_hz!(target: target, 1.0, "switched to {target}");
+    }
+    assert_eq!(captured.take_lines(), ["INFO app::sub: switched to app::sub"]);
+}
@@ -0,0 +1,59 @@
+//! Sites hammered from many threads on their very first calls emit once per interval.
+//!
+//! In its own test binary so that the clock's start time and the first site's interval are initialized by the racing
+//! threads themselves.
+
+use log::{LevelFilter, Log, Metadata, Record, set_logger, set_max_level};
+use log_hz::*;
+use std::sync::Barrier;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const THREADS: usize = 16;
+
+/// Messages emitted by each site, indexed by the number the site logs.
+static EMITTED: [AtomicUsize; 8] = [const { AtomicUsize::new(0) }; 8];
+
+/// Counts messages from every thread, unlike `testing_logger` which captures per thread.
+struct CountingLogger;
+
+impl Log for CountingLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        let site: usize = record.args().to_string().parse().unwrap();
+        EMITTED[site].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn flush(&self) {}
+}
+
+/// A separate call site for each number, each logging its own number.
+macro_rules! sites {
+    ($($site:literal)*) => {
+        [$((|| { info_hz!(0.001, "{}", $site); }) as fn(),)*]
+    };
+}
+
+#[test]
+#[cfg_attr(feature = "single-threaded", ignore = "racing calls may each emit with the `single-threaded` feature")]
+fn racing_first_calls_emit_once() {
+    set_logger(&CountingLogger).unwrap();
+    set_max_level(LevelFilter::Info);
+    for (number, site) in sites!(0 1 2 3 4 5 6 7).iter().enumerate() {
+        let barrier = Barrier::new(THREADS);
+        std::thread::scope(|scope| {
+            for _ in 0..THREADS {
+                scope.spawn(|| {
+                    barrier.wait();
+                    for _ in 0..1_000 {
+                        site();
+                    }
+                });
+            }
+        });
+        // The sites' first interval is over 16 minutes, so no second message is due yet
+        assert_eq!(EMITTED[number].load(Ordering::Relaxed), 1, "site {number}");
+    }
+}
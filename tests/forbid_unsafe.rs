@@ -0,0 +1,19 @@
+//! The macros must expand to safe code, so they can be used from crates that forbid unsafe.
+//!
+//! With `link-section-metadata` each site's link-section static is flagged by the lint, so only `deny` can be checked.
+#![cfg_attr(not(feature = "link-section-metadata"), forbid(unsafe_code))]
+#![cfg_attr(feature = "link-section-metadata", deny(unsafe_code))]
+
+use log_hz::*;
+
+#[test]
+fn macros_compile_in_a_crate_forbidding_unsafe() {
+    for i in 0..3 {
+        info_hz!(1.0, "Hello, world! {}", i);
+        log_hz!(Level::Warn, 2, first: false, "Hello, world!");
+        log_hz_dyn!(Level::Debug, 1.0 + i as f64, "Hello, world!");
+        log_hz_smart!(1, 1.0, Level::Error, "Hello, world!");
+        log_hz_dedup_window!(std::time::Duration::from_secs(1), Level::Info, "Hello, world!");
+    }
+    info_hz!(handle: 1.0, "Hello, world!").reset();
+}
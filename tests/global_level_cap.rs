@@ -0,0 +1,65 @@
+//! The global per-level cap limits messages across every call site.
+//!
+//! In its own test binary as the cap is process-wide.
+
+use log_hz::*;
+
+/// Ten distinct warning sites that would each log on every call.
+fn ten_warning_sites() {
+    warn_hz!(1_000.0, "site 0");
+    warn_hz!(1_000.0, "site 1");
+    warn_hz!(1_000.0, "site 2");
+    warn_hz!(1_000.0, "site 3");
+    warn_hz!(1_000.0, "site 4");
+    warn_hz!(1_000.0, "site 5");
+    warn_hz!(1_000.0, "site 6");
+    warn_hz!(1_000.0, "site 7");
+    warn_hz!(1_000.0, "site 8");
+    warn_hz!(1_000.0, "site 9");
+}
+
+#[test]
+fn global_cap_dominates_many_sites() {
+    testing_logger::setup();
+    let clock = ManualClock::default();
+    set_global_level_cap(Level::Warn, 1.0);
+    with_clock(clock.clone(), || {
+        for _ in 0..5 {
+            ten_warning_sites();
+            // Other levels aren't capped
+            error_hz!(100.0, "uncapped");
+            clock.advance(std::time::Duration::from_millis(20));
+        }
+    });
+    testing_logger::validate(|captured_logs| {
+        let warnings = captured_logs.iter().filter(|log| log.level == Level::Warn).count();
+        let errors = captured_logs.iter().filter(|log| log.level == Level::Error).count();
+        assert_eq!((warnings, errors), (1, 5));
+    });
+    assert_eq!(global_level_suppressed(Level::Warn), 49);
+    assert_eq!(global_level_suppressed(Level::Error), 0);
+
+    clear_global_level_cap(Level::Warn);
+    clock.advance(std::time::Duration::from_millis(20));
+    with_clock(clock, ten_warning_sites);
+    testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 10));
+}
+
+#[test]
+fn global_cap_applies_to_the_variants() {
+    testing_logger::setup();
+    set_global_level_cap(Level::Info, 1.0);
+    log_hz_smart!(3, 1_000.0, Level::Info, "smart");
+    log_hz_edge!(1_000.0, Level::Info, "edge");
+    log_hz_const!(Level::Info, 1_000.0, "const");
+    log_hz_caller!(Level::Info, 1_000.0, "caller");
+    log_hz_accumulate!(1_000.0, 1, Level::Info, "{count} accumulated");
+    log_every_dyn!(std::time::Duration::ZERO, Level::Info, "dyn");
+    log_hz_jittered!(1_000.0, 0.0, Level::Info, "jittered");
+    testing_logger::validate(|captured_logs| {
+        let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+        assert_eq!(bodies, ["smart"]);
+    });
+    assert_eq!(global_level_suppressed(Level::Info), 6);
+    clear_global_level_cap(Level::Info);
+}
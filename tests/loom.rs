@@ -0,0 +1,53 @@
+//! Exhaustive interleavings of the throttle's claim protocol with relaxed atomics, checked with `loom`.
+//!
+//! `ThrottleState` uses `const` atomics that loom can't stand in for, so this models its fast path load and
+//! compare-and-swap with the same orderings. Run with:
+//!
+//! The cfg is named for this crate, as dependencies like tokio change their code under a plain `loom` cfg.
+//!
+//! ```sh
+//! RUSTFLAGS="--cfg log_hz_loom" cargo test --release --test loom
+//! ```
+#![cfg(log_hz_loom)]
+
+use loom::sync::Arc;
+use loom::sync::atomic::{AtomicU64, Ordering};
+use loom::thread;
+
+/// The claim in `ThrottleState::try_log`, where 0 means never logged.
+fn try_claim(last_log_ns: &AtomicU64, now_ns: u64, interval_ns: u64) -> bool {
+    let last_ns = last_log_ns.load(Ordering::Relaxed);
+    if last_ns != 0 && now_ns.saturating_sub(last_ns) < interval_ns {
+        return false;
+    }
+    last_log_ns.compare_exchange(last_ns, now_ns.max(1), Ordering::Relaxed, Ordering::Relaxed).is_ok()
+}
+
+/// Run one thread per timestamp, each trying to claim `last_log_ns`, and return the timestamps that claimed it.
+fn claims(now_ns: &[u64], interval_ns: u64) -> Vec<u64> {
+    let last_log_ns = Arc::new(AtomicU64::new(0));
+    let threads: Vec<_> = now_ns
+        .iter()
+        .map(|&now_ns| {
+            let last_log_ns = last_log_ns.clone();
+            thread::spawn(move || try_claim(&last_log_ns, now_ns, interval_ns).then_some(now_ns))
+        })
+        .collect();
+    let mut claimed: Vec<_> = threads.into_iter().filter_map(|thread| thread.join().unwrap()).collect();
+    claimed.sort();
+    claimed
+}
+
+#[test]
+fn one_claim_per_interval() {
+    loom::model(|| assert_eq!(claims(&[10, 20, 30], 100).len(), 1));
+}
+
+#[test]
+fn claims_are_an_interval_apart() {
+    loom::model(|| {
+        let claimed = claims(&[10, 60, 150], 100);
+        assert!(!claimed.is_empty());
+        assert!(claimed.windows(2).all(|pair| pair[1] - pair[0] >= 100), "{claimed:?}");
+    });
+}
@@ -0,0 +1,44 @@
+//! `set_max_hz` slows down every site that is faster than it.
+//!
+//! In its own test binary as the limit is process-wide.
+
+use log_hz::*;
+use std::time::Duration;
+
+#[test]
+fn max_hz_clamps_faster_sites() {
+    let clock = ManualClock::default();
+    set_max_hz(1.0);
+    assert_eq!(max_hz(), 1.0);
+    let emitted = |log: &dyn Fn()| {
+        with_clock(clock.clone(), || {
+            count_emissions(|| {
+                // Every 10ms for 3 seconds
+                for _ in 0..300 {
+                    log();
+                    clock.advance(Duration::from_millis(10));
+                }
+            })
+        })
+    };
+    assert_eq!(emitted(&|| info_hz!(100.0, "clamped to 1Hz")), 3);
+    assert_eq!(emitted(&|| log_hz_dyn!(Level::Info, 100, "clamped to 1Hz")), 3);
+    // The variants are limited the same way
+    assert_eq!(emitted(&|| log_hz_smart!(2, 100.0, Level::Info, "clamped after the burst")), 3);
+    assert_eq!(emitted(&|| log_hz_every_nth_or_hz!(1, 100.0, Level::Info, "clamped to 1Hz")), 3);
+    assert_eq!(emitted(&|| log_hz_const!(Level::Info, 100.0, "clamped to 1Hz")), 3);
+    assert_eq!(emitted(&|| log_hz_jittered!(100.0, 0.0, Level::Info, "clamped to 1Hz")), 3);
+    assert_eq!(emitted(&|| log_hz_caller!(Level::Info, 100.0, "clamped to 1Hz")), 3);
+    assert_eq!(emitted(&|| log_hz_merge!("max-hz", 100.0, Level::Info, "clamped to 1Hz")), 3);
+    assert_eq!(emitted(&|| log_hz_ratio!(1, min_gap: Duration::ZERO, Level::Info, "clamped to 1Hz")), 3);
+    assert_eq!(emitted(&|| log_hz_batched!(100.0, Level::Info, 1)), 3);
+    assert_eq!(emitted(&|| log_hz_accumulate!(100.0, 1, Level::Info, "{count} clamped to 1Hz")), 3);
+    assert_eq!(emitted(&|| log_every_dyn!(Duration::ZERO, Level::Info, "clamped to 1Hz")), 3);
+    assert_eq!(emitted(&|| log_hz_edge!(100.0, Level::Info, "edge clamped to 1Hz")), 3);
+    // Slower sites keep their own rate
+    assert_eq!(emitted(&|| info_hz!(0.5, "at 0.5Hz")), 2);
+
+    set_max_hz(f64::INFINITY);
+    assert_eq!(max_hz(), f64::INFINITY);
+    assert_eq!(emitted(&|| info_hz!(100.0, "at 100Hz")), 300);
+}
@@ -0,0 +1,8 @@
+//! With the `no-log-reexport` feature, `use log_hz::*` brings in the throttled macros and `Level` but not `log`.
+
+#[test]
+fn log_items_are_not_reexported() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/hz_macros_without_log.rs");
+    cases.compile_fail("tests/ui/log_glob_not_reexported.rs");
+}
@@ -0,0 +1,25 @@
+//! Runs in its own process, as the panic hook and the flush affect every call site.
+#![cfg(feature = "registry")]
+
+use log_hz::*;
+
+#[test]
+fn panic_hook_flushes_suppressed_counts() {
+    testing_logger::setup();
+    install_panic_flush_hook();
+    let result = std::panic::catch_unwind(|| {
+        for _ in 0..5 {
+            warn_hz!(1.0, "before the crash");
+        }
+        panic!("crash");
+    });
+    assert!(result.is_err());
+    testing_logger::validate(|captured_logs| {
+        let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+        assert_eq!(
+            bodies,
+            ["before the crash", "log_hz: suppressed 4 messages since last logged"]
+        );
+        assert_eq!(captured_logs[1].level, Level::Warn);
+    });
+}
@@ -0,0 +1,51 @@
+//! Sites with the same rate that start together are spread across the interval by phase spreading.
+//!
+//! In its own test binary as phase spreading is process-wide.
+
+use log_hz::*;
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+#[test]
+fn sites_keep_their_rate_at_different_phases() {
+    testing_logger::setup();
+    set_phase_spreading(true);
+    let sites: [&dyn Fn(u64); 8] = [
+        &|ms| log_hz!(Level::Info, 1.0, "0 {ms}"),
+        &|ms| log_hz!(Level::Info, 1.0, "1 {ms}"),
+        &|ms| log_hz!(Level::Info, 1.0, "2 {ms}"),
+        &|ms| log_hz!(Level::Info, 1.0, "3 {ms}"),
+        &|ms| log_hz!(Level::Info, 1.0, "4 {ms}"),
+        &|ms| log_hz!(Level::Info, 1.0, "5 {ms}"),
+        &|ms| log_hz!(Level::Info, 1.0, "6 {ms}"),
+        &|ms| log_hz!(Level::Info, 1.0, "7 {ms}"),
+    ];
+    let clock = ManualClock::default();
+    with_clock(clock.clone(), || {
+        // 6 seconds of calls to every site each millisecond
+        for ms in 0..6_000 {
+            for site in &sites {
+                site(ms);
+            }
+            clock.advance(Duration::from_millis(1));
+        }
+    });
+
+    testing_logger::validate(|captured_logs| {
+        let mut phases = BTreeSet::new();
+        for site in 0..sites.len() {
+            let emitted_at: Vec<u64> = captured_logs
+                .iter()
+                .filter_map(|log| log.body.strip_prefix(&format!("{site} ")))
+                .map(|ms| ms.parse().unwrap())
+                .collect();
+            // The first message is immediate, the second is delayed by the phase, and the rest keep the rate
+            assert_eq!(emitted_at[0], 0);
+            assert!(emitted_at[1] >= 1_000 && emitted_at[1] < 2_000, "{emitted_at:?}");
+            assert!(emitted_at.windows(2).skip(1).all(|pair| pair[1] - pair[0] == 1_000), "{emitted_at:?}");
+            assert!(emitted_at.len() >= 5, "{emitted_at:?}");
+            phases.insert(emitted_at[1] % 1_000);
+        }
+        assert!(phases.len() > sites.len() / 2, "{phases:?}");
+    });
+}
@@ -0,0 +1,33 @@
+//! Runs in its own process, as it sets environment variables.
+
+use log_hz::*;
+use std::time::Duration;
+
+fn count_logs(prefix: &str, captured_logs: &[testing_logger::CapturedLog]) -> usize {
+    captured_logs.iter().filter(|log| log.body.starts_with(prefix)).count()
+}
+
+#[test]
+fn rate_is_read_from_the_environment_with_a_default() {
+    testing_logger::setup();
+    // SAFETY: this is the only test in this binary, so no other thread reads the environment
+    unsafe {
+        std::env::set_var("LOG_HZ_TEST_SITE_HZ", "10");
+        std::env::set_var("LOG_HZ_TEST_INVALID_HZ", "fast");
+    }
+    let clock = ManualClock::default();
+    with_clock(clock.clone(), || {
+        for _ in 0..50 {
+            log_hz_rate_from_env!("LOG_HZ_TEST_SITE_HZ", 1.0, Level::Info, "from env");
+            log_hz_rate_from_env!("LOG_HZ_TEST_UNSET_HZ", 1.0, Level::Info, "unset");
+            log_hz_rate_from_env!("LOG_HZ_TEST_INVALID_HZ", 1.0, Level::Info, "invalid");
+            clock.advance(Duration::from_millis(5));
+        }
+    });
+    testing_logger::validate(|captured_logs| {
+        // 10 Hz for 250ms logs at 0, 100 and 200ms
+        assert_eq!(count_logs("from env", captured_logs), 3);
+        assert_eq!(count_logs("unset", captured_logs), 1);
+        assert_eq!(count_logs("invalid", captured_logs), 1);
+    });
+}
@@ -0,0 +1,61 @@
+//! Emitted records carry their call site as `hz_file` and `hz_line` fields, run in its own process so it can install
+//! a kv-capturing logger.
+
+use log::kv::{self, Key, Value, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use log_hz::*;
+use std::sync::{Mutex, PoisonError};
+
+type Fields = Vec<(String, String)>;
+
+static RECORDS: Mutex<Vec<Fields>> = Mutex::new(Vec::new());
+
+struct KvLogger;
+
+impl Log for KvLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        struct Collect(Fields);
+        impl<'kvs> VisitSource<'kvs> for Collect {
+            fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+                self.0.push((key.to_string(), value.to_string()));
+                Ok(())
+            }
+        }
+        let mut fields = Collect(Vec::new());
+        record.key_values().visit(&mut fields).unwrap();
+        RECORDS.lock().unwrap_or_else(PoisonError::into_inner).push(fields.0);
+    }
+
+    fn flush(&self) {}
+}
+
+fn fields(pairs: &[(&str, &str)]) -> Fields {
+    pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+}
+
+#[test]
+fn emitted_records_carry_their_site() {
+    log::set_logger(&KvLogger).unwrap();
+    log::set_max_level(LevelFilter::Trace);
+
+    let mut lines = Vec::new();
+    for _ in 0..3 {
+        info_hz!(1.0, "plain message");
+        lines.push(line!() - 1);
+        warn_hz!(1.0, attempt = 3);
+        lines.push(line!() - 1);
+    }
+    let (plain_line, kv_line) = (lines[0].to_string(), lines[1].to_string());
+    let records = RECORDS.lock().unwrap_or_else(PoisonError::into_inner);
+    assert_eq!(
+        *records,
+        [
+            fields(&[("hz_file", file!()), ("hz_line", &plain_line)]),
+            fields(&[("attempt", "3"), ("hz_file", file!()), ("hz_line", &kv_line)]),
+        ]
+    );
+}
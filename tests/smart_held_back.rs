@@ -0,0 +1,31 @@
+//! A `log_hz_smart!` message held back by the backpressure signal leaves its suppressed count to the next one.
+//!
+//! In its own test binary as the signal is process-wide.
+
+use log_hz::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+static OVERLOADED: AtomicBool = AtomicBool::new(false);
+
+#[test]
+fn held_back_message_keeps_the_suppressed_count() {
+    testing_logger::setup();
+    let clock = ManualClock::default();
+    let log = |i: u32| with_clock(clock.clone(), || log_hz_smart!(1, 10.0, Level::Error, "failure {}", i));
+    set_backpressure_signal(|| OVERLOADED.load(Ordering::Relaxed));
+    for i in 0..4 {
+        log(i);
+    }
+    clock.advance(Duration::from_millis(100));
+    OVERLOADED.store(true, Ordering::Relaxed);
+    log(4);
+    OVERLOADED.store(false, Ordering::Relaxed);
+    clock.advance(Duration::from_millis(100));
+    log(5);
+    testing_logger::validate(|captured_logs| {
+        let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+        assert_eq!(bodies, ["failure 0", "failure 5 (suppressed 3 since last)"]);
+    });
+    assert_eq!(backpressure_suppressed(), 1);
+}
@@ -0,0 +1,63 @@
+//! `log_hz_stderr_fallback!` writes to stderr until a logger is installed, then logs normally.
+//!
+//! The scenario runs in a child process, as it installs the global logger and its stderr has to be captured.
+
+use log::{LevelFilter, Metadata, Record};
+use log_hz::*;
+use std::process::Command;
+use std::time::Duration;
+
+/// Set in the child process that runs the scenario.
+const CHILD_VAR: &str = "LOG_HZ_STDERR_FALLBACK_CHILD";
+
+/// Prints each message to stdout, so the parent can tell it apart from the stderr fallback.
+struct StdoutLogger;
+
+impl log::Log for StdoutLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        println!("logger: {}", record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+fn run_scenario() {
+    let clock = ManualClock::default();
+    with_clock(clock.clone(), || {
+        let log = |phase: &str| log_hz_stderr_fallback!(Level::Warn, 1.0, "waiting ({})", phase);
+        for _ in 0..10 {
+            log("before");
+            clock.advance(Duration::from_millis(250));
+        }
+        log::set_logger(&StdoutLogger).unwrap();
+        log::set_max_level(LevelFilter::Info);
+        for _ in 0..10 {
+            log("after");
+            clock.advance(Duration::from_millis(250));
+        }
+    });
+}
+
+#[test]
+fn falls_back_to_stderr_until_a_logger_is_installed() {
+    if std::env::var_os(CHILD_VAR).is_some() {
+        run_scenario();
+        return;
+    }
+    let output = Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "falls_back_to_stderr_until_a_logger_is_installed", "--nocapture", "--quiet"])
+        .env(CHILD_VAR, "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let lines = |bytes: &[u8], prefix: &str| -> Vec<String> {
+        String::from_utf8_lossy(bytes).lines().filter(|line| line.starts_with(prefix)).map(String::from).collect()
+    };
+    // 2.5 seconds at 1Hz in each phase, with the throttle carried across the switch
+    assert_eq!(lines(&output.stderr, "[WARN"), ["[WARN stderr_fallback] waiting (before)"; 3]);
+    assert_eq!(lines(&output.stdout, "logger:"), ["logger: waiting (after)"; 2]);
+}
@@ -0,0 +1,8 @@
+//! Literal rates above the `strict-rate-ceiling` feature's ceiling fail to compile.
+
+#[test]
+fn over_ceiling_rates_fail_to_compile() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/rate_under_ceiling.rs");
+    cases.compile_fail("tests/ui/rate_over_ceiling.rs");
+}
@@ -0,0 +1,23 @@
+//! Runs in its own process, as the suppressed count format is global.
+
+use log_hz::*;
+
+#[test]
+fn custom_suppressed_format_is_used() {
+    testing_logger::setup();
+    set_suppressed_format(|suppressed| format!("[{suppressed} dropped]"));
+    let clock = ManualClock::default();
+    let log = |i: u32| with_clock(clock.clone(), || log_hz_smart!(1, 10.0, Level::Error, "failure {}", i));
+    for i in 0..5 {
+        log(i);
+    }
+    clock.advance(std::time::Duration::from_millis(100));
+    log(5);
+    set_suppressed_format(|_| String::new());
+    clock.advance(std::time::Duration::from_millis(100));
+    log(6);
+    testing_logger::validate(|captured_logs| {
+        let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+        assert_eq!(bodies, ["failure 0", "failure 5 [4 dropped]", "failure 6"]);
+    });
+}
@@ -0,0 +1,65 @@
+//! Stopping the reporter thread logs a final report without waiting out its period.
+//!
+//! In its own test binary as the reporter logs from its own thread, which `testing_logger` doesn't capture.
+
+use log::{LevelFilter, Log, Metadata, Record, set_logger, set_max_level};
+use log_hz::*;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+static REPORTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Collects the reports logged from any thread.
+struct ReportLogger;
+
+impl Log for ReportLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        if record.target() == "log_hz" {
+            REPORTS.lock().unwrap_or_else(PoisonError::into_inner).push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn reports() -> usize {
+    REPORTS.lock().unwrap_or_else(PoisonError::into_inner).len()
+}
+
+fn suppress_some() {
+    for _ in 0..10 {
+        info_hz!(1.0, "tick");
+    }
+}
+
+#[test]
+fn stopped_reporters_log_a_final_report() {
+    set_logger(&ReportLogger).unwrap();
+    set_max_level(LevelFilter::Info);
+
+    // Dropping the guard wakes the thread long before its period is up
+    let reporter = start_suppression_reporter(Duration::from_secs(3600));
+    suppress_some();
+    let start = Instant::now();
+    drop(reporter);
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert_eq!(reports(), 1);
+    let report = REPORTS.lock().unwrap()[0].clone();
+    assert!(report.contains("tests/suppression_reporter.rs"), "{report}");
+
+    // The thread is gone, so nothing more is reported
+    suppress_some();
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(reports(), 1);
+
+    // A reporter without a guard is stopped with `stop_reporter`
+    spawn_suppression_reporter(Duration::from_secs(3600));
+    stop_reporter();
+    assert_eq!(reports(), 2);
+    stop_reporter();
+    assert_eq!(reports(), 2);
+}
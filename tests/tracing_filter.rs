@@ -0,0 +1,33 @@
+//! With the `tracing` feature, a call at a level the `tracing` subscriber filters out doesn't use up its site's slot.
+//!
+//! In its own test binary as `tracing`'s maximum level is process-wide.
+
+use log_hz::*;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+
+fn poll() {
+    info_hz!(1.0, "polling");
+}
+
+#[test]
+fn filtered_out_calls_keep_the_slot() {
+    testing_logger::setup();
+    let clock = ManualClock::default();
+    with_clock(clock.clone(), || {
+        let filtered = tracing_subscriber::registry().with(LevelFilter::WARN);
+        tracing::subscriber::with_default(filtered, || {
+            for _ in 0..10 {
+                poll();
+            }
+        });
+        testing_logger::validate(|captured_logs| assert!(captured_logs.is_empty()));
+
+        // The clock hasn't moved, yet the first call without the filter logs right away
+        tracing::subscriber::with_default(tracing_subscriber::registry(), || {
+            poll();
+            poll();
+        });
+        testing_logger::validate(|captured_logs| assert_eq!(captured_logs.len(), 1));
+    });
+}
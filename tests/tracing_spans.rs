@@ -0,0 +1,87 @@
+//! With the `tracing` feature, a call site throttles separately within each span.
+//!
+//! In its own test binary as span timers are process-wide.
+
+use log_hz::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tracing::span::{Attributes, Id};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+fn handle_request(request: &str) -> ThrottleHandle {
+    info_hz!(handle: 1.0, "handling {request}")
+}
+
+#[test]
+fn spans_throttle_on_their_own_timers() {
+    testing_logger::setup();
+    let subscriber = tracing_subscriber::registry().with(SpanThrottleLayer);
+    let clock = ManualClock::default();
+    tracing::subscriber::with_default(subscriber, || {
+        with_clock(clock.clone(), || {
+            let (a, b) = (tracing::info_span!("a"), tracing::info_span!("b"));
+            // Both requests are in flight at once, each calling the site every 100ms for 2 seconds
+            let mut handle = None;
+            for _ in 0..20 {
+                a.in_scope(|| handle_request("a"));
+                handle = Some(b.in_scope(|| handle_request("b")));
+                clock.advance(Duration::from_millis(100));
+            }
+            assert_eq!(span_throttle_count(), 2);
+            // The site counts the calls in every span, both were suppressed in the 9 rounds since they last logged
+            assert_eq!(handle.unwrap().suppressed_count(), 18);
+
+            // Closing a span drops its timer, so a new span reusing its id starts afresh
+            drop(a);
+            assert_eq!(span_throttle_count(), 1);
+            tracing::info_span!("c").in_scope(|| handle_request("c"));
+            handle_request("outside");
+        });
+    });
+
+    testing_logger::validate(|captured_logs| {
+        let bodies: Vec<_> = captured_logs.iter().map(|log| log.body.as_str()).collect();
+        assert_eq!(
+            bodies,
+            [
+                "handling a",
+                "handling b",
+                "handling a",
+                "handling b",
+                "handling c",
+                "handling outside"
+            ]
+        );
+    });
+}
+
+/// Counts the spans opened under it.
+struct CountSpans(Arc<AtomicUsize>);
+
+impl<S: tracing::Subscriber> Layer<S> for CountSpans {
+    fn on_new_span(&self, _attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn trace_spans_open_at_the_throttled_rate() {
+    let opened = Arc::new(AtomicUsize::new(0));
+    let subscriber = tracing_subscriber::registry().with(CountSpans(opened.clone()));
+    let clock = ManualClock::default();
+    let entered = tracing::subscriber::with_default(subscriber, || {
+        with_clock(clock.clone(), || {
+            // A batch every 100ms for 3 seconds
+            (0..30)
+                .filter(|batch| {
+                    let span = log_hz_trace_span!(1.0, Level::Info, "batch", batch);
+                    clock.advance(Duration::from_millis(100));
+                    span.is_some()
+                })
+                .count()
+        })
+    });
+    assert_eq!(entered, 3);
+    assert_eq!(opened.load(Ordering::Relaxed), 3);
+}
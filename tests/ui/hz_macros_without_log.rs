@@ -0,0 +1,7 @@
+use log_hz::*;
+
+fn main() {
+    info_hz!(1.0, "Throttled");
+    log_hz!(Level::Warn, 1.0, "Throttled at {:?}", Level::Warn);
+    log::info!("Plain messages come from `log` itself");
+}
@@ -0,0 +1,7 @@
+use log_hz::*;
+
+fn main() {
+    info_hz!(1.0, "In scope");
+    info!("Not in scope");
+    set_max_level(LevelFilter::Info);
+}
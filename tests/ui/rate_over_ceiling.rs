@@ -0,0 +1,5 @@
+use log_hz::*;
+
+fn main() {
+    info_hz!(5000.0, "Over the ceiling");
+}
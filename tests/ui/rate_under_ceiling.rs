@@ -0,0 +1,11 @@
+use log_hz::*;
+
+fn main() {
+    info_hz!(1000, "At the ceiling");
+    log_hz!(Level::Warn, 10.0, "Under the ceiling");
+    log_hz_const!(Level::Info, 500.0, "Under the ceiling");
+    // Rates that aren't literals are clamped instead
+    info_hz!(1e3 * 1e3, "Clamped");
+    let rate = 1e6;
+    log_hz_dyn!(Level::Info, rate, "Clamped");
+}
@@ -0,0 +1,41 @@
+//! With the `wait-for-logger` feature, a site's first message waits for a logger that accepts it.
+//!
+//! In its own test binary as it installs the global logger.
+
+use log::{LevelFilter, Log, Metadata, Record, set_logger, set_max_level};
+use log_hz::*;
+use std::sync::Mutex;
+
+static MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct CollectingLogger;
+
+impl Log for CollectingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Info
+    }
+
+    fn log(&self, record: &Record) {
+        MESSAGES.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+fn start_up(step: u32) -> ThrottleHandle {
+    info_hz!(handle: 1.0, "starting up, step {}", step)
+}
+
+#[test]
+fn first_message_waits_for_the_logger() {
+    // As in an initialization order that raises the level before setting the logger
+    set_max_level(LevelFilter::Info);
+    start_up(1);
+    let site = start_up(2);
+    assert_eq!(site.suppressed_count(), 2);
+
+    set_logger(&CollectingLogger).unwrap();
+    start_up(3);
+    start_up(4);
+    assert_eq!(*MESSAGES.lock().unwrap(), ["starting up, step 3"]);
+}